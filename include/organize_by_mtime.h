@@ -0,0 +1,37 @@
+/* The C API of the organize-by-mtime library, documented in src/ffi.rs. */
+
+#ifndef ORGANIZE_BY_MTIME_H
+#define ORGANIZE_BY_MTIME_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define ORGANIZE_DRY_RUN 1
+
+#define ORGANIZE_FORCE 2
+
+#define ORGANIZE_REAPPLY 4
+
+typedef struct OrganizePlan OrganizePlan;
+
+struct OrganizePlan *organize_plan(int argc, const char *const *argv, char **error);
+
+char *organize_plan_json(const struct OrganizePlan *plan);
+
+struct OrganizePlan *organize_plan_parse(const char *text, char **error);
+
+long organize_execute(const struct OrganizePlan *plan,
+                      unsigned int flags,
+                      const char *manifest,
+                      char **error);
+
+void organize_plan_free(struct OrganizePlan *plan);
+
+void organize_string_free(char *s);
+
+#endif /* ORGANIZE_BY_MTIME_H */
@@ -0,0 +1,19 @@
+# German messages for organize-by-mtime, use with --messages=messages/{lang}.ftl
+# Copy this file to messages/LANG.ftl to translate to another language; the
+# { $name } parts are filled in by the program and must be kept.
+
+moved-files = { $count } Dateien verschoben, { $size } in { $seconds }s ({ $rate }/s)
+total-errors = Fehler insgesamt: { $count }
+interrupted = abgebrochen
+skipped-in-use = { $count } Dateien übersprungen (in Benutzung)
+locked = Fehler: ein anderer Lauf sortiert bereits nach { $output }
+conflict-quarantined = Konflikt { $dest }, in Quarantäne verschoben als { $quarantined }
+conflict-renamed = Konflikt { $dest }, umbenannt in { $renamed }
+conflict-same-destination =
+    Fehler: Konflikt: { $src } hat dasselbe Ziel { $dest }
+    wie eine frühere Datei
+conflict-skipped = übersprungen: { $src }, { $dest } existiert
+ask-overwrite = { $dest } mit { $src } überschreiben? [y] ja, [n] nein, [a] alle, [o] keine:
+merge-suffix = { $folder } enthält bereits Dateien, stattdessen { $target }
+ask-merge = { $folder } enthält bereits Dateien; zusammenführen? [y] ja, [n] neuer Ordner, [a] alle, [o] keine:
+skipped-files = { $count } Dateien nicht verschoben: { $reasons }
@@ -0,0 +1,244 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// The library API: planning a run the way `organize-by-mtime plan` does and
+// carrying out the plan the way `apply` does, for programs that embed the
+// organizer instead of starting it. The bindings for other languages are
+// built on it.
+//
+// Arguments and errors are those of the command line; what went wrong is
+// logged as it is by the program, and a run that would have exited returns
+// an Error instead.
+
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use docopt::Docopt;
+
+use apply;
+use hash;
+use i18n;
+use log;
+use {interrupted, lock_outputs, run, setup, Args, Exit, CANCELLED, EXIT_INTERRUPTED, EXIT_USAGE,
+     LIBRARY, USAGE};
+
+pub use apply::Outcome;
+pub use plan::{Move, Plan};
+
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    // the arguments are invalid; docopt's message, or the text --help and
+    // --version print
+    Usage(String),
+    // the run stopped with this exit status, as the program would have
+    Exit(i32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Usage(ref message) => write!(f, "{}", message),
+            Error::Exit(status) => write!(f, "stopped with exit status {}", status),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+// numbers the plan files of calls made at the same time
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+// Stops the calls given it after the file each is moving, as SIGINT stops
+// the program; they return Error::Exit(130), and so does every later call
+// given it. Calls given another Cancel go on.
+#[derive(Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn new() -> Cancel {
+        Cancel::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+// Runs `f` with exit() unwinding back here instead of ending the process, and
+// with logging settings and messages of its own, so that calls made at the
+// same time don't change each other's.
+pub fn call<T, F: FnOnce() -> T>(cancel: &Cancel, f: F) -> Result<T, Error> {
+    let nested = LIBRARY.with(|library| library.replace(true));
+    let cancelled = CANCELLED.with(|cancelled| cancelled.replace(Some(cancel.0.clone())));
+    let settings = log::install(log::Settings::default());
+    let catalog = i18n::install(i18n::Catalog::default());
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    let stopped = interrupted();
+    i18n::install(catalog);
+    log::install(settings);
+    CANCELLED.with(|current| current.replace(cancelled));
+    LIBRARY.with(|library| library.set(nested));
+    match result {
+        Ok(_) if stopped => Err(Error::Exit(EXIT_INTERRUPTED)),
+        Ok(value) => Ok(value),
+        Err(payload) => {
+            match payload.downcast::<Exit>() {
+                Ok(exit) => Err(Error::Exit(exit.0)),
+                // a bug, not an exit
+                Err(payload) => panic::resume_unwind(payload),
+            }
+        }
+    }
+}
+
+// Plans a run: `argv` are the options and directories of
+// `organize-by-mtime plan [options] <plan> <directory>...`, without the
+// program name, the subcommand and <plan>. Nothing is moved. Files that can't
+// be read are logged and left out of the plan, as in a dry run.
+pub fn plan<S: AsRef<str>>(argv: &[S]) -> Result<Plan, Error> {
+    plan_with(argv, &Cancel::new(), |_| ())
+}
+
+// plan, stopped by `cancel`, handing each move to `on_move` as soon as it is
+// decided on
+pub fn plan_with<S, F>(argv: &[S], cancel: &Cancel, on_move: F) -> Result<Plan, Error>
+    where S: AsRef<str>,
+          F: FnMut(Move) + 'static
+{
+    let path = env::temp_dir().join(format!("organize-by-mtime-{}-{}.json",
+                                            process::id(),
+                                            CALLS.fetch_add(1, Ordering::SeqCst)));
+    let result = call(cancel, || {
+        let mut args = try!(parse(&path, argv));
+        let agepolicy = setup(&mut args);
+        if args.flag_watch.is_some() {
+            log_error!("Error: --watch can't be used by the library");
+            ::exit(EXIT_USAGE);
+        }
+        run(&args, agepolicy, Some(Box::new(on_move)));
+        Plan::load(&path).map_err(|e| {
+            log_error!("Error: plan {:?}: {}", path, e);
+            Error::Exit(EXIT_USAGE)
+        })
+    });
+    // a run that stopped early may not have finished it
+    let _ = fs::remove_file(&path);
+    result.and_then(|plan| plan)
+}
+
+// the arguments of `plan`, which saves the plan to `path`
+fn parse<S: AsRef<str>>(path: &Path, argv: &[S]) -> Result<Args, Error> {
+    let path = path.to_string_lossy();
+    let argv = vec!["organize-by-mtime", "plan", &path[..]]
+                   .into_iter()
+                   .chain(argv.iter().map(|arg| arg.as_ref()));
+    Docopt::new(USAGE)
+        .and_then(|d| d.argv(argv).decode())
+        .map_err(|e| Error::Usage(e.to_string()))
+}
+
+// Carries out a plan, from plan() or Plan::load, like `apply`: every move is
+// checked again, and those that no longer make sense are rejected. Returns
+// the number of moves rejected or failed.
+pub fn execute(plan: &Plan,
+               dry_run: bool,
+               force: bool,
+               reapply: bool,
+               manifest: Option<&Path>)
+               -> Result<usize, Error> {
+    execute_with(plan, dry_run, force, reapply, manifest, &Cancel::new(), |_, _| ())
+}
+
+// execute, stopped by `cancel`, telling `on_move` what became of each move as
+// it is made
+pub fn execute_with<F>(plan: &Plan,
+                       dry_run: bool,
+                       force: bool,
+                       reapply: bool,
+                       manifest: Option<&Path>,
+                       cancel: &Cancel,
+                       mut on_move: F)
+                       -> Result<usize, Error>
+    where F: FnMut(&Move, Outcome)
+{
+    call(cancel, || {
+        let _locks = if dry_run {
+            vec![]
+        } else {
+            lock_outputs(plan.moves.iter().map(|m| Path::new(&m.dst)))
+        };
+        let algorithm = hash::DEFAULT;
+        apply::apply(plan, dry_run, force, reapply, manifest, algorithm, &mut on_move) as usize
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+
+    use testing::scratch;
+    use super::{plan, plan_with, Cancel, Error};
+
+    #[test]
+    fn calls_keep_their_settings_and_cancellation() {
+        let dir = scratch("api-calls");
+        let src = dir.join("src");
+        fs::create_dir_all(&src).unwrap();
+        File::create(src.join("a.jpg")).unwrap();
+        let out = dir.join("out");
+        let (src, out) = (src.to_str().unwrap(), out.to_str().unwrap());
+        let argv = |buckets: &'static str| {
+            vec!["--layout={size}", buckets, "--strip-prefix", src, "-O", out, src]
+        };
+        let folder = |buckets| {
+            let plan = plan(&argv(buckets)).unwrap();
+            plan.moves[0].dst[out.len() + 1..].split('/').next().unwrap().to_string()
+        };
+        assert_eq!(folder("--size-buckets=empty:1,full"), "empty");
+        assert_eq!(folder("--size-buckets=none:0,some"), "some");
+
+        // cancelled during another call, which goes on
+        let cancel = Cancel::new();
+        let other = cancel.clone();
+        let planned = plan_with(&argv("--size-buckets=empty:1,full"), &Cancel::new(), move |_| {
+            other.cancel()
+        });
+        assert_eq!(planned.map(|plan| plan.moves.len()), Ok(1));
+        assert_eq!(plan_with(&argv("--size-buckets=empty:1,full"), &cancel, |_| ()).err(),
+                   Some(Error::Exit(130)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// `apply PLAN` carries out a plan saved with --save-plan, typically after
+// editing it by hand: moves can be deleted and destinations changed. Every
+// move is checked again before it is made, and those that no longer make
+// sense are rejected rather than applied.
+
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+use chrono::*;
+
+use executor::{Executor, LocalExecutor, MoveOp};
+use hash;
+use log::{self, Paint};
+use manifest::{self, Manifest};
+use plan::{Move, Plan};
+use {interrupted, new_run_id};
+
+
+// what became of a move, for the library API
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    // made, or found valid by a dry run
+    Applied,
+    // checked again and found to make no sense any more
+    Rejected(String),
+    Failed(String),
+}
+
+
+// the move to make for `m`, or why it is rejected
+fn check(m: &Move, force: bool, targets: &mut HashSet<PathBuf>) -> Result<MoveOp, String> {
+    let date = try!(NaiveDateTime::parse_from_str(&m.date, "%Y-%m-%dT%H:%M:%S")
+                        .map_err(|_| format!("invalid date {:?}", m.date)));
+    let src = PathBuf::from(&m.src);
+    let dst = PathBuf::from(&m.dst);
+    if !src.is_file() {
+        if src.ancestors().skip(1).any(|dir| dir.is_file()) {
+            return Err("an archive member, only extracted by --expand-archives".to_string());
+        }
+        return Err("source is gone".to_string());
+    }
+    if dst.file_name().is_none() || dst.components().any(|c| c == Component::ParentDir) {
+        return Err(format!("invalid destination {:?}", dst));
+    }
+    if dst == src {
+        return Err("destination is the source".to_string());
+    }
+    if !force && dst.exists() {
+        return Err(format!("destination {:?} exists", dst));
+    }
+    if !targets.insert(dst.clone()) {
+        return Err(format!("destination {:?} used twice", dst));
+    }
+    Ok(MoveOp {
+        src: src,
+        dst: dst,
+        date: date,
+        compress: false,
+        conflict: None,
+    })
+}
+
+// returns error count, including rejected moves
+pub fn run(plan_path: &Path,
+           dry_run: bool,
+           force: bool,
+           reapply: bool,
+           manifest_path: Option<&Path>,
+           algorithm: hash::Algorithm)
+           -> isize {
+    match Plan::load(plan_path) {
+        Ok(plan) => {
+            apply(&plan, dry_run, force, reapply, manifest_path, algorithm, &mut |_, _| ())
+        }
+        Err(e) => {
+            log_error!("Error: plan {:?}: {}", plan_path, e);
+            1
+        }
+    }
+}
+
+// run, for a plan that was loaded already, or made by the library API;
+// `report` is told what became of each move. Stops early when interrupted.
+// Checksums for the manifest are made with `algorithm`.
+pub fn apply(plan: &Plan,
+             dry_run: bool,
+             force: bool,
+             reapply: bool,
+             manifest_path: Option<&Path>,
+             algorithm: hash::Algorithm,
+             report: &mut dyn FnMut(&Move, Outcome))
+             -> isize {
+    // moves are recorded under the plan's id, which shows if it was applied
+    let run = plan.id.clone().unwrap_or_else(new_run_id);
+    if let (Some(id), Some(path)) = (plan.id.as_ref(), manifest_path) {
+        let applied = manifest::read(path)
+                          .map(|entries| entries.iter().any(|e| e.run.as_ref() == Some(id)))
+                          .unwrap_or(false);
+        if applied && !reapply {
+            log_error!("Error: plan {} was applied already (see {:?}); --reapply applies it again",
+                       id,
+                       path);
+            return 1;
+        }
+    }
+    let mut manifest = match manifest_path {
+        Some(path) if !dry_run => {
+            match Manifest::open(path, &run) {
+                Ok(manifest) => Some(manifest),
+                Err(e) => {
+                    log_error!("Error: manifest {:?}: {}", path, e);
+                    return 1;
+                }
+            }
+        }
+        _ => None,
+    };
+    let mut executor = LocalExecutor;
+    let mut targets = HashSet::new();
+    let (mut applied, mut rejected, mut errors) = (0, 0, 0);
+
+    for m in &plan.moves {
+        if interrupted() {
+            break;
+        }
+        let op = match check(m, force, &mut targets) {
+            Ok(op) => op,
+            Err(reason) => {
+                log_warn!("rejected {:?}: {}", m.src, reason);
+                rejected += 1;
+                report(m, Outcome::Rejected(reason));
+                continue;
+            }
+        };
+        log_info!("move {} {}",
+                  log::paint(&format!("{:?}", op.src), Paint::Source),
+                  log::paint(&executor.describe(&op), Paint::Destination));
+        if dry_run {
+            applied += 1;
+            report(m, Outcome::Applied);
+            continue;
+        }
+        let size = op.src.metadata().map(|md| md.len()).unwrap_or(0);
+        let checksum = manifest.as_ref().and_then(|_| hash::file(&op.src, algorithm).ok());
+        if let Err(e) = executor.execute(&op, force) {
+            log_error!("Error: dest: {:?}: {}", op.dst, e);
+            errors += 1;
+            report(m, Outcome::Failed(e.to_string()));
+            continue;
+        }
+        applied += 1;
+        report(m, Outcome::Applied);
+        if let Some(ref mut manifest) = manifest {
+            if let Err(e) = manifest.record(&op, size, checksum.as_ref()) {
+                log_error!("Error: manifest: {}", e);
+                errors += 1;
+            }
+        }
+    }
+    log_info!("{} of {} moves {}, {} rejected",
+              applied,
+              plan.moves.len(),
+              if dry_run { "valid" } else { "applied" },
+              rejected);
+    rejected + errors
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+
+    use api::{self, Error};
+    use testing::{organize, scratch};
+
+    // plans of moves `apply` would make as plain renames, losing what they do
+    #[test]
+    fn refuses_plans_it_cant_carry_out() {
+        let dir = scratch("apply-refuses");
+        let src = dir.join("src");
+        fs::create_dir_all(&src).unwrap();
+        File::create(src.join("a.jpg")).unwrap();
+        let plan = dir.join("plan.json");
+        let (plan_path, src_dir) = (plan.to_str().unwrap(), src.to_str().unwrap());
+        let out = dir.join("out");
+        let out = out.to_str().unwrap();
+        let refused = [vec!["--compress-older-than=1d", "-O", out],
+                       vec!["--archive=zip", "-O", out],
+                       vec!["--mirror", "-O", out],
+                       vec!["--executor=cat", "-O", out],
+                       vec!["--remove-source-after-verify", "-O", out],
+                       vec!["-O", "sftp://host/photos"]];
+        for options in &refused {
+            let mut argv = vec!["--save-plan", plan_path];
+            argv.extend(options.iter().cloned());
+            argv.push(src_dir);
+            assert_eq!(organize(&argv), Err(Error::Exit(2)), "{:?}", options);
+            let mut argv = options.clone();
+            argv.push(src_dir);
+            assert!(api::plan(&argv).is_err(), "{:?}", options);
+            assert!(!plan.exists(), "{:?}", options);
+            assert!(src.join("a.jpg").is_file(), "{:?}", options);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
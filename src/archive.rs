@@ -0,0 +1,241 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Archive executor: instead of moving files loose into YEAR/, append them to
+// one archive per year (or per month) in the output directory. Sources are
+// only removed once their archive has been written out completely.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::*;
+use filetime::FileTime;
+use tar;
+use zip;
+use zstd;
+
+use executor::{Executor, MoveOp};
+use {timestamp_date, unix_seconds};
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArchiveFormat {
+    TarZst,
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn parse(s: &str) -> Option<ArchiveFormat> {
+        match s {
+            "tar.zst" => Some(ArchiveFormat::TarZst),
+            "zip" => Some(ArchiveFormat::Zip),
+            _ => None,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match *self {
+            ArchiveFormat::TarZst => "tar.zst",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+
+enum Writer {
+    Tar(tar::Builder<zstd::Encoder<'static, File>>),
+    Zip(zip::ZipWriter<File>),
+}
+
+impl Writer {
+    fn open(path: &Path, format: ArchiveFormat) -> io::Result<Writer> {
+        if let Some(parent) = path.parent() {
+            try!(fs::create_dir_all(parent));
+        }
+        match format {
+            ArchiveFormat::TarZst => {
+                // never an existing archive, see `part_path`
+                let file = try!(OpenOptions::new().write(true).create_new(true).open(path));
+                let encoder = try!(zstd::Encoder::new(file, 0));
+                Ok(Writer::Tar(tar::Builder::new(encoder)))
+            }
+            ArchiveFormat::Zip => {
+                if path.exists() {
+                    let file = try!(OpenOptions::new().read(true).write(true).open(path));
+                    let writer = try!(zip::ZipWriter::new_append(file).map_err(zip_error));
+                    Ok(Writer::Zip(writer))
+                } else {
+                    Ok(Writer::Zip(zip::ZipWriter::new(try!(File::create(path)))))
+                }
+            }
+        }
+    }
+
+    fn append(&mut self, src: &Path, name: &Path) -> io::Result<()> {
+        let md = try!(fs::metadata(src));
+        let mtime = FileTime::from_last_modification_time(&md);
+        match *self {
+            Writer::Tar(ref mut builder) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_metadata(&md);
+                // tar can't store dates before 1970
+                header.set_mtime(cmp::max(unix_seconds(&mtime), 0) as u64);
+                let mut file = try!(File::open(src));
+                builder.append_data(&mut header, name, &mut file)
+            }
+            Writer::Zip(ref mut writer) => {
+                let dt = timestamp_date(unix_seconds(&mtime), 0);
+                let mut options = zip::write::SimpleFileOptions::default();
+                if let Ok(t) = zip::DateTime::from_date_and_time(dt.year() as u16,
+                                                                dt.month() as u8,
+                                                                dt.day() as u8,
+                                                                dt.hour() as u8,
+                                                                dt.minute() as u8,
+                                                                dt.second() as u8) {
+                    options = options.last_modified_time(t);
+                }
+                let name = name.to_string_lossy().replace('\\', "/");
+                try!(writer.start_file(name, options).map_err(zip_error));
+                let mut file = try!(File::open(src));
+                try!(io::copy(&mut file, writer));
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Writer::Tar(builder) => {
+                let encoder = try!(builder.into_inner());
+                try!(encoder.finish());
+                Ok(())
+            }
+            Writer::Zip(writer) => {
+                try!(writer.finish().map_err(zip_error));
+                Ok(())
+            }
+        }
+    }
+}
+
+fn zip_error(e: zip::result::ZipError) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+
+pub struct ArchiveExecutor {
+    output_dir: PathBuf,
+    format: ArchiveFormat,
+    per_month: bool,
+    // open archives by the path of their year or month, with the path they
+    // are written to and the sources appended to them so far
+    archives: HashMap<PathBuf, (PathBuf, Writer, Vec<PathBuf>)>,
+}
+
+impl ArchiveExecutor {
+    pub fn new(output_dir: &Path, format: ArchiveFormat, per_month: bool) -> ArchiveExecutor {
+        ArchiveExecutor {
+            output_dir: output_dir.to_path_buf(),
+            format: format,
+            per_month: per_month,
+            archives: HashMap::new(),
+        }
+    }
+
+    fn archive_path(&self, datetime: &NaiveDateTime) -> PathBuf {
+        let stem = if self.per_month {
+            datetime.format("%Y-%m").to_string()
+        } else {
+            datetime.year().to_string()
+        };
+        self.output_dir.join(format!("{}.{}", stem, self.format.extension()))
+    }
+
+    // A finished zstd stream can't be appended to, so a tar.zst archive
+    // written by an earlier run is left alone and the year continues in a new
+    // part, YEAR-2.tar.zst and so on. Zip archives are appended to.
+    fn part_path(&self, path: &Path) -> PathBuf {
+        if let Some(&(ref part, _, _)) = self.archives.get(path) {
+            return part.clone();
+        }
+        if self.format == ArchiveFormat::Zip {
+            return path.to_path_buf();
+        }
+        let extension = format!(".{}", self.format.extension());
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let stem = name.trim_end_matches(&extension[..]);
+        let mut part = path.to_path_buf();
+        let mut n = 2;
+        while part.exists() {
+            part = path.with_file_name(format!("{}-{}{}", stem, n, extension));
+            n += 1;
+        }
+        part
+    }
+
+    // path inside the archive: the destination below the year folder
+    fn member_name(&self, op: &MoveOp) -> PathBuf {
+        let rel = op.dst.strip_prefix(&self.output_dir).unwrap_or(&op.dst);
+        let year = op.date.year().to_string();
+        rel.strip_prefix(&year).unwrap_or(rel).to_path_buf()
+    }
+}
+
+impl Executor for ArchiveExecutor {
+    fn execute(&mut self, op: &MoveOp, _force: bool) -> io::Result<()> {
+        let path = self.archive_path(&op.date);
+        let name = self.member_name(op);
+        if !self.archives.contains_key(&path) {
+            let part = self.part_path(&path);
+            let writer = try!(Writer::open(&part, self.format));
+            self.archives.insert(path.clone(), (part, writer, vec![]));
+        }
+        let entry = self.archives.get_mut(&path).unwrap();
+        try!(entry.1.append(&op.src, &name));
+        entry.2.push(op.src.clone());
+        Ok(())
+    }
+
+    fn describe(&self, op: &MoveOp) -> String {
+        format!("{:?}", self.part_path(&self.archive_path(&op.date)).join(self.member_name(op)))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for (_, (path, writer, sources)) in self.archives.drain() {
+            if let Err(e) = writer.finish() {
+                result = Err(io::Error::new(e.kind(), format!("{:?}: {}", path, e)));
+                continue;
+            }
+            for src in sources {
+                if let Err(e) = fs::remove_file(&src) {
+                    result = Err(io::Error::new(e.kind(), format!("{:?}: {}", src, e)));
+                }
+            }
+        }
+        result
+    }
+}
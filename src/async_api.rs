@@ -0,0 +1,133 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// The library API for tokio programs, built with the `async` feature. plan
+// and execute run on tokio's blocking threads and return at once: a Run to
+// await for the result, and the moves as they are decided on or made, to
+// receive as they come.
+//
+//     let (run, mut moves) = async_api::plan(vec!["-O".into(), "sorted".into(), "photos".into()]);
+//     while let Some(m) = moves.recv().await {
+//         println!("{} -> {}", m.src, m.dst);
+//     }
+//     let plan = run.await?;
+//
+// Both must be called within a tokio runtime. Runs may overlap: each has its
+// own options, logging and cancellation, as api calls on different threads
+// do.
+
+use std::future::Future;
+use std::panic;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{self, Poll};
+
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::task::JoinHandle;
+
+use api::{self, Cancel, Error, Move, Outcome, Plan};
+use EXIT_INTERRUPTED;
+
+
+// a run in progress; resolves to its result
+pub struct Run<T> {
+    handle: JoinHandle<Result<T, Error>>,
+    cancel: Cancel,
+}
+
+impl<T> Run<T> {
+    // Stops the run after the file it is moving, as api::Cancel does; other
+    // runs go on. It then resolves to Error::Exit(130). Dropping a Run
+    // doesn't stop it.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+}
+
+// runs `f` on a blocking thread, unless cancelled before it gets one
+fn spawn<T, F>(f: F) -> Run<T>
+    where T: Send + 'static,
+          F: FnOnce(&Cancel) -> Result<T, Error> + Send + 'static
+{
+    let cancel = Cancel::new();
+    let handle = {
+        let cancel = cancel.clone();
+        tokio::task::spawn_blocking(move || {
+            if cancel.is_cancelled() {
+                return Err(Error::Exit(EXIT_INTERRUPTED));
+            }
+            f(&cancel)
+        })
+    };
+    Run {
+        handle: handle,
+        cancel: cancel,
+    }
+}
+
+impl<T> Future for Run<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<Result<T, Error>> {
+        match Pin::new(&mut self.handle).poll(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Ok(result)) => Poll::Ready(result),
+            Poll::Ready(Err(e)) => {
+                if e.is_panic() {
+                    panic::resume_unwind(e.into_panic());
+                }
+                // the runtime is shutting down
+                Poll::Ready(Err(Error::Exit(EXIT_INTERRUPTED)))
+            }
+        }
+    }
+}
+
+// api::plan; the moves come as they are decided on, and end when it is done
+pub fn plan(argv: Vec<String>) -> (Run<Plan>, UnboundedReceiver<Move>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let run = spawn(move |cancel| {
+        // the receiver may have been dropped; the run goes on
+        api::plan_with(&argv, cancel, move |m| {
+            let _ = tx.send(m);
+        })
+    });
+    (run, rx)
+}
+
+// api::execute; what became of each move comes as it is made
+pub fn execute(plan: Plan,
+               dry_run: bool,
+               force: bool,
+               reapply: bool,
+               manifest: Option<PathBuf>)
+               -> (Run<usize>, UnboundedReceiver<(Move, Outcome)>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let run = spawn(move |cancel| {
+        let manifest = manifest.as_ref().map(PathBuf::as_path);
+        api::execute_with(&plan, dry_run, force, reapply, manifest, cancel, |m, outcome| {
+            let _ = tx.send((m.clone(), outcome));
+        })
+    });
+    (run, rx)
+}
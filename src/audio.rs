@@ -0,0 +1,267 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Recording/release dates from audio tags: ID3v2 (TDRC, or TYER and TDAT)
+// and ID3v1 in MP3s, Vorbis comments (DATE) in FLAC and Ogg files, and the
+// ©day atom of MP4/M4A files.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use chrono::*;
+
+use dates::{find, from_digits};
+
+
+pub fn date(path: &Path) -> Option<NaiveDateTime> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return None,
+    };
+    let mut magic = [0; 8];
+    if file.read_exact(&mut magic).is_err() || file.seek(SeekFrom::Start(0)).is_err() {
+        return None;
+    }
+    let digits = if &magic[..3] == b"ID3" {
+        id3v2(&mut file).or_else(|| id3v1(&mut file))
+    } else if &magic[..4] == b"fLaC" {
+        flac(&mut file)
+    } else if &magic[..4] == b"OggS" {
+        ogg(&mut file)
+    } else if &magic[4..8] == b"ftyp" {
+        mp4(&mut file)
+    } else {
+        // MP3s without an ID3v2 tag may still have the old one at the end
+        id3v1(&mut file)
+    };
+    digits.and_then(|d| from_digits(&d))
+}
+
+// the digits of a tag value like 2019, 2019-07-14 or 2019-07-14T10:00:00,
+// up to anything else; UTF-16 text is handled by skipping its zero bytes
+fn digits_of(value: &[u8]) -> String {
+    value.iter()
+         .filter(|&&b| b != 0 && b != 0xFF && b != 0xFE)
+         .take_while(|b| b.is_ascii_digit() || b"-T:".contains(b))
+         .filter(|b| b.is_ascii_digit())
+         .map(|&b| b as char)
+         .collect()
+}
+
+fn read_at(file: &mut File, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    try!(file.seek(SeekFrom::Start(offset)));
+    let mut buf = vec![];
+    try!(file.take(len as u64).read_to_end(&mut buf));
+    Ok(buf)
+}
+
+fn syncsafe(b: &[u8]) -> usize {
+    (b[0] as usize) << 21 | (b[1] as usize) << 14 | (b[2] as usize) << 7 | b[3] as usize
+}
+
+fn be32(b: &[u8]) -> usize {
+    (b[0] as usize) << 24 | (b[1] as usize) << 16 | (b[2] as usize) << 8 | b[3] as usize
+}
+
+fn le32(b: &[u8]) -> usize {
+    (b[3] as usize) << 24 | (b[2] as usize) << 16 | (b[1] as usize) << 8 | b[0] as usize
+}
+
+fn id3v2(file: &mut File) -> Option<String> {
+    let header = match read_at(file, 0, 10) {
+        Ok(ref h) if h.len() == 10 => h.clone(),
+        _ => return None,
+    };
+    let version = header[3];
+    if version != 3 && version != 4 {
+        return None;
+    }
+    let tag = match read_at(file, 10, syncsafe(&header[6..10])) {
+        Ok(tag) => tag,
+        Err(_) => return None,
+    };
+    let (mut tdrc, mut tyer, mut tdat) = (None, None, None);
+    let mut at = 0;
+    while at + 10 <= tag.len() && tag[at] != 0 {
+        let id = &tag[at..at + 4];
+        let size = if version == 4 {
+            syncsafe(&tag[at + 4..at + 8])
+        } else {
+            be32(&tag[at + 4..at + 8])
+        };
+        let end = at + 10 + size;
+        if end > tag.len() {
+            break;
+        }
+        // text frames start with their encoding
+        let text = if size > 0 { digits_of(&tag[at + 11..end]) } else { String::new() };
+        match id {
+            b"TDRC" => tdrc = Some(text),
+            b"TYER" => tyer = Some(text),
+            b"TDAT" => tdat = Some(text),
+            _ => {}
+        }
+        at = end;
+    }
+    match (tdrc, tyer, tdat) {
+        (Some(date), _, _) => Some(date),
+        // TDAT is DDMM
+        (None, Some(year), Some(ref ddmm)) if ddmm.len() == 4 => {
+            Some(format!("{}{}{}", year, &ddmm[2..], &ddmm[..2]))
+        }
+        (None, Some(year), _) => Some(year),
+        _ => None,
+    }
+}
+
+fn id3v1(file: &mut File) -> Option<String> {
+    let len = match file.seek(SeekFrom::End(0)) {
+        Ok(len) if len >= 128 => len,
+        _ => return None,
+    };
+    match read_at(file, len - 128, 128) {
+        Ok(ref tag) if tag.starts_with(b"TAG") => {
+            Some(digits_of(&tag[93..97])).filter(|y| y.len() == 4)
+        }
+        _ => None,
+    }
+}
+
+// the value of the DATE comment in a Vorbis comment block
+fn vorbis_date(block: &[u8]) -> Option<String> {
+    let vendor = match block.get(..4) {
+        Some(len) => le32(len),
+        None => return None,
+    };
+    let mut at = 4 + vendor;
+    let count = match block.get(at..at + 4) {
+        Some(count) => le32(count),
+        None => return None,
+    };
+    at += 4;
+    for _ in 0..count {
+        let len = match block.get(at..at + 4) {
+            Some(len) => le32(len),
+            None => return None,
+        };
+        let comment = match block.get(at + 4..at + 4 + len) {
+            Some(comment) => comment,
+            None => return None,
+        };
+        if comment.len() > 5 && comment[..5].eq_ignore_ascii_case(b"DATE=") {
+            return Some(digits_of(&comment[5..]));
+        }
+        at += 4 + len;
+    }
+    None
+}
+
+fn flac(file: &mut File) -> Option<String> {
+    let mut at = 4;
+    loop {
+        let header = match read_at(file, at, 4) {
+            Ok(ref h) if h.len() == 4 => h.clone(),
+            _ => return None,
+        };
+        let len = (header[1] as usize) << 16 | (header[2] as usize) << 8 | header[3] as usize;
+        if header[0] & 0x7F == 4 {
+            return read_at(file, at + 4, len).ok().and_then(|block| vorbis_date(&block));
+        }
+        if header[0] & 0x80 != 0 {
+            return None;
+        }
+        at += 4 + len as u64;
+    }
+}
+
+// The comment header is the second packet of the stream. It is looked for in
+// the first pages, and only found if it doesn't span a page boundary, which
+// holds unless it carries big pictures.
+fn ogg(file: &mut File) -> Option<String> {
+    let head = match read_at(file, 0, 64 * 1024) {
+        Ok(head) => head,
+        Err(_) => return None,
+    };
+    let start = find(&head, b"\x03vorbis")
+                    .map(|i| i + 7)
+                    .or_else(|| find(&head, b"OpusTags").map(|i| i + 8));
+    start.and_then(|i| vorbis_date(&head[i..]))
+}
+
+// the children of an MP4 atom's payload, as (type, payload)
+fn atoms(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut found = vec![];
+    let mut at = 0;
+    while at + 8 <= data.len() {
+        let size = be32(&data[at..at + 4]);
+        if size < 8 || at + size > data.len() {
+            break;
+        }
+        found.push((&data[at + 4..at + 8], &data[at + 8..at + size]));
+        at += size;
+    }
+    found
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8]) -> Option<&'a [u8]> {
+    atoms(data).into_iter().find(|&(k, _)| k == kind).map(|(_, payload)| payload)
+}
+
+fn mp4(file: &mut File) -> Option<String> {
+    // find moov among the top-level atoms, skipping over the media data
+    let mut at = 0;
+    let moov = loop {
+        let header = match read_at(file, at, 16) {
+            Ok(ref h) if h.len() >= 8 => h.clone(),
+            _ => return None,
+        };
+        let size = match be32(&header[..4]) {
+            1 if header.len() == 16 => {
+                (be32(&header[8..12]) as u64) << 32 | be32(&header[12..16]) as u64
+            }
+            size if size >= 8 => size as u64,
+            _ => return None,
+        };
+        if &header[4..8] == b"moov" {
+            match read_at(file, at + 8, size as usize - 8) {
+                Ok(moov) => break moov,
+                Err(_) => return None,
+            }
+        }
+        at += size;
+    };
+    let udta = match child(&moov, b"udta") {
+        Some(udta) => udta,
+        None => return None,
+    };
+    // iTunes-style metadata, or the QuickTime user data atom
+    let day = child(udta, b"meta")
+                  .and_then(|meta| meta.get(4..))
+                  .and_then(|meta| child(meta, b"ilst"))
+                  .and_then(|ilst| child(ilst, b"\xA9day"))
+                  .and_then(|day| child(day, b"data"))
+                  .and_then(|data| data.get(8..))
+                  .or_else(|| child(udta, b"\xA9day").and_then(|day| day.get(4..)));
+    day.map(digits_of)
+}
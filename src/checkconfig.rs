@@ -0,0 +1,137 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+
+// `check-config`: checks the options of a run and the .organize.toml files
+// below its source directories without moving anything, and prints the
+// settings that are in effect, so that a typo shows before a long run rather
+// than after it. The options themselves are checked by building the run's
+// context, which stops at the first invalid one.
+
+use std::path::Path;
+
+use config;
+use patterns;
+use {is_remote, layout_text, walk_source, Args, Context};
+
+
+fn show(name: &str, values: &[String]) {
+    if !values.is_empty() {
+        println!("  {} = {:?}", name, values);
+    }
+}
+
+// the folder a file will be written to exists, or can be made
+fn check_parent(what: &str, path: &str) -> Option<String> {
+    let parent = Path::new(path).parent().filter(|p| !p.as_os_str().is_empty());
+    match parent {
+        Some(p) if !p.is_dir() => Some(format!("{} {:?}: no folder {:?}", what, path, p)),
+        _ => None,
+    }
+}
+
+// Returns the number of problems found
+pub fn run(args: &Args, ctx: &Context, save_plan: Option<&String>) -> isize {
+    let mut problems = vec![];
+    for dir in &args.arg_directory {
+        if !Path::new(dir).is_dir() {
+            problems.push(format!("source {:?} is not a directory", dir));
+        }
+    }
+    let output = Path::new(&args.flag_output_dir);
+    if !is_remote(&args.flag_output_dir) {
+        // the output may not exist yet, in which case the run makes it there
+        let existing = output.ancestors()
+                             .find(|p| !p.as_os_str().is_empty() && p.exists())
+                             .unwrap_or(Path::new("."));
+        if !existing.is_dir() {
+            problems.push(format!("output {:?} can't be created, {:?} is not a folder",
+                                  args.flag_output_dir,
+                                  existing));
+        } else if let Ok(resolved) = existing.canonicalize() {
+            for dir in &args.arg_directory {
+                let inside = Path::new(dir).canonicalize().map(|d| resolved.starts_with(d));
+                if inside.unwrap_or(false) {
+                    problems.push(format!("output {:?} is inside source {:?}, whose next run \
+                                           would organize it again",
+                                          args.flag_output_dir,
+                                          dir));
+                }
+            }
+        }
+    }
+    let (matching, not_matching) = patterns::cli_patterns(args);
+    if let Err(e) = patterns::build(&matching, &not_matching, args.flag_smart_case) {
+        problems.push(e);
+    }
+    if let Some(ref path) = args.flag_manifest {
+        problems.extend(check_parent("manifest", path));
+    }
+    if let Some(path) = save_plan {
+        problems.extend(check_parent("plan", path));
+    }
+
+    println!("output: {}", args.flag_output_dir);
+    println!("sources: {}", args.arg_directory.join(", "));
+    show("patterns", &args.flag_pattern);
+    show("not_patterns", &args.flag_not_pattern);
+    show("patterns_from", &args.flag_patterns_from);
+    show("not_patterns_from", &args.flag_not_patterns_from);
+    show("date_sources", &args.flag_date_source);
+    show("date_overrides", &args.flag_date_override);
+    println!("  layout = {:?}", layout_text(args).unwrap_or_default());
+    println!("  on_conflict = {:?}", args.flag_on_conflict);
+
+    // every .organize.toml, with the settings it comes to
+    for dir in &args.arg_directory {
+        for entry in walk_source(dir, args) {
+            if entry.file_name() != config::FILE_NAME {
+                continue;
+            }
+            let folder = entry.path().parent().unwrap_or(Path::new(dir));
+            let broken = ctx.configs.for_dir(folder, Path::new(dir)).map_or(false, |c| c.broken);
+            if broken {
+                // reported by for_dir
+                problems.push(format!("{:?} is invalid", entry.path()));
+                continue;
+            }
+            let settings = ctx.configs.settings(folder, Path::new(dir));
+            println!("{}:", entry.path().display());
+            show("patterns", settings.patterns.as_ref().unwrap_or(&vec![]));
+            show("not_patterns", settings.not_patterns.as_ref().unwrap_or(&vec![]));
+            show("date_sources", settings.date_sources.as_ref().unwrap_or(&vec![]));
+            if let Some(ref layout) = settings.layout {
+                println!("  layout = {:?}", layout);
+            }
+        }
+    }
+    ctx.configs.take_errors();
+
+    for problem in &problems {
+        log_error!("Error: {}", problem);
+    }
+    if problems.is_empty() {
+        log_info!("ok");
+    }
+    problems.len() as isize
+}
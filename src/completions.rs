@@ -0,0 +1,109 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+
+// `completions SHELL`: prints a completion script for bash, zsh or fish,
+// with the subcommands and the long options of the usage text.
+
+use std::collections::BTreeSet;
+
+
+const BIN: &'static str = "organize-by-mtime";
+
+const COMMANDS: [&'static str; 13] = ["organize",
+                                          "plan",
+                                          "apply",
+                                          "undo",
+                                          "verify",
+                                          "stats",
+                                          "completions",
+                                          "reorganize",
+                                          "plan-diff",
+                                          "find-duplicates",
+                                          "fix-mtime",
+                                          "check-config",
+                                          "doctor"];
+
+// every --long option named in `usage`
+fn options(usage: &str) -> Vec<String> {
+    let mut options = BTreeSet::new();
+    for word in usage.split(|c: char| !(c.is_alphanumeric() || c == '-')) {
+        if word.starts_with("--") && word.len() > 2 {
+            options.insert(word.to_string());
+        }
+    }
+    options.into_iter().collect()
+}
+
+// None for a shell it doesn't know
+pub fn script(shell: &str, usage: &str) -> Option<String> {
+    let commands = COMMANDS.join(" ");
+    let options = options(usage);
+    match shell {
+        "bash" => {
+            Some(format!("_organize_by_mtime() {{
+    local cur=${{COMP_WORDS[COMP_CWORD]}}
+    if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W \"{options}\" -- \"$cur\"))
+    elif [[ $COMP_CWORD == 1 ]]; then
+        COMPREPLY=($(compgen -W \"{commands}\" -- \"$cur\") $(compgen -d -- \"$cur\"))
+    else
+        COMPREPLY=($(compgen -f -- \"$cur\"))
+    fi
+}}
+complete -F _organize_by_mtime {bin}
+",
+                         options = options.join(" "),
+                         commands = commands,
+                         bin = BIN))
+        }
+        "zsh" => {
+            Some(format!("#compdef {bin}
+_organize_by_mtime() {{
+    if [[ $PREFIX == -* ]]; then
+        compadd -- {options}
+    elif (( CURRENT == 2 )); then
+        compadd -- {commands}
+        _files
+    else
+        _files
+    fi
+}}
+_organize_by_mtime \"$@\"
+",
+                         options = options.join(" "),
+                         commands = commands,
+                         bin = BIN))
+        }
+        "fish" => {
+            let mut script = format!("complete -c {} -n __fish_use_subcommand -a '{}'\n",
+                                     BIN,
+                                     commands);
+            for option in &options {
+                script.push_str(&format!("complete -c {} -l {}\n", BIN, &option[2..]));
+            }
+            Some(script)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,281 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+
+// Per-folder settings: a .organize.toml in a source directory, or any folder
+// below it, overrides the patterns, date sources and layout of the command
+// line for the files under that folder, so that photos and documents in one
+// tree can be organized in the same run by rules of their own. Settings not
+// given are taken from the .organize.toml above, if any, and then from the
+// command line:
+//
+//     # scans: dated by the name of their folder, kept by month
+//     patterns = ["*.pdf", "*.tiff"]
+//     date_sources = ["dirname", "mtime"]
+//     layout = "scans/{year}/{month}"
+//
+// The file is read as TOML; the settings are strings and arrays of strings.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use dates::Source;
+use patterns;
+use template::Template;
+use toml::{self, Value};
+
+
+pub const FILE_NAME: &'static str = ".organize.toml";
+
+// the settings of one .organize.toml as written
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    pub patterns: Option<Vec<String>>,
+    pub not_patterns: Option<Vec<String>>,
+    pub date_sources: Option<Vec<String>>,
+    pub layout: Option<String>,
+}
+
+// an array of strings, or an error about `key`
+fn strings(key: &str, value: Value) -> Result<Vec<String>, String> {
+    let error = || format!("{} must be an array of strings", key);
+    match value {
+        Value::Array(items) => {
+            items.into_iter()
+                 .map(|item| match item {
+                     Value::String(s) => Ok(s),
+                     _ => Err(error()),
+                 })
+                 .collect()
+        }
+        _ => Err(error()),
+    }
+}
+
+pub fn parse(text: &str) -> Result<Settings, String> {
+    let mut parser = toml::Parser::new(text);
+    let table = match parser.parse() {
+        Some(table) => table,
+        None => {
+            let e = &parser.errors[0];
+            let (line, _) = parser.to_linecol(e.lo);
+            return Err(format!("line {}: {}", line + 1, e.desc));
+        }
+    };
+    let mut settings = Settings::default();
+    for (key, value) in table {
+        match &key[..] {
+            "patterns" => settings.patterns = Some(try!(strings(&key, value))),
+            "not_patterns" => settings.not_patterns = Some(try!(strings(&key, value))),
+            "date_sources" => settings.date_sources = Some(try!(strings(&key, value))),
+            "layout" => {
+                match value {
+                    Value::String(s) => settings.layout = Some(s),
+                    _ => return Err("layout must be a string".to_string()),
+                }
+            }
+            _ => return Err(format!("unknown setting {:?}", key)),
+        }
+    }
+    Ok(settings)
+}
+
+pub fn load(path: &Path) -> Result<Settings, String> {
+    let mut text = String::new();
+    try!(File::open(path).and_then(|mut f| f.read_to_string(&mut text)).map_err(|e| e.to_string()));
+    parse(&text)
+}
+
+// The settings in effect for a folder, checked and compiled
+pub struct DirConfig {
+    // the folder of the .organize.toml that set the patterns, and the filter
+    // matching paths below it
+    filter: Option<(PathBuf, Box<dyn Fn(&Path) -> bool>)>,
+    pub date_sources: Option<Vec<Source>>,
+    pub layout: Option<Template>,
+    // an invalid .organize.toml here or above; its files are left alone
+    pub broken: bool,
+}
+
+impl DirConfig {
+    // None if the command line patterns apply
+    pub fn wanted(&self, path: &Path) -> Option<bool> {
+        self.filter
+            .as_ref()
+            .map(|&(ref dir, ref filter)| filter(path.strip_prefix(dir).unwrap_or(path)))
+    }
+}
+
+// The settings merged down to a folder, checked. Patterns not set there are
+// the command line's, `cli`.
+fn compile(merged: &Settings, patterns_dir: &Path, cli: &Cli) -> Result<DirConfig, String> {
+    let filter = if merged.patterns.is_some() || merged.not_patterns.is_some() {
+        let matching = merged.patterns.as_ref().unwrap_or(&cli.patterns);
+        let not_matching = merged.not_patterns.as_ref().unwrap_or(&cli.not_patterns);
+        Some((patterns_dir.to_path_buf(),
+              try!(patterns::build(matching, not_matching, cli.smart_case))))
+    } else {
+        None
+    };
+    let date_sources = match merged.date_sources {
+        Some(ref names) => {
+            let mut sources = vec![];
+            for name in names {
+                sources.push(try!(Source::parse(name)
+                                      .ok_or_else(|| format!("unknown date source {:?}", name))));
+            }
+            Some(sources)
+        }
+        None => None,
+    };
+    let layout = match merged.layout {
+        Some(ref text) => Some(try!(Template::parse(text))),
+        None => None,
+    };
+    Ok(DirConfig {
+        filter: filter,
+        date_sources: date_sources,
+        layout: layout,
+        broken: false,
+    })
+}
+
+// what a folder's .organize.toml and those above it come to
+#[derive(Clone)]
+struct Merged {
+    settings: Settings,
+    // where the patterns in effect were set
+    patterns_dir: PathBuf,
+    config: Option<Rc<DirConfig>>,
+}
+
+// the patterns of the command line
+pub struct Cli {
+    pub patterns: Vec<String>,
+    pub not_patterns: Vec<String>,
+    pub smart_case: bool,
+}
+
+// The .organize.toml files found so far, by folder
+pub struct Configs {
+    cli: Cli,
+    dirs: RefCell<HashMap<PathBuf, Merged>>,
+    // invalid files, reported once each, not yet counted by the caller
+    errors: Cell<isize>,
+}
+
+impl Configs {
+    pub fn new(cli: Cli) -> Configs {
+        Configs {
+            cli: cli,
+            dirs: RefCell::new(HashMap::new()),
+            errors: Cell::new(0),
+        }
+    }
+
+    // the settings for files in `dir`, below the source directory `root`;
+    // None where no .organize.toml applies
+    pub fn for_dir(&self, dir: &Path, root: &Path) -> Option<Rc<DirConfig>> {
+        self.merged(dir, root).config
+    }
+
+    // the settings for files in `dir` as written, merged with those above
+    pub fn settings(&self, dir: &Path, root: &Path) -> Settings {
+        self.merged(dir, root).settings
+    }
+
+    fn merged(&self, dir: &Path, root: &Path) -> Merged {
+        if let Some(m) = self.dirs.borrow().get(dir) {
+            return m.clone();
+        }
+        let parent = match dir.parent() {
+            Some(parent) if dir != root && dir.starts_with(root) => self.merged(parent, root),
+            _ => {
+                Merged {
+                    settings: Settings::default(),
+                    patterns_dir: dir.to_path_buf(),
+                    config: None,
+                }
+            }
+        };
+        let path = dir.join(FILE_NAME);
+        let merged = if !path.is_file() {
+            parent
+        } else {
+            match load(&path) {
+                Ok(own) => {
+                    let own_patterns = own.patterns.is_some() || own.not_patterns.is_some();
+                    let settings = Settings {
+                        patterns: own.patterns.or(parent.settings.patterns),
+                        not_patterns: own.not_patterns.or(parent.settings.not_patterns),
+                        date_sources: own.date_sources.or(parent.settings.date_sources),
+                        layout: own.layout.or(parent.settings.layout),
+                    };
+                    let patterns_dir = if own_patterns {
+                        dir.to_path_buf()
+                    } else {
+                        parent.patterns_dir
+                    };
+                    let config = match compile(&settings, &patterns_dir, &self.cli) {
+                        Ok(config) => config,
+                        Err(e) => self.broken(&path, &e),
+                    };
+                    Merged {
+                        settings: settings,
+                        patterns_dir: patterns_dir,
+                        config: Some(Rc::new(config)),
+                    }
+                }
+                Err(e) => {
+                    Merged {
+                        settings: parent.settings,
+                        patterns_dir: parent.patterns_dir,
+                        config: Some(Rc::new(self.broken(&path, &e))),
+                    }
+                }
+            }
+        };
+        self.dirs.borrow_mut().insert(dir.to_path_buf(), merged.clone());
+        merged
+    }
+
+    fn broken(&self, path: &Path, e: &str) -> DirConfig {
+        log_error!("Error: {:?}: {}; files below it are left alone", path, e);
+        self.errors.set(self.errors.get() + 1);
+        DirConfig {
+            filter: None,
+            date_sources: None,
+            layout: None,
+            broken: true,
+        }
+    }
+
+    // the invalid files found since the last call
+    pub fn take_errors(&self) -> isize {
+        self.errors.replace(0)
+    }
+}
@@ -0,0 +1,158 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Control socket for --watch mode: JSON-RPC 2.0 over a unix socket, one
+// request per line. Methods: status, trigger-run, pause, resume and
+// reload-config. reload-config only acknowledges the request: the destination
+// script and the .organize.toml files are read afresh at the start of every
+// run, so the next one sees their changes. The options stay those the daemon
+// was started with.
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::sync::Arc;
+use std::thread;
+
+use rustc_serialize::json::Json;
+
+use daemon::Daemon;
+
+
+fn response(id: Json, result: Result<Json, (i64, String)>) -> Json {
+    let mut obj = BTreeMap::new();
+    obj.insert("jsonrpc".to_string(), Json::String("2.0".to_string()));
+    obj.insert("id".to_string(), id);
+    match result {
+        Ok(value) => {
+            obj.insert("result".to_string(), value);
+        }
+        Err((code, message)) => {
+            let mut err = BTreeMap::new();
+            err.insert("code".to_string(), Json::I64(code));
+            err.insert("message".to_string(), Json::String(message));
+            obj.insert("error".to_string(), Json::Object(err));
+        }
+    }
+    Json::Object(obj)
+}
+
+fn handle(line: &str, daemon: &Daemon) -> Json {
+    let request = match Json::from_str(line) {
+        Ok(Json::Object(obj)) => obj,
+        _ => return response(Json::Null, Err((-32700, "Parse error".to_string()))),
+    };
+    let id = request.get("id").cloned().unwrap_or(Json::Null);
+    let method = match request.get("method") {
+        Some(&Json::String(ref m)) => m.clone(),
+        _ => return response(id, Err((-32600, "Invalid Request".to_string()))),
+    };
+    let result = match &method[..] {
+        "status" => Ok(daemon.status()),
+        "trigger-run" => {
+            daemon.trigger();
+            Ok(Json::Boolean(true))
+        }
+        "pause" => {
+            daemon.set_paused(true);
+            Ok(Json::Boolean(true))
+        }
+        "resume" => {
+            daemon.set_paused(false);
+            Ok(Json::Boolean(true))
+        }
+        // see the top of the file
+        "reload-config" => Ok(Json::Boolean(true)),
+        _ => Err((-32601, "Method not found".to_string())),
+    };
+    response(id, result)
+}
+
+#[cfg(unix)]
+pub fn serve(path: &str, daemon: Arc<Daemon>) -> io::Result<()> {
+    use std::fs;
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+
+    // a stale socket from an earlier run would make bind fail; anything
+    // else at PATH is someone's file, and stays
+    match fs::symlink_metadata(path) {
+        Ok(ref md) if md.file_type().is_socket() => try!(fs::remove_file(path)),
+        Ok(_) => {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "exists and isn't a socket"))
+        }
+        Err(_) => {}
+    }
+    let listener = try!(UnixListener::bind(path));
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+            let daemon = daemon.clone();
+            thread::spawn(move || {
+                let mut writer = match stream.try_clone() {
+                    Ok(w) => w,
+                    Err(_) => return,
+                };
+                for line in BufReader::new(stream).lines() {
+                    let line = match line {
+                        Ok(l) => l,
+                        Err(_) => return,
+                    };
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let reply = handle(&line, &daemon);
+                    if writeln!(writer, "{}", reply).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve(_: &str, _: Arc<Daemon>) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "control sockets need unix"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rustc_serialize::json::Json;
+
+    use daemon::Daemon;
+    use super::handle;
+
+    #[test]
+    fn reload_config_is_acknowledged() {
+        let reply = handle(r#"{"jsonrpc": "2.0", "id": 1, "method": "reload-config"}"#,
+                           &Daemon::new());
+        assert_eq!(reply.find("result"), Some(&Json::Boolean(true)));
+        let reply = handle(r#"{"jsonrpc": "2.0", "id": 2, "method": "reload"}"#, &Daemon::new());
+        assert!(reply.find("error").is_some());
+    }
+}
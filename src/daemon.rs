@@ -0,0 +1,122 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Shared state of --watch mode, so that runs can be triggered, paused and
+// inspected from outside the main loop (see control.rs).
+
+use std::collections::BTreeMap;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use rustc_serialize::json::Json;
+
+use summary::Summary;
+
+
+struct State {
+    paused: bool,
+    triggered: bool,
+    running: bool,
+    runs: usize,
+    last: Option<Summary>,
+}
+
+pub struct Daemon {
+    state: Mutex<State>,
+    wake: Condvar,
+}
+
+impl Daemon {
+    pub fn new() -> Daemon {
+        Daemon {
+            state: Mutex::new(State {
+                paused: false,
+                triggered: false,
+                running: false,
+                runs: 0,
+                last: None,
+            }),
+            wake: Condvar::new(),
+        }
+    }
+
+    // asks for a run as soon as possible, even when paused
+    pub fn trigger(&self) {
+        self.state.lock().unwrap().triggered = true;
+        self.wake.notify_all();
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.state.lock().unwrap().paused = paused;
+        self.wake.notify_all();
+    }
+
+    pub fn start_run(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.running = true;
+        state.triggered = false;
+    }
+
+    pub fn finish_run(&self, summary: Summary) {
+        let mut state = self.state.lock().unwrap();
+        state.running = false;
+        state.runs += 1;
+        state.last = Some(summary);
+    }
+
+    pub fn last_summary(&self) -> Option<Summary> {
+        self.state.lock().unwrap().last.clone()
+    }
+
+    pub fn status(&self) -> Json {
+        let state = self.state.lock().unwrap();
+        let mut status = BTreeMap::new();
+        status.insert("paused".to_string(), Json::Boolean(state.paused));
+        status.insert("running".to_string(), Json::Boolean(state.running));
+        status.insert("runs".to_string(), Json::U64(state.runs as u64));
+        status.insert("last_run".to_string(),
+                      match state.last {
+                          Some(ref summary) => Json::from_str(&summary.to_json()).unwrap(),
+                          None => Json::Null,
+                      });
+        Json::Object(status)
+    }
+
+    // blocks until the next run is due: `interval` has passed without being
+    // paused, or a run was triggered
+    pub fn wait(&self, interval: Duration) {
+        let deadline = Instant::now() + interval;
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.triggered {
+                return;
+            }
+            let now = Instant::now();
+            if !state.paused && now >= deadline {
+                return;
+            }
+            let timeout = if state.paused { interval } else { deadline - now };
+            state = self.wake.wait_timeout(state, timeout).unwrap().0;
+        }
+    }
+}
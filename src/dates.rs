@@ -0,0 +1,261 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Dates taken from somewhere else than a file's mtime, for when mtimes were
+// lost to copying or downloading, and rules for which files use which.
+
+use std::fs;
+use std::path::Path;
+
+use chrono::*;
+use glob::Pattern;
+
+use audio;
+use email;
+use exif;
+use file_mtime;
+use office;
+use pdf;
+
+
+// where the date of a file comes from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    Mtime,
+    // the closest folder named after a date, see from_dirname
+    Dirname,
+    // a date anywhere in the file name, e.g. IMG_20190714_123456.jpg
+    Filename,
+    // the creation date embedded in PDF documents
+    Pdf,
+    // the creation or last-save date stored in office documents
+    Office(office::Which),
+    // the recording date from ID3, Vorbis comment or MP4 tags
+    AudioTags,
+    // when an .eml or .msg file was sent
+    Email,
+    // when a photo was taken, from its EXIF data
+    Exif,
+}
+
+impl Source {
+    pub fn parse(s: &str) -> Option<Source> {
+        match s {
+            "mtime" => Some(Source::Mtime),
+            "dirname" => Some(Source::Dirname),
+            "filename" => Some(Source::Filename),
+            "pdf" => Some(Source::Pdf),
+            "office" => Some(Source::Office(office::Which::Created)),
+            "office-modified" => Some(Source::Office(office::Which::Modified)),
+            "audio-tags" => Some(Source::AudioTags),
+            "email" => Some(Source::Email),
+            "exif" => Some(Source::Exif),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Source::Mtime => "mtime",
+            Source::Dirname => "dirname",
+            Source::Filename => "filename",
+            Source::Pdf => "pdf",
+            Source::Office(office::Which::Created) => "office",
+            Source::Office(office::Which::Modified) => "office-modified",
+            Source::AudioTags => "audio-tags",
+            Source::Email => "email",
+            Source::Exif => "exif",
+        }
+    }
+}
+
+// --round-to: periods batch dates are snapped to the start of
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Period {
+    Day,
+    // starting on Monday
+    Week,
+    Month,
+}
+
+impl Period {
+    pub fn parse(s: &str) -> Option<Period> {
+        match s {
+            "day" => Some(Period::Day),
+            "week" => Some(Period::Week),
+            "month" => Some(Period::Month),
+            _ => None,
+        }
+    }
+}
+
+pub fn round_to(date: &NaiveDateTime, period: Period) -> NaiveDateTime {
+    let day = date.date();
+    let start = match period {
+        Period::Day => day,
+        Period::Week => day - Duration::days(day.weekday().num_days_from_monday() as i64),
+        Period::Month => NaiveDate::from_ymd(day.year(), day.month(), 1),
+    };
+    start.and_hms(0, 0, 0)
+}
+
+// --date-override PATTERN=SOURCE: files whose path below the source
+// directory matches PATTERN are dated by SOURCE
+pub struct Override {
+    pattern: Pattern,
+    source: Source,
+}
+
+impl Override {
+    pub fn parse(spec: &str) -> Result<Override, String> {
+        let split = try!(spec.rfind('=').ok_or("expected PATTERN=SOURCE".to_string()));
+        let pattern = try!(Pattern::new(&spec[..split]).map_err(|e| e.to_string()));
+        let source = try!(Source::parse(&spec[split + 1..])
+                              .ok_or(format!("unknown date source {:?}", &spec[split + 1..])));
+        Ok(Override {
+            pattern: pattern,
+            source: source,
+        })
+    }
+
+    pub fn matches(&self, rel: &Path) -> bool {
+        self.pattern.matches_path(rel)
+    }
+
+    pub fn source(&self) -> Source {
+        self.source
+    }
+}
+
+// the source of the first override matching `rel`, a path below the source
+// directory
+pub fn override_for(overrides: &[Override], rel: &Path) -> Option<Source> {
+    overrides.iter().find(|o| o.matches(rel)).map(|o| o.source())
+}
+
+// the date of `path` by `source`, if it has one
+pub fn date_by(source: Source,
+               path: &Path,
+               root: &Path,
+               md: &fs::Metadata)
+               -> Option<NaiveDateTime> {
+    match source {
+        Source::Mtime => Some(file_mtime(md)),
+        Source::Dirname => from_dirname(path, root),
+        Source::Filename => path.file_name().and_then(|n| n.to_str()).and_then(from_filename),
+        Source::Pdf => pdf::creation_date(path),
+        Source::Office(which) => office::date(path, which),
+        Source::AudioTags => audio::date(path),
+        Source::Email => email::date(path),
+        Source::Exif => exif::date(path),
+    }
+}
+
+pub fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+// the digits following `key` and any of the characters in `skip`, up to the
+// first character that is neither a digit nor in `between`
+pub fn digits_after(data: &[u8], key: &[u8], skip: &[u8], between: &[u8]) -> Option<String> {
+    let rest = match find(data, key) {
+        Some(i) => &data[i + key.len()..],
+        None => return None,
+    };
+    let rest = match rest.iter().position(|b| !skip.contains(b)) {
+        Some(i) => &rest[i..],
+        None => return None,
+    };
+    Some(rest.iter()
+             .take_while(|b| b.is_ascii_digit() || between.contains(b))
+             .filter(|b| b.is_ascii_digit())
+             .map(|&b| b as char)
+             .collect())
+}
+
+// parses YYYY[MM[DD[HH[mm[SS]]]]], the missing parts being the earliest
+// possible
+pub fn from_digits(digits: &str) -> Option<NaiveDateTime> {
+    let part = |from: usize, len: usize, default: u32| {
+        digits.get(from..from + len).and_then(|s| s.parse().ok()).unwrap_or(default)
+    };
+    if digits.len() < 4 {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(part(0, 4, 0) as i32, part(4, 2, 1), part(6, 2, 1))
+        .and_then(|d| d.and_hms_opt(part(8, 2, 0), part(10, 2, 0), part(12, 2, 0)))
+}
+
+fn number(digits: &[u8]) -> Option<u32> {
+    if digits.iter().all(|b| b.is_ascii_digit()) {
+        ::std::str::from_utf8(digits).ok().and_then(|s| s.parse().ok())
+    } else {
+        None
+    }
+}
+
+// Parses a date at the start of `name`: 2019-07-14, 2019_07_14, 2019.07.14,
+// 20190714 or just the month, 2019-07; what follows must not be a digit, as
+// in "2019-07 Vacation" or "20180101_NewYear".
+pub fn from_name(name: &str) -> Option<NaiveDateTime> {
+    let b = name.as_bytes();
+    let sep = |i: usize| i < b.len() && b"-_.".contains(&b[i]);
+    let ends = |i: usize| i >= b.len() || !b[i].is_ascii_digit();
+    let (y, m, d) = if b.len() >= 8 && ends(8) && number(&b[..8]).is_some() {
+        (number(&b[..4]), number(&b[4..6]), number(&b[6..8]))
+    } else if b.len() >= 7 && sep(4) && ends(7) {
+        let day = if b.len() >= 10 && sep(7) && ends(10) { number(&b[8..10]) } else { Some(1) };
+        (number(&b[..4]), number(&b[5..7]), day)
+    } else {
+        return None;
+    };
+    match (y, m, d) {
+        (Some(y), Some(m), Some(d)) => {
+            NaiveDate::from_ymd_opt(y as i32, m, d).map(|date| date.and_hms(0, 0, 0))
+        }
+        _ => None,
+    }
+}
+
+// the first date in a file name that starts after a non-digit
+pub fn from_filename(name: &str) -> Option<NaiveDateTime> {
+    let b = name.as_bytes();
+    (0..b.len())
+        .filter(|&i| b[i].is_ascii_digit() && (i == 0 || !b[i - 1].is_ascii_digit()))
+        .filter_map(|i| from_name(&name[i..]))
+        .next()
+}
+
+// the date of the closest folder above `path`, up to and including `root`,
+// that is named after one
+pub fn from_dirname(path: &Path, root: &Path) -> Option<NaiveDateTime> {
+    for dir in path.ancestors().skip(1) {
+        if let Some(date) = dir.file_name().and_then(|n| n.to_str()).and_then(from_name) {
+            return Some(date);
+        }
+        if dir == root {
+            break;
+        }
+    }
+    None
+}
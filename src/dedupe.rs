@@ -0,0 +1,143 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Finding files with the same content. Candidates are grouped by size, then
+// by a hash of their content, and finally compared byte by byte, so a hash
+// collision can't make two different files look alike. A quick check may take
+// files of the same size and mtime to be the same without reading them, as
+// rsync does.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use filetime::FileTime;
+
+use hash::{self, Checksum};
+use stats::human_size;
+use unix_seconds;
+use verify;
+
+
+pub fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    let mut a = try!(File::open(a));
+    let mut b = try!(File::open(b));
+    let mut abuf = [0; 64 * 1024];
+    let mut bbuf = [0; 64 * 1024];
+    loop {
+        let n = try!(a.read(&mut abuf));
+        if n == 0 {
+            return Ok(try!(b.read(&mut bbuf)) == 0);
+        }
+        try!(b.read_exact(&mut bbuf[..n]));
+        if abuf[..n] != bbuf[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+// Returns the groups of identical files among `files`, each in the order
+// given, with their size. Empty files and files that can't be read are left
+// out. With `quick`, files of the same size and mtime count as identical and
+// only one of them is read.
+pub fn duplicates(files: &[PathBuf],
+                  quick: bool,
+                  algorithm: hash::Algorithm)
+                  -> Vec<(u64, Vec<PathBuf>)> {
+    // indices into `files` by size, in sets taken to be identical: those of
+    // the same mtime if quick, else each file alone
+    let mut by_size: BTreeMap<u64, BTreeMap<i64, Vec<usize>>> = BTreeMap::new();
+    for (i, file) in files.iter().enumerate() {
+        match fs::metadata(file) {
+            Ok(ref md) if md.len() > 0 => {
+                let set = if quick {
+                    unix_seconds(&FileTime::from_last_modification_time(md))
+                } else {
+                    i as i64
+                };
+                by_size.entry(md.len())
+                       .or_insert_with(BTreeMap::new)
+                       .entry(set)
+                       .or_insert(vec![])
+                       .push(i);
+            }
+            _ => {}
+        }
+    }
+
+    let mut groups: Vec<(u64, Vec<PathBuf>)> = vec![];
+    for (size, sets) in by_size {
+        // the sets of files already known to be identical, read as one
+        let mut by_hash: BTreeMap<Option<Checksum>, Vec<Vec<usize>>> = BTreeMap::new();
+        let mut sets: Vec<Vec<usize>> = sets.into_iter().map(|(_, set)| set).collect();
+        if sets.len() == 1 {
+            if sets[0].len() > 1 {
+                by_hash.insert(None, sets);
+            }
+        } else {
+            for set in sets.drain(..) {
+                if let Ok(checksum) = hash::file(&files[set[0]], algorithm) {
+                    by_hash.entry(Some(checksum)).or_insert(vec![]).push(set);
+                }
+            }
+        }
+        for (_, mut rest) in by_hash {
+            while !rest.is_empty() {
+                let mut group = rest.remove(0);
+                let first = &files[group[0]];
+                let (same, other): (Vec<Vec<usize>>, Vec<Vec<usize>>) =
+                    rest.into_iter()
+                        .partition(|set| same_content(first, &files[set[0]]).unwrap_or(false));
+                for set in same {
+                    group.extend(set);
+                }
+                if group.len() > 1 {
+                    group.sort();
+                    groups.push((size, group.into_iter().map(|i| files[i].clone()).collect()));
+                }
+                rest = other;
+            }
+        }
+    }
+    groups.sort_by(|a, b| a.1.cmp(&b.1));
+    groups
+}
+
+// `find-duplicates OUTPUT_DIR`: reports identical files in the year folders
+// of an organized tree; returns the number of redundant copies
+pub fn report(output: &Path, algorithm: hash::Algorithm) -> usize {
+    let files: Vec<PathBuf> = verify::organized_files(output).into_iter().map(|(f, _)| f).collect();
+    let mut copies = 0;
+    let mut wasted = 0;
+    for (size, group) in duplicates(&files, false, algorithm) {
+        println!("{} copies, {} each:", group.len(), human_size(size));
+        for file in &group {
+            println!("  {:?}", file);
+        }
+        copies += group.len() - 1;
+        wasted += size * (group.len() - 1) as u64;
+    }
+    println!("{} redundant copies, {} wasted", copies, human_size(wasted));
+    copies
+}
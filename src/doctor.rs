@@ -0,0 +1,222 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+// `doctor DIRECTORY...`: probes the filesystems of the source directories and
+// the output directory, and reports what that means for a run, before a big
+// one is started: whether moves are renames or copies, whether names that
+// differ only in case clash, whether extended attributes and fine timestamps
+// are kept, how long paths may get and how much space is free. The probes
+// are a few hidden files in the output and source directories, removed
+// again. A remote output isn't probed.
+
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use filetime::{self, FileTime};
+
+use executor::CROSS_DEVICE;
+use pathlen;
+use preflight::space;
+use stats::human_size;
+use {is_remote, unix_seconds, Args};
+
+
+const PROBE: &'static str = ".organize-by-mtime-doctor";
+// moved from a source directory to the output to see what a move is there
+const RENAME_PROBE: &'static str = ".organize-by-mtime-doctor-rename";
+
+// Returns the number of problems found, which make a run fail
+pub fn run(args: &Args) -> isize {
+    let mut problems = 0;
+    if is_remote(&args.flag_output_dir) {
+        println!("output {:?}: remote, not probed; moves there are copies",
+                 args.flag_output_dir);
+        for dir in &args.arg_directory {
+            println!("source {:?}:", dir);
+            problems += probe_source(Path::new(dir));
+        }
+        return problems;
+    }
+    let output = Path::new(&args.flag_output_dir);
+    // the output may not exist yet; what counts is the filesystem it will be on
+    let existing = match output.ancestors().find(|p| p.is_dir()) {
+        Some(p) if p.as_os_str().is_empty() => PathBuf::from("."),
+        Some(p) => p.to_path_buf(),
+        None => PathBuf::from("."),
+    };
+    println!("output {:?}{}:",
+             args.flag_output_dir,
+             if existing == output { String::new() } else { format!(", on {:?}", existing) });
+    match space(&existing) {
+        Some((_, free)) => println!("  free space: {}", human_size(free)),
+        None => println!("  free space: unknown"),
+    }
+    let limits = pathlen::limits(output, args.flag_max_path_length);
+    println!("  longest path: {} bytes, file name: {} bytes (see --long-paths)",
+             limits.path,
+             limits.name);
+    let writable = probe(&existing);
+    problems += writable;
+    for dir in &args.arg_directory {
+        println!("source {:?}:", dir);
+        let source = probe_source(Path::new(dir));
+        problems += source;
+        if writable > 0 || source > 0 {
+            continue;
+        }
+        match renames(Path::new(dir), &existing) {
+            Ok(true) => println!("  moves to the output: renames, fast and atomic"),
+            Ok(false) => {
+                println!("  moves to the output: copies and deletes, as it is another \
+                          filesystem, and take space")
+            }
+            Err(e) => {
+                println!("  moves to the output: fail: {}", e);
+                problems += 1;
+            }
+        }
+    }
+    problems
+}
+
+// the probes of a source directory; returns the number of problems found
+fn probe_source(dir: &Path) -> isize {
+    if !dir.is_dir() {
+        println!("  not a directory");
+        return 1;
+    }
+    probe(dir)
+}
+
+// Prints what the filesystem of `dir` keeps, from a probe file made there;
+// returns the number of problems found. The probe is never one that exists,
+// which would be a file of someone's, or left over from a probe that failed.
+fn probe(dir: &Path) -> isize {
+    let probe = dir.join(PROBE);
+    if let Err(e) = OpenOptions::new().write(true).create_new(true).open(&probe) {
+        match e.kind() {
+            io::ErrorKind::AlreadyExists => println!("  {:?} is in the way, remove it", probe),
+            _ => println!("  not writable: {}", e),
+        }
+        return 1;
+    }
+    println!("  names differing only in case: {}",
+             if case_sensitive(dir) {
+                 "kept apart"
+             } else {
+                 "the same file; files named so clash, see --on-conflict"
+             });
+    println!("  extended attributes: {}",
+             match xattrs(&probe) {
+                 Some(true) => "supported",
+                 Some(false) => "not supported; they are lost when files are copied there",
+                 None => "unknown",
+             });
+    println!("  timestamps: {}", resolution(&probe));
+    let _ = fs::remove_file(&probe);
+    0
+}
+
+// whether a file moves from `src` into `output` by a rename, found by moving
+// a probe file: false if the system says they are different filesystems
+fn renames(src: &Path, output: &Path) -> io::Result<bool> {
+    let from = src.join(RENAME_PROBE);
+    let to = output.join(RENAME_PROBE);
+    try!(OpenOptions::new().write(true).create_new(true).open(&from));
+    if to.exists() {
+        let _ = fs::remove_file(&from);
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                  format!("{:?} is in the way, remove it", to)));
+    }
+    match fs::rename(&from, &to) {
+        Ok(()) => {
+            let _ = fs::remove_file(&to);
+            Ok(true)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&from);
+            if e.raw_os_error() == Some(CROSS_DEVICE) {
+                Ok(false)
+            } else {
+                Err(e)
+            }
+        }
+    }
+}
+
+// whether `dir` tells names apart by case: the probe file doesn't show up
+// under its name in upper case
+fn case_sensitive(dir: &Path) -> bool {
+    !dir.join(PROBE.to_uppercase()).exists()
+}
+
+#[cfg(target_os = "linux")]
+fn xattrs(path: &Path) -> Option<bool> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(p) => p,
+        Err(_) => return None,
+    };
+    let name = CString::new("user.organize-by-mtime").unwrap();
+    let value = b"1";
+    let set = unsafe {
+        libc::setxattr(c_path.as_ptr(),
+                       name.as_ptr(),
+                       value.as_ptr() as *const libc::c_void,
+                       value.len(),
+                       0)
+    };
+    Some(set == 0)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn xattrs(_: &Path) -> Option<bool> {
+    None
+}
+
+// how finely mtimes are kept, found by setting an odd one and reading it back
+fn resolution(path: &Path) -> String {
+    // odd seconds and nanoseconds, as in 2001-09-09T01:46:41.123456789
+    let secs = 1000000001;
+    let set = FileTime::from_seconds_since_1970(secs, 123456789);
+    if let Err(e) = filetime::set_file_times(path, set, set) {
+        return format!("can't be set: {}", e);
+    }
+    let got = match fs::metadata(path) {
+        Ok(md) => FileTime::from_last_modification_time(&md),
+        Err(e) => return format!("can't be read: {}", e),
+    };
+    if unix_seconds(&got) != secs as i64 {
+        return "kept to 2 s (FAT); dates may shift by a second".to_string();
+    }
+    match got.nanoseconds() {
+        123456789 => "kept to 1 ns".to_string(),
+        123456700 => "kept to 100 ns".to_string(),
+        123456000 => "kept to 1 µs".to_string(),
+        123000000 => "kept to 1 ms".to_string(),
+        0 => "kept to 1 s".to_string(),
+        n => format!("kept as .{:09} for .123456789", n),
+    }
+}
@@ -0,0 +1,105 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// When mail was sent: the Date header of .eml files, or the client submit
+// time property of Outlook .msg files.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use chrono::*;
+
+use dates::find;
+use office;
+
+
+// PR_CLIENT_SUBMIT_TIME and PR_MESSAGE_DELIVERY_TIME, as the tags of their
+// entries in the .msg property stream
+const SUBMIT_TIME: &'static [u8] = b"\x40\x00\x39\x00";
+const DELIVERY_TIME: &'static [u8] = b"\x40\x00\x06\x0E";
+
+pub fn date(path: &Path) -> Option<NaiveDateTime> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return None,
+    };
+    let mut magic = [0; 8];
+    if file.read_exact(&mut magic).is_ok() && &magic[..] == office::OLE_MAGIC {
+        let mut data = magic.to_vec();
+        if file.read_to_end(&mut data).is_err() {
+            return None;
+        }
+        msg_date(&data)
+    } else {
+        File::open(path).ok().and_then(|f| eml_date(BufReader::new(f)))
+    }
+}
+
+// the time as written by the sender, without converting its offset
+fn eml_date<R: BufRead>(reader: R) -> Option<NaiveDateTime> {
+    let mut date: Option<String> = None;
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return None,
+        };
+        // headers end at the first empty line
+        if line.trim().is_empty() {
+            break;
+        }
+        if line.starts_with(' ') || line.starts_with('\t') {
+            // a folded continuation of the previous header
+            if let Some(ref mut date) = date {
+                date.push(' ');
+                date.push_str(line.trim());
+            }
+            continue;
+        }
+        if date.is_some() {
+            break;
+        }
+        if line.get(..5).map_or(false, |h| h.eq_ignore_ascii_case("date:")) {
+            date = Some(line[5..].trim().to_string());
+        }
+    }
+    // drop comments like "(PDT)", which the parser doesn't take
+    let date = match date {
+        Some(date) => date.split('(').next().unwrap_or("").trim().to_string(),
+        None => return None,
+    };
+    DateTime::parse_from_rfc2822(&date).ok().map(|d| d.naive_local())
+}
+
+fn msg_date(data: &[u8]) -> Option<NaiveDateTime> {
+    // each property entry is its tag, flags and an 8 byte value
+    for tag in &[SUBMIT_TIME, DELIVERY_TIME] {
+        if let Some(i) = find(data, tag) {
+            if let Some(value) = data.get(i + 8..i + 16) {
+                let ft = value.iter().rev().fold(0u64, |ft, &b| ft << 8 | b as u64);
+                return office::from_filetime(ft);
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,416 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Executors carry out the moves decided by the scanning/batching logic. The
+// local executor renames files on disk, the copying one (--mirror) leaves
+// them where they are; the verifying executor copies them
+// and only removes the source once the copy reads back the same; the plugin
+// executor hands each move to an external program over a line-based protocol
+// (see README).
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Stdio};
+
+use chrono::NaiveDateTime;
+use filetime::{self, FileTime};
+use zstd;
+
+use hash::{self, Hasher};
+
+use shell_command;
+
+
+#[derive(Clone, Debug)]
+pub struct MoveOp {
+    pub src: PathBuf,
+    pub dst: PathBuf,
+    // the date the batch was filed under
+    pub date: NaiveDateTime,
+    // store zstd-compressed at dst instead of moving as is
+    pub compress: bool,
+    // the --on-conflict action taken because dst was taken
+    pub conflict: Option<&'static str>,
+}
+
+pub trait Executor {
+    fn execute(&mut self, op: &MoveOp, force: bool) -> io::Result<()>;
+
+    // where the file ends up, for printing
+    fn describe(&self, op: &MoveOp) -> String {
+        format!("{:?}", op.dst)
+    }
+
+    // called once after the last move
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+
+pub struct LocalExecutor;
+
+impl Executor for LocalExecutor {
+    fn execute(&mut self, op: &MoveOp, force: bool) -> io::Result<()> {
+        if op.compress {
+            compress_single_file(&op.src, &op.dst, force).and_then(|_| fs::remove_file(&op.src))
+        } else {
+            move_single_file(&op.src, &op.dst, force)
+        }
+    }
+}
+
+// --mirror: copies the files, with their mtime and permissions, leaving the
+// sources in place
+pub struct CopyExecutor;
+
+impl Executor for CopyExecutor {
+    fn execute(&mut self, op: &MoveOp, force: bool) -> io::Result<()> {
+        if op.compress {
+            return compress_single_file(&op.src, &op.dst, force);
+        }
+        if let Some(dstparent) = op.dst.parent() {
+            try!(fs::create_dir_all(dstparent));
+        }
+        if !force && op.dst.exists() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                      "destination file already exists"));
+        }
+        copy_single_file(&op.src, &op.dst)
+    }
+}
+
+// --remove-source-after-verify: copies even within a filesystem, reads the
+// copy back and compares its checksum with that of the source as it was
+// copied, and removes the source only if they match. A copy that doesn't is
+// removed again, leaving the source as it was. Checksums are made with the
+// algorithm given.
+pub struct VerifyingExecutor(pub hash::Algorithm);
+
+impl Executor for VerifyingExecutor {
+    fn execute(&mut self, op: &MoveOp, force: bool) -> io::Result<()> {
+        if let Some(dstparent) = op.dst.parent() {
+            try!(fs::create_dir_all(dstparent));
+        }
+        if !force && op.dst.exists() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                      "destination file already exists"));
+        }
+        let md = try!(fs::metadata(&op.src));
+        let mtime = FileTime::from_last_modification_time(&md);
+        let algorithm = self.0;
+        let mut input = Checksummed {
+            inner: try!(File::open(&op.src)),
+            hasher: Hasher::new(algorithm),
+        };
+        try!(write_atomically(&op.dst, mtime, |mut output| {
+            if op.compress {
+                try!(zstd::stream::copy_encode(&mut input, &mut output, 0));
+            } else {
+                try!(io::copy(&mut input, &mut output));
+            }
+            try!(output.sync_all());
+            output.set_permissions(md.permissions())
+        }));
+        let expected = input.hasher.finish();
+        let copied = if op.compress {
+            File::open(&op.dst)
+                .and_then(zstd::Decoder::new)
+                .and_then(|decoder| hash::reader(decoder, algorithm))
+        } else {
+            hash::file(&op.dst, algorithm)
+        };
+        match copied {
+            Ok(checksum) if checksum == expected => fs::remove_file(&op.src),
+            Ok(_) => {
+                let _ = fs::remove_file(&op.dst);
+                Err(io::Error::new(io::ErrorKind::InvalidData,
+                                   "the copy differs from the source, which was kept"))
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&op.dst);
+                Err(e)
+            }
+        }
+    }
+}
+
+// checksums what is read through it
+struct Checksummed<R> {
+    inner: R,
+    hasher: Hasher,
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = try!(self.inner.read(buf));
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+// EXDEV, or ERROR_NOT_SAME_DEVICE on Windows, where renames through a
+// junction to another volume fail with it too
+pub const CROSS_DEVICE: i32 = if cfg!(windows) { 17 } else { 18 };
+
+// ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION: on Windows, a file held
+// open by another process without FILE_SHARE_DELETE can't be renamed
+pub fn in_use(e: &io::Error) -> bool {
+    cfg!(windows) && (e.raw_os_error() == Some(32) || e.raw_os_error() == Some(33))
+}
+
+const TEMP_SUFFIX: &'static str = ".organize-tmp";
+
+// the temporary files of `write_atomically`, which a crash may leave behind
+// and the scans pass over
+pub fn is_temp(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .map_or(false, |n| n.starts_with('.') && n.ends_with(TEMP_SUFFIX))
+}
+
+// Writes `dst` through `write` to a hidden temporary file next to it, which
+// is flushed to disk and renamed into place with the mtime `mtime` once
+// complete, so that a crash never leaves a half-written file that looks
+// organized, nor one that is lost with the source already removed.
+pub fn write_atomically<F>(dst: &Path, mtime: FileTime, write: F) -> io::Result<()>
+    where F: FnOnce(File) -> io::Result<()>
+{
+    let name = dst.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let tmp = dst.with_file_name(format!(".{}{}", name, TEMP_SUFFIX));
+    let result = File::create(&tmp)
+                     .and_then(|file| file.try_clone().and_then(|copy| write(file).map(|_| copy)))
+                     .and_then(|copy| {
+                         try!(filetime::set_file_times(&tmp, mtime, mtime));
+                         copy.sync_all()
+                     })
+                     .and_then(|_| fs::rename(&tmp, dst))
+                     .and_then(|_| sync_dir(dst.parent().unwrap_or(Path::new(""))));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+// --fsync: flushes `dst` to disk, then the folders from its own up to
+// `output`, which may have been created for it, and last the folder of `src`
+// (if any), so that a power loss can't lose the file to either side
+pub fn sync_move(src: Option<&Path>, dst: &Path, output: &Path) -> io::Result<()> {
+    try!(try!(File::open(dst)).sync_all());
+    let mut dir = dst.parent();
+    while let Some(d) = dir {
+        try!(sync_dir(d));
+        if !d.starts_with(output) || d == output {
+            break;
+        }
+        dir = d.parent();
+    }
+    match src.and_then(|src| src.parent()) {
+        Some(d) => sync_dir(d),
+        None => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    try!(File::open(dir)).sync_all()
+}
+
+// folders can't be opened to sync them on Windows
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn move_single_file(src: &Path, dst: &Path, force: bool) -> io::Result<()> {
+    if let Some(dstparent) = dst.parent() {
+        try!(fs::create_dir_all(dstparent));
+        if !force && dst.exists() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                      "destination file already exists"));
+        }
+        match fs::rename(src, dst) {
+            // another filesystem: copy, then remove the source
+            Err(ref e) if e.raw_os_error() == Some(CROSS_DEVICE) => {
+                try!(copy_single_file(src, dst));
+                fs::remove_file(src)
+            }
+            result => result,
+        }
+    } else {
+        Err(io::Error::new(io::ErrorKind::Other, "parent path impossible to compute"))
+    }
+}
+
+fn copy_single_file(src: &Path, dst: &Path) -> io::Result<()> {
+    let md = try!(fs::metadata(src));
+    let mtime = FileTime::from_last_modification_time(&md);
+    write_atomically(dst, mtime, |mut output| {
+        let mut input = try!(File::open(src));
+        try!(io::copy(&mut input, &mut output));
+        output.set_permissions(md.permissions())
+    })
+}
+
+// writes a zstd-compressed copy of src to dst with the same mtime
+fn compress_single_file(src: &Path, dst: &Path, force: bool) -> io::Result<()> {
+    if let Some(dstparent) = dst.parent() {
+        try!(fs::create_dir_all(dstparent));
+    }
+    if !force && dst.exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                  "destination file already exists"));
+    }
+    let md = try!(fs::metadata(src));
+    let mtime = FileTime::from_last_modification_time(&md);
+    write_atomically(dst, mtime, |output| {
+        let input = try!(File::open(src));
+        zstd::stream::copy_encode(input, output, 0)
+    })
+}
+
+
+// Protocol, one line per message, fields separated by tabs:
+//
+//   we send:       "move" | "overwrite", SRC, DST, DATE
+//   plugin sends:  "ok"  or  "error", MESSAGE
+//
+// DATE is the date the file was filed under, as 2016-05-14T09:30:00. Moves
+// are never compressed: --executor can't be combined with
+// --compress-older-than.
+//
+// The plugin's stdin is closed after the last move; a non-zero exit status is
+// reported as an error.
+pub struct PluginExecutor {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl PluginExecutor {
+    pub fn spawn(cmd: &str) -> io::Result<PluginExecutor> {
+        let mut child = try!(shell_command(cmd).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn());
+        let stdin = child.stdin.take();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(PluginExecutor {
+            child: child,
+            stdin: stdin,
+            stdout: stdout,
+        })
+    }
+}
+
+fn protocol_field(path: &Path) -> io::Result<String> {
+    let s = path.to_string_lossy().into_owned();
+    if s.contains('\t') || s.contains('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "path contains a tab or newline"));
+    }
+    Ok(s)
+}
+
+impl Executor for PluginExecutor {
+    fn execute(&mut self, op: &MoveOp, force: bool) -> io::Result<()> {
+        let line = format!("{}\t{}\t{}\t{}\n",
+                           if force { "overwrite" } else { "move" },
+                           try!(protocol_field(&op.src)),
+                           try!(protocol_field(&op.dst)),
+                           op.date.format("%Y-%m-%dT%H:%M:%S"));
+        {
+            let stdin = try!(self.stdin.as_mut().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::BrokenPipe, "plugin already finished")
+            }));
+            try!(stdin.write_all(line.as_bytes()));
+            try!(stdin.flush());
+        }
+        let mut reply = String::new();
+        if try!(self.stdout.read_line(&mut reply)) == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "plugin closed its output"));
+        }
+        let reply = reply.trim_end_matches(|c| c == '\n' || c == '\r');
+        let mut fields = reply.splitn(2, '\t');
+        match (fields.next(), fields.next()) {
+            (Some("ok"), _) => Ok(()),
+            (Some("error"), msg) => {
+                Err(io::Error::new(io::ErrorKind::Other, msg.unwrap_or("plugin error").to_string()))
+            }
+            _ => {
+                Err(io::Error::new(io::ErrorKind::InvalidData,
+                                   format!("unexpected plugin reply {:?}", reply)))
+            }
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        // closing stdin tells the plugin there is nothing more to do
+        self.stdin.take();
+        let status = try!(self.child.wait());
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::Other, format!("plugin failed: {}", status)))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+
+    use filetime::{self, FileTime};
+
+    use api::Error;
+    use testing::{organize, scratch};
+
+    #[test]
+    fn plugins_get_the_date() {
+        let dir = scratch("executor-plugin");
+        let src = dir.join("src");
+        fs::create_dir_all(&src).unwrap();
+        File::create(src.join("a.jpg")).unwrap();
+        // 2015-06-01 12:00 UTC
+        let mtime = FileTime::from_seconds_since_1970(1433160000, 0);
+        filetime::set_file_times(src.join("a.jpg"), mtime, mtime).unwrap();
+        let log = dir.join("plugin.log");
+        let plugin = format!("while read l; do printf '%s\\n' \"$l\" >> '{}'; echo ok; done",
+                             log.display());
+        let out = dir.join("out");
+        let (src_dir, out) = (src.to_str().unwrap(), out.to_str().unwrap());
+        let executor = format!("--executor={}", plugin);
+        let argv = ["organize", &executor[..], "--strip-prefix", src_dir, "-O", out, src_dir];
+        assert_eq!(organize(&argv), Ok(0));
+        let sent = fs::read_to_string(&log).unwrap();
+        let fields: Vec<_> = sent.trim_end().split('\t').collect();
+        assert_eq!(fields.len(), 4, "{:?}", sent);
+        assert_eq!(fields[..2], ["move", src.join("a.jpg").to_str().unwrap()]);
+        assert!(fields[3].starts_with("2015-06-01T"), "{:?}", sent);
+
+        // it would store them as is
+        let argv = ["organize", &executor[..], "--compress-older-than=1d", "-O", out, src_dir];
+        assert_eq!(organize(&argv), Err(Error::Exit(2)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,401 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// When a photo was taken, from its EXIF data: DateTimeOriginal (or DateTime)
+// and, when the camera recorded it, the matching OffsetTime* tag, which is
+// used to convert the date to UTC like mtimes are. Also where it was taken,
+// from the GPS tags, and with which camera.
+//
+// The EXIF block is found in JPEGs (APP1 segment), WebP (EXIF chunk) and
+// HEIF-based formats such as HEIC and AVIF (the Exif item of the meta box).
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use chrono::*;
+
+
+// try! for the Options of parsing fields that may be cut short
+macro_rules! opt(
+    ($e:expr) => (
+        match $e {
+            Some(v) => v,
+            None => return None,
+        }
+    )
+);
+
+const MAKE: u16 = 0x010F;
+const MODEL: u16 = 0x0110;
+const DATE_TIME: u16 = 0x0132;
+const EXIF_IFD: u16 = 0x8769;
+const DATE_TIME_ORIGINAL: u16 = 0x9003;
+const OFFSET_TIME: u16 = 0x9010;
+const OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+const GPS_IFD: u16 = 0x8825;
+const GPS_LATITUDE_REF: u16 = 0x0001;
+const GPS_LATITUDE: u16 = 0x0002;
+const GPS_LONGITUDE_REF: u16 = 0x0003;
+const GPS_LONGITUDE: u16 = 0x0004;
+
+pub fn date(path: &Path) -> Option<NaiveDateTime> {
+    tiff_block(path).and_then(|t| tiff_date(&t))
+}
+
+// latitude and longitude in degrees, negative to the south and west
+pub fn gps(path: &Path) -> Option<(f64, f64)> {
+    let data = opt!(tiff_block(path));
+    let tiff = Tiff::new(&data);
+    let ifd0 = opt!(tiff.ifd0());
+    let gps = opt!(tiff.entry(ifd0, GPS_IFD)).0 as usize;
+    let coordinate = |value_tag, ref_tag, negative| {
+        let dms = opt!(tiff.rationals(gps, value_tag, 3));
+        let degrees = dms[0] + dms[1] / 60.0 + dms[2] / 3600.0;
+        match tiff.text(gps, ref_tag) {
+            Some(ref r) if r == negative => Some(-degrees),
+            _ => Some(degrees),
+        }
+    };
+    match (coordinate(GPS_LATITUDE, GPS_LATITUDE_REF, "S"),
+           coordinate(GPS_LONGITUDE, GPS_LONGITUDE_REF, "W")) {
+        (Some(lat), Some(lon)) => Some((lat, lon)),
+        _ => None,
+    }
+}
+
+// The camera model without spaces, e.g. CanonEOSR6 or iPhone13; models
+// mostly include the make already, so it is only used without a model.
+// The name comes from the file, so it is kept to a single plain folder name:
+// no separators, drive colons or control characters, and not `.` or `..`.
+pub fn camera(path: &Path) -> Option<String> {
+    let data = opt!(tiff_block(path));
+    let tiff = Tiff::new(&data);
+    let ifd0 = opt!(tiff.ifd0());
+    tiff.text(ifd0, MODEL)
+        .or_else(|| tiff.text(ifd0, MAKE))
+        .map(|name| {
+            name.chars()
+                .filter(|&c| !c.is_whitespace() && !c.is_control())
+                .filter(|&c| c != '/' && c != '\\' && c != ':')
+                .collect::<String>()
+        })
+        .filter(|name| name.chars().any(|c| c != '.'))
+}
+
+// the TIFF structure holding the EXIF tags
+fn tiff_block(path: &Path) -> Option<Vec<u8>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return None,
+    };
+    let mut magic = [0; 12];
+    if file.read_exact(&mut magic).is_err() {
+        return None;
+    }
+    if magic[..2] == [0xFF, 0xD8] {
+        jpeg(&mut file)
+    } else if &magic[..4] == b"RIFF" && &magic[8..12] == b"WEBP" {
+        webp(&mut file)
+    } else if &magic[4..8] == b"ftyp" {
+        heif(&mut file)
+    } else {
+        None
+    }
+}
+
+fn read_at(file: &mut File, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    try!(file.seek(SeekFrom::Start(offset)));
+    let mut buf = vec![];
+    try!(file.take(len as u64).read_to_end(&mut buf));
+    if buf.len() < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated"));
+    }
+    Ok(buf)
+}
+
+fn be(b: &[u8]) -> u64 {
+    b.iter().fold(0, |n, &b| n << 8 | b as u64)
+}
+
+// some writers keep the "Exif\0\0" APP1 prefix in other containers too
+fn strip_exif_prefix(data: Vec<u8>) -> Vec<u8> {
+    if data.starts_with(b"Exif\0\0") {
+        data[6..].to_vec()
+    } else {
+        data
+    }
+}
+
+fn jpeg(file: &mut File) -> Option<Vec<u8>> {
+    let mut at = 2;
+    loop {
+        let header = match read_at(file, at, 4) {
+            Ok(header) => header,
+            Err(_) => return None,
+        };
+        // the image data follows the start of scan; no metadata after it
+        if header[0] != 0xFF || header[1] == 0xDA {
+            return None;
+        }
+        let len = be(&header[2..4]);
+        if header[1] == 0xE1 {
+            if let Ok(data) = read_at(file, at + 4, len.saturating_sub(2) as usize) {
+                if data.starts_with(b"Exif\0\0") {
+                    return Some(strip_exif_prefix(data));
+                }
+            }
+        }
+        at += 2 + len;
+    }
+}
+
+fn webp(file: &mut File) -> Option<Vec<u8>> {
+    let mut at = 12;
+    loop {
+        let header = match read_at(file, at, 8) {
+            Ok(header) => header,
+            Err(_) => return None,
+        };
+        let len = header[4] as u64 | (header[5] as u64) << 8 | (header[6] as u64) << 16 |
+                  (header[7] as u64) << 24;
+        if &header[..4] == b"EXIF" {
+            return read_at(file, at + 8, len as usize).ok().map(strip_exif_prefix);
+        }
+        // chunks are padded to an even size
+        at += 8 + len + (len & 1);
+    }
+}
+
+// the children of an ISO BMFF box's payload, as (type, payload)
+fn boxes(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut found = vec![];
+    let mut at = 0;
+    while at + 8 <= data.len() {
+        let size = be(&data[at..at + 4]) as usize;
+        if size < 8 || at + size > data.len() {
+            break;
+        }
+        found.push((&data[at + 4..at + 8], &data[at + 8..at + size]));
+        at += size;
+    }
+    found
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8]) -> Option<&'a [u8]> {
+    boxes(data).into_iter().find(|&(k, _)| k == kind).map(|(_, payload)| payload)
+}
+
+// reads `n` bytes at `*at` as a big-endian number and advances past them
+fn field(data: &[u8], at: &mut usize, n: usize) -> Option<u64> {
+    let value = data.get(*at..*at + n).map(be);
+    *at += n;
+    value
+}
+
+// the id of the item of type Exif, from the item info box
+fn exif_item_id(iinf: &[u8]) -> Option<u64> {
+    // skip the entry count
+    let at = if iinf.get(0) == Some(&0) { 6 } else { 8 };
+    for (kind, infe) in boxes(opt!(iinf.get(at..))) {
+        if kind != b"infe" || infe.len() < 4 || infe[0] < 2 {
+            continue;
+        }
+        let mut at = 4;
+        let id = if infe[0] == 2 { field(infe, &mut at, 2) } else { field(infe, &mut at, 4) };
+        at += 2;
+        if infe.get(at..at + 4) == Some(b"Exif") {
+            return id;
+        }
+    }
+    None
+}
+
+// the file offset and length of the first extent of item `id`, from the item
+// location box
+fn item_location(iloc: &[u8], id: u64) -> Option<(u64, u64)> {
+    let version = *opt!(iloc.get(0));
+    let mut at = 4;
+    let sizes = opt!(field(iloc, &mut at, 2));
+    let (offset_size, length_size) = ((sizes >> 12) as usize, (sizes >> 8 & 0xF) as usize);
+    let (base_size, index_size) = ((sizes >> 4 & 0xF) as usize, (sizes & 0xF) as usize);
+    let count = opt!(field(iloc, &mut at, if version < 2 { 2 } else { 4 }));
+    for _ in 0..count {
+        let item = opt!(field(iloc, &mut at, if version < 2 { 2 } else { 4 }));
+        if version > 0 {
+            // construction method; only offsets into the file are handled
+            let method = opt!(field(iloc, &mut at, 2)) & 0xF;
+            if item == id && method != 0 {
+                return None;
+            }
+        }
+        at += 2;
+        let base = opt!(field(iloc, &mut at, base_size));
+        let extents = opt!(field(iloc, &mut at, 2));
+        let mut first = None;
+        for _ in 0..extents {
+            if version > 0 {
+                at += index_size;
+            }
+            let offset = opt!(field(iloc, &mut at, offset_size));
+            let length = opt!(field(iloc, &mut at, length_size));
+            first = first.or(Some((base + offset, length)));
+        }
+        if item == id {
+            return first;
+        }
+    }
+    None
+}
+
+fn heif(file: &mut File) -> Option<Vec<u8>> {
+    // the meta box is near the start, before the image data
+    let mut head = vec![];
+    if file.seek(SeekFrom::Start(0)).is_err() ||
+       (&mut *file).take(1024 * 1024).read_to_end(&mut head).is_err() {
+        return None;
+    }
+    let meta = match child(&head, b"meta") {
+        Some(meta) if meta.len() > 4 => &meta[4..],
+        _ => return None,
+    };
+    let id = match child(meta, b"iinf").and_then(exif_item_id) {
+        Some(id) => id,
+        None => return None,
+    };
+    let (offset, length) = match child(meta, b"iloc").and_then(|iloc| item_location(iloc, id)) {
+        Some(location) => location,
+        None => return None,
+    };
+    // the item starts with the offset of the TIFF header after this field
+    let data = match read_at(file, offset, length as usize) {
+        Ok(ref data) if data.len() >= 4 => data.clone(),
+        _ => return None,
+    };
+    let skip = 4 + be(&data[..4]) as usize;
+    data.get(skip..).map(|tiff| tiff.to_vec())
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Tiff<'a> {
+        Tiff {
+            data: data,
+            little_endian: data.starts_with(b"II"),
+        }
+    }
+
+    fn ifd0(&self) -> Option<usize> {
+        self.num(4, 4).map(|offset| offset as usize)
+    }
+
+    fn num(&self, at: usize, n: usize) -> Option<u64> {
+        self.data.get(at..at + n).map(|b| {
+            if self.little_endian {
+                b.iter().rev().fold(0, |n, &b| n << 8 | b as u64)
+            } else {
+                be(b)
+            }
+        })
+    }
+
+    // the value of `tag` in the IFD at `ifd`: the offset it points to, or
+    // for ASCII tags the text
+    fn entry(&self, ifd: usize, tag: u16) -> Option<(u64, Option<String>)> {
+        let count = match self.num(ifd, 2) {
+            Some(count) => count as usize,
+            None => return None,
+        };
+        for i in 0..count {
+            let entry = ifd + 2 + i * 12;
+            if self.num(entry, 2) != Some(tag as u64) {
+                continue;
+            }
+            let kind = self.num(entry + 2, 2);
+            let len = self.num(entry + 4, 4).unwrap_or(0) as usize;
+            let value = self.num(entry + 8, 4).unwrap_or(0);
+            if kind != Some(2) {
+                return Some((value, None));
+            }
+            // ASCII values of up to 4 bytes are stored in the entry itself
+            let start = if len <= 4 { entry + 8 } else { value as usize };
+            let text = self.data
+                           .get(start..start + len)
+                           .map(|b| String::from_utf8_lossy(b).trim_end_matches('\0').to_string());
+            return Some((value, text));
+        }
+        None
+    }
+
+    fn text(&self, ifd: usize, tag: u16) -> Option<String> {
+        self.entry(ifd, tag).and_then(|(_, text)| text)
+    }
+
+    // `count` unsigned rationals, which are always stored at an offset
+    fn rationals(&self, ifd: usize, tag: u16, count: usize) -> Option<Vec<f64>> {
+        let at = opt!(self.entry(ifd, tag)).0 as usize;
+        let mut values = vec![];
+        for i in 0..count {
+            let numerator = opt!(self.num(at + i * 8, 4));
+            let denominator = opt!(self.num(at + i * 8 + 4, 4));
+            if denominator == 0 {
+                return None;
+            }
+            values.push(numerator as f64 / denominator as f64);
+        }
+        Some(values)
+    }
+}
+
+fn tiff_date(data: &[u8]) -> Option<NaiveDateTime> {
+    let tiff = Tiff::new(data);
+    let ifd0 = opt!(tiff.ifd0());
+    let exif_ifd = tiff.entry(ifd0, EXIF_IFD).map(|(offset, _)| offset as usize);
+    let (date, offset) = match exif_ifd.and_then(|ifd| tiff.text(ifd, DATE_TIME_ORIGINAL)) {
+        Some(date) => (date, exif_ifd.and_then(|ifd| tiff.text(ifd, OFFSET_TIME_ORIGINAL))),
+        None => {
+            match tiff.text(ifd0, DATE_TIME) {
+                Some(date) => (date, exif_ifd.and_then(|ifd| tiff.text(ifd, OFFSET_TIME))),
+                None => return None,
+            }
+        }
+    };
+    let date = match NaiveDateTime::parse_from_str(date.trim(), "%Y:%m:%d %H:%M:%S") {
+        Ok(date) => date,
+        Err(_) => return None,
+    };
+    // +HH:MM; other text, or bytes of a wider character, are no offset
+    match offset.as_ref().map(|o| o.trim()).filter(|o| o.len() == 6 && o.is_ascii()) {
+        Some(o) => {
+            let sign = if o.starts_with('-') { -1 } else { 1 };
+            let hours: i64 = o[1..3].parse().unwrap_or(0);
+            let minutes: i64 = o[4..6].parse().unwrap_or(0);
+            Some(date - Duration::minutes(sign * (hours * 60 + minutes)))
+        }
+        None => Some(date),
+    }
+}
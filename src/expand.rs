@@ -0,0 +1,367 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// --expand-archives: extract the members of zip/tar files found in the source
+// into the organized tree, each dated by its own stored mtime rather than by
+// the mtime of the archive that contains it.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use chrono::*;
+use flate2;
+use tar;
+use zip;
+use zstd;
+
+use executor::{write_atomically, MoveOp};
+use hash::{self, Checksum};
+use {compresses, destination, finish_move, fit_path, interrupted, merge_target, overwrites,
+     plan_move, resolve_conflict, timestamp_date, to_filetime, zst_path, Args, Context,
+     Resolution};
+
+
+#[derive(Clone, Copy, Debug)]
+enum Kind {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+}
+
+// recognized archive suffixes, longest first
+const SUFFIXES: &'static [(&'static str, Kind)] = &[(".tar.gz", Kind::TarGz),
+                                                     (".tar.zst", Kind::TarZst),
+                                                     (".tgz", Kind::TarGz),
+                                                     (".tar", Kind::Tar),
+                                                     (".zip", Kind::Zip)];
+
+fn kind_of(path: &Path) -> Option<(Kind, usize)> {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+    SUFFIXES.iter()
+            .find(|&&(suffix, _)| name.ends_with(suffix) && name.len() > suffix.len())
+            .map(|&(suffix, kind)| (kind, suffix.len()))
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    kind_of(path).is_some()
+}
+
+// archive members may not escape the directory they are extracted to
+fn safe_member_path(name: &Path) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for c in name.components() {
+        match c {
+            Component::Normal(part) => out.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    if out.as_os_str().is_empty() {
+        None
+    } else {
+        Some(out)
+    }
+}
+
+fn zip_datetime(t: zip::DateTime) -> Option<NaiveDateTime> {
+    NaiveDate::from_ymd_opt(t.year() as i32, t.month() as u32, t.day() as u32)
+        .and_then(|d| d.and_hms_opt(t.hour() as u32, t.minute() as u32, t.second() as u32))
+}
+
+fn for_each_member<F>(archive: &Path, kind: Kind, mut f: F) -> io::Result<()>
+    where F: FnMut(&Path, NaiveDateTime, u64, &mut dyn Read)
+{
+    let file = try!(File::open(archive));
+    match kind {
+        Kind::Zip => {
+            let mut zip = try!(zip::ZipArchive::new(file)
+                                   .map_err(|e| io::Error::new(io::ErrorKind::InvalidData,
+                                                               e.to_string())));
+            for i in 0..zip.len() {
+                let mut member = try!(zip.by_index(i)
+                                         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData,
+                                                                     e.to_string())));
+                if !member.is_file() {
+                    continue;
+                }
+                let name = PathBuf::from(member.name());
+                let mtime = member.last_modified()
+                                  .and_then(zip_datetime)
+                                  .unwrap_or_else(|| NaiveDateTime::from_timestamp(0, 0));
+                let size = member.size();
+                f(&name, mtime, size, &mut member);
+            }
+            Ok(())
+        }
+        Kind::Tar => tar_members(tar::Archive::new(file), f),
+        Kind::TarGz => tar_members(tar::Archive::new(flate2::read::GzDecoder::new(file)), f),
+        Kind::TarZst => tar_members(tar::Archive::new(try!(zstd::Decoder::new(file))), f),
+    }
+}
+
+fn tar_members<R, F>(mut archive: tar::Archive<R>, mut f: F) -> io::Result<()>
+    where R: Read,
+          F: FnMut(&Path, NaiveDateTime, u64, &mut dyn Read)
+{
+    for member in try!(archive.entries()) {
+        let mut member = try!(member);
+        if !member.header().entry_type().is_file() {
+            continue;
+        }
+        let name = try!(member.path()).into_owned();
+        let mtime = timestamp_date(try!(member.header().mtime()) as i64, 0);
+        let size = try!(member.header().size());
+        f(&name, mtime, size, &mut member);
+    }
+    Ok(())
+}
+
+// writes the member read from `reader` to `op.dst`, compressed if `op.compress`
+fn extract_member(reader: &mut dyn Read, op: &MoveOp, force: bool) -> io::Result<()> {
+    let dst = &op.dst;
+    if let Some(parent) = dst.parent() {
+        try!(fs::create_dir_all(parent));
+    }
+    if !force && dst.exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                  "destination file already exists"));
+    }
+    write_atomically(dst, to_filetime(&op.date), |mut out| {
+        if op.compress {
+            zstd::stream::copy_encode(reader, &mut out, 0)
+        } else {
+            io::copy(reader, &mut out).map(|_| ())
+        }
+    })
+}
+
+// that of the member's content, as the manifest records for moved files
+fn extracted_checksum(op: &MoveOp, algorithm: hash::Algorithm) -> io::Result<Checksum> {
+    if op.compress {
+        File::open(&op.dst)
+            .and_then(zstd::Decoder::new)
+            .and_then(|decoder| hash::reader(decoder, algorithm))
+    } else {
+        hash::file(&op.dst, algorithm)
+    }
+}
+
+// `path` without the archive suffix of `len` bytes
+fn strip_suffix(path: &Path, len: usize) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    path.with_file_name(&name[..name.len() - len])
+}
+
+// Extracts the members of `archive` accepted by `wanted` where they would go
+// if the archive were a folder of that name, `rel` being the archive's own
+// path below the layout folder. Each is dated by its own mtime, and goes
+// through the layout, --merge, --long-paths and --on-conflict, is planned and
+// is finished as any moved file. `wanted` is given the path each member would
+// have below the source, `source_rel` being the archive's. The archive is
+// removed once every member was extracted; with a member left out or failing
+// it is kept. Returns the error count.
+pub fn expand_archive(archive: &Path,
+                      source_rel: &Path,
+                      rel: &Path,
+                      args: &Args,
+                      ctx: &mut Context,
+                      wanted: &dyn Fn(&Path) -> bool)
+                      -> isize {
+    let (kind, suffix_len) = match kind_of(archive) {
+        Some(k) => k,
+        None => return 0,
+    };
+    let rel = strip_suffix(rel, suffix_len);
+    let source_rel = strip_suffix(source_rel, suffix_len);
+
+    let mut errors: isize = 0;
+    let mut skipped = 0;
+    let result = for_each_member(archive, kind, |name, mtime, size, reader| {
+        let member = match safe_member_path(name) {
+            Some(m) => m,
+            None => {
+                log_error!("Error: {:?}: unsafe member path {:?}", archive, name);
+                errors += 1;
+                return;
+            }
+        };
+        if !wanted(&source_rel.join(&member)) || interrupted() {
+            skipped += 1;
+            return;
+        }
+        let src = archive.join(&member);
+        let member_rel = rel.join(&member);
+        let fin = match destination(&src, &member_rel, &mtime, args, ctx) {
+            Ok(fin) => merge_target(&src, fin, &member_rel, args, ctx),
+            Err(e) => {
+                log_error!("Error: dest script: {:?}: {}", src, e);
+                errors += 1;
+                return;
+            }
+        };
+        let compress = compresses(&mtime, ctx);
+        let fin = if compress { zst_path(fin) } else { fin };
+        let fin = match fit_path(fin, args, ctx) {
+            Ok((fin, Some(long))) => {
+                log_warn!("{:?}: path too long, shortened to {:?}", long, fin);
+                fin
+            }
+            Ok((fin, None)) => fin,
+            Err(()) => {
+                errors += 1;
+                return;
+            }
+        };
+        // --on-conflict as for any other file
+        let (dst, conflict) = match resolve_conflict(&src, Some(mtime), fin, args, ctx) {
+            Resolution::Move(dst, conflict) => (dst, conflict),
+            Resolution::Skip => {
+                *ctx.skipped.entry("conflict").or_insert(0) += 1;
+                skipped += 1;
+                return;
+            }
+            Resolution::Fail => {
+                errors += 1;
+                return;
+            }
+        };
+        ctx.claimed.insert(dst.clone());
+        let op = MoveOp {
+            src: src,
+            dst: dst,
+            date: mtime,
+            compress: compress,
+            conflict: conflict,
+        };
+        let shown = format!("{:?}", op.dst);
+        errors += plan_move(&op, "extract", &shown, size, args, ctx);
+        if args.flag_dry_run {
+            return;
+        }
+        match extract_member(reader, &op, overwrites(&op, args)) {
+            Ok(()) => {
+                let checksum = ctx.manifest
+                                  .as_ref()
+                                  .and_then(|_| extracted_checksum(&op, ctx.hash).ok());
+                errors += finish_move(&op, true, size, checksum.as_ref(), &mtime, args, ctx);
+            }
+            Err(e) => {
+                log_error!("Error: dest: {:?}: {}", op.dst, e);
+                errors += 1;
+            }
+        }
+    });
+    if let Err(e) = result {
+        log_error!("Error: {:?}: {}", archive, e);
+        errors += 1;
+    }
+    if skipped > 0 {
+        log_info!("{:?}: kept, {} members were left out by the patterns or conflicts",
+                  archive,
+                  skipped);
+    }
+    if errors == 0 && skipped == 0 && !args.flag_dry_run {
+        if let Err(e) = fs::remove_file(archive) {
+            log_error!("Error: {:?}: {}", archive, e);
+            errors += 1;
+        }
+    }
+    errors
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::Path;
+
+    use zip;
+
+    use api::Error;
+    use testing::{organize, scratch};
+
+    // members are dated 2015-06-01
+    fn make_zip(path: &Path, members: &[(&str, &[u8])]) {
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        let date = zip::DateTime::from_date_and_time(2015, 6, 1, 12, 0, 0).unwrap();
+        for &(name, content) in members {
+            let options = zip::write::SimpleFileOptions::default().last_modified_time(date);
+            zip.start_file(name, options).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    // the archive would be removed, which these promise not to do
+    #[test]
+    fn refuses_options_that_keep_the_source() {
+        let dir = scratch("expand-refuses");
+        let src = dir.join("src");
+        fs::create_dir_all(&src).unwrap();
+        let archive = src.join("photos.zip");
+        make_zip(&archive, &[("a.jpg", b"a")]);
+        let out = dir.join("out");
+        let (out, src_dir) = (out.to_str().unwrap(), src.to_str().unwrap());
+        let refused = ["--mirror", "--remove-source-after-verify", "--archive=zip",
+                       "--executor=cat"];
+        for opt in &refused {
+            let argv = ["--expand-archives", opt, "-O", out, src_dir];
+            assert_eq!(organize(&argv), Err(Error::Exit(2)), "{}", opt);
+            assert!(archive.is_file(), "{}", opt);
+        }
+    }
+
+    // members go through the layout and are planned and recorded like files
+    #[test]
+    fn members_are_moved_as_files() {
+        let dir = scratch("expand-members");
+        let src = dir.join("src");
+        fs::create_dir_all(&src).unwrap();
+        let archive = src.join("photos.zip");
+        make_zip(&archive, &[("a.jpg", b"a"), ("trip/b.jpg", b"b")]);
+        let (out, plan, manifest) = (dir.join("out"), dir.join("plan.json"), dir.join("manifest"));
+        let argv = ["--expand-archives",
+                    "--layout={year}/{month}",
+                    "--save-plan",
+                    plan.to_str().unwrap(),
+                    "--manifest",
+                    manifest.to_str().unwrap(),
+                    "--strip-prefix",
+                    src.to_str().unwrap(),
+                    "-O",
+                    out.to_str().unwrap(),
+                    src.to_str().unwrap()];
+        assert_eq!(organize(&argv), Ok(0));
+        assert!(out.join("2015/06/photos/a.jpg").is_file());
+        assert!(out.join("2015/06/photos/trip/b.jpg").is_file());
+        assert!(!archive.exists());
+        let planned = fs::read_to_string(&plan).unwrap();
+        assert!(planned.contains("photos/trip/b.jpg"));
+        let recorded = fs::read_to_string(&manifest).unwrap();
+        assert_eq!(recorded.lines().filter(|l| l.contains("\textract\t")).count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// The C API, declared in include/organize_by_mtime.h (made by cbindgen, see
+// cbindgen.toml): the library API for programs that load the cdylib.
+//
+//     const char *argv[] = {"-O", "sorted", "photos"};
+//     char *error = NULL;
+//     OrganizePlan *plan = organize_plan(3, argv, &error);
+//     char *json = organize_plan_json(plan);   // as saved by --save-plan
+//     long failed = organize_execute(plan, 0, "moves.log", &error);
+//     organize_string_free(json);
+//     organize_plan_free(plan);
+//
+// Functions that can fail return NULL or -1 and, if `error` isn't NULL, set
+// it to a message to free with organize_string_free. No panic crosses into
+// C.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_long, c_uint};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+use std::slice;
+
+use rustc_serialize::json;
+
+use api::{self, Plan};
+
+
+// moves are checked and logged, not made
+pub const ORGANIZE_DRY_RUN: c_uint = 1;
+// replace destinations that exist
+pub const ORGANIZE_FORCE: c_uint = 2;
+// apply a plan the manifest shows was applied already
+pub const ORGANIZE_REAPPLY: c_uint = 4;
+
+// the moves from organize_plan or organize_plan_parse
+pub struct OrganizePlan(Plan);
+
+// Runs `f`, turning a failure or a panic into a message for `error`.
+fn guard<T, F>(error: *mut *mut c_char, failed: T, f: F) -> T
+    where F: FnOnce() -> Result<T, String>
+{
+    let message = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => return value,
+        Ok(Err(message)) => message,
+        Err(_) => "panicked".to_string(),
+    };
+    if !error.is_null() {
+        unsafe {
+            *error = c_string(message);
+        }
+    }
+    failed
+}
+
+// a string to hand to C; a NUL in it ends it
+fn c_string(s: String) -> *mut c_char {
+    let s = s.split('\0').next().unwrap_or("").to_string();
+    CString::new(s).map(CString::into_raw).unwrap_or(ptr::null_mut())
+}
+
+unsafe fn rust_str<'a>(s: *const c_char, what: &str) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err(format!("{} is NULL", what));
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| format!("{} isn't UTF-8", what))
+}
+
+// Plans a run like api::plan; `argv` are the `argc` arguments of
+// `organize-by-mtime plan` without the program name, the subcommand and
+// <plan>. Returns NULL on failure.
+#[no_mangle]
+pub unsafe extern "C" fn organize_plan(argc: c_int,
+                                       argv: *const *const c_char,
+                                       error: *mut *mut c_char)
+                                       -> *mut OrganizePlan {
+    guard(error, ptr::null_mut(), || {
+        let argv = if argc > 0 && !argv.is_null() {
+            slice::from_raw_parts(argv, argc as usize)
+        } else {
+            &[]
+        };
+        let mut args = vec![];
+        for &arg in argv {
+            args.push(try!(rust_str(arg, "argument")));
+        }
+        let plan = try!(api::plan(&args).map_err(|e| e.to_string()));
+        Ok(Box::into_raw(Box::new(OrganizePlan(plan))))
+    })
+}
+
+// the plan as JSON, as saved by --save-plan; NULL for a NULL plan
+#[no_mangle]
+pub unsafe extern "C" fn organize_plan_json(plan: *const OrganizePlan) -> *mut c_char {
+    match plan.as_ref() {
+        Some(plan) => json::encode(&plan.0).map(c_string).unwrap_or(ptr::null_mut()),
+        None => ptr::null_mut(),
+    }
+}
+
+// a plan from JSON in the --save-plan format, such as an edited
+// organize_plan_json; NULL if it is invalid
+#[no_mangle]
+pub unsafe extern "C" fn organize_plan_parse(text: *const c_char,
+                                             error: *mut *mut c_char)
+                                             -> *mut OrganizePlan {
+    guard(error, ptr::null_mut(), || {
+        let text = try!(rust_str(text, "plan"));
+        let plan = try!(json::decode(text).map_err(|e| e.to_string()));
+        Ok(Box::into_raw(Box::new(OrganizePlan(plan))))
+    })
+}
+
+// Carries out a plan like api::execute, with ORGANIZE_* `flags`; `manifest`
+// may be NULL. Returns the number of moves rejected or failed, or -1 if the
+// run stopped.
+#[no_mangle]
+pub unsafe extern "C" fn organize_execute(plan: *const OrganizePlan,
+                                          flags: c_uint,
+                                          manifest: *const c_char,
+                                          error: *mut *mut c_char)
+                                          -> c_long {
+    guard(error, -1, || {
+        let plan = try!(plan.as_ref().ok_or("plan is NULL".to_string()));
+        let manifest = if manifest.is_null() {
+            None
+        } else {
+            Some(Path::new(try!(rust_str(manifest, "manifest"))))
+        };
+        api::execute(&plan.0,
+                     flags & ORGANIZE_DRY_RUN != 0,
+                     flags & ORGANIZE_FORCE != 0,
+                     flags & ORGANIZE_REAPPLY != 0,
+                     manifest)
+            .map(|failed| failed as c_long)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn organize_plan_free(plan: *mut OrganizePlan) {
+    if !plan.is_null() {
+        drop(Box::from_raw(plan));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn organize_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
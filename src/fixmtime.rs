@@ -0,0 +1,78 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// `fix-mtime DIRECTORY...` sets the mtime of files to their date from the
+// date sources, without moving them, so that a later run by mtime (or any
+// other tool) sees the right dates.
+
+use std::fs;
+use std::path::Path;
+
+use filetime;
+
+use log::{self, Paint};
+use {file_mtime, patterns, source_date, to_filetime, walk_source, Args, Context};
+
+
+// returns error count
+pub fn run(args: &Args, ctx: &Context) -> isize {
+    let wanted = patterns::filter(args);
+    let mut errors: isize = 0;
+
+    for dir in &args.arg_directory {
+        let root = Path::new(dir);
+        for entry in walk_source(dir, args) {
+            if !entry.file_type().is_file() || !wanted(entry.path().strip_prefix(dir).unwrap_or(entry.path())) {
+                continue;
+            }
+            let md = match fs::metadata(entry.path()) {
+                Ok(md) => md,
+                Err(e) => {
+                    log_error!("Error: {:?}: {}", entry.path(), e);
+                    errors += 1;
+                    continue;
+                }
+            };
+            // files without a date in any source keep their mtime
+            let date = match source_date(entry.path(), root, &md, ctx) {
+                Some(date) => date,
+                None => continue,
+            };
+            if date.timestamp() == file_mtime(&md).timestamp() {
+                continue;
+            }
+            log_info!("set mtime {} {}",
+                      log::paint(&format!("{:?}", entry.path()), Paint::Source),
+                      log::paint(&date.to_string(), Paint::Destination));
+            if args.flag_dry_run {
+                continue;
+            }
+            let ft = to_filetime(&date);
+            if let Err(e) = filetime::set_file_times(entry.path(), ft, ft) {
+                log_error!("Error: {:?}: {}", entry.path(), e);
+                errors += 1;
+            }
+        }
+    }
+    errors
+}
@@ -0,0 +1,86 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Offline reverse geocoding for the {country} and {city} layout tokens: the
+// closest place in a table of cities and travel destinations (places.tsv),
+// if there is one within MAX_DISTANCE. It is coarse on purpose; a photo from
+// a village is filed under the nearest city on the list.
+
+use std::f64::consts::PI;
+
+const PLACES: &'static str = include_str!("places.tsv");
+
+const MAX_DISTANCE_KM: f64 = 150.0;
+
+
+pub struct Place {
+    pub city: String,
+    pub country: String,
+    lat: f64,
+    lon: f64,
+}
+
+pub struct Places {
+    places: Vec<Place>,
+}
+
+fn distance_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let rad = |deg: f64| deg * PI / 180.0;
+    let (dlat, dlon) = (rad(lat2 - lat1), rad(lon2 - lon1));
+    let a = (dlat / 2.0).sin().powi(2) +
+            rad(lat1).cos() * rad(lat2).cos() * (dlon / 2.0).sin().powi(2);
+    6371.0 * 2.0 * a.sqrt().asin()
+}
+
+impl Places {
+    // the built-in table: city, country, latitude and longitude per line
+    pub fn load() -> Places {
+        let places = PLACES.lines()
+                           .filter_map(|line| {
+                               let fields: Vec<&str> = line.split('\t').collect();
+                               match (fields.get(2).and_then(|f| f.parse().ok()),
+                                      fields.get(3).and_then(|f| f.parse().ok())) {
+                                   (Some(lat), Some(lon)) => {
+                                       Some(Place {
+                                           city: fields[0].to_string(),
+                                           country: fields[1].to_string(),
+                                           lat: lat,
+                                           lon: lon,
+                                       })
+                                   }
+                                   _ => None,
+                               }
+                           })
+                           .collect();
+        Places { places: places }
+    }
+
+    pub fn nearest(&self, lat: f64, lon: f64) -> Option<&Place> {
+        self.places
+            .iter()
+            .map(|p| (distance_km(lat, lon, p.lat, p.lon), p))
+            .filter(|&(d, _)| d <= MAX_DISTANCE_KM)
+            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+            .map(|(_, p)| p)
+    }
+}
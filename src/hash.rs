@@ -0,0 +1,184 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+// Content checksums, for the manifest, --remove-source-after-verify and
+// finding duplicates. xxh3 is the default, being fast and wide enough that
+// different files don't share one by chance; FNV-1a is what older manifests
+// hold and can still be picked with --hash, as can blake3 or sha256 where a
+// checksum should also resist tampering. A checksum carries its algorithm,
+// so those recorded with one are checked with it whatever --hash says.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use blake3;
+use sha2::{Digest, Sha256};
+use xxhash_rust::xxh3::Xxh3;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Algorithm {
+    Fnv = 0,
+    Xxh3 = 1,
+    Blake3 = 2,
+    Sha256 = 3,
+}
+
+const ALGORITHMS: [Algorithm; 4] = [Algorithm::Fnv,
+                                    Algorithm::Xxh3,
+                                    Algorithm::Blake3,
+                                    Algorithm::Sha256];
+
+impl Algorithm {
+    pub fn parse(s: &str) -> Option<Algorithm> {
+        ALGORITHMS.iter().cloned().find(|a| a.name() == s)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Algorithm::Fnv => "fnv",
+            Algorithm::Xxh3 => "xxh3",
+            Algorithm::Blake3 => "blake3",
+            Algorithm::Sha256 => "sha256",
+        }
+    }
+}
+
+// for new checksums when --hash isn't given
+pub const DEFAULT: Algorithm = Algorithm::Xxh3;
+
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Checksum {
+    algorithm: Algorithm,
+    digest: Vec<u8>,
+}
+
+impl Checksum {
+    // as written by Display: ALGORITHM:HEX, or just the hex digits for FNV
+    pub fn parse(s: &str) -> Option<Checksum> {
+        let (algorithm, hex) = match s.find(':') {
+            Some(i) => (Algorithm::parse(&s[..i]), &s[i + 1..]),
+            None => (Some(Algorithm::Fnv), s),
+        };
+        if hex.is_empty() || hex.len() % 2 != 0 || !hex.is_ascii() {
+            return None;
+        }
+        let digest: Option<Vec<u8>> = (0..hex.len())
+                                          .step_by(2)
+                                          .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+                                          .collect();
+        match (algorithm, digest) {
+            (Some(algorithm), Some(digest)) => {
+                Some(Checksum {
+                    algorithm: algorithm,
+                    digest: digest,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    // the digest alone
+    pub fn hex(&self) -> String {
+        self.digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.algorithm != Algorithm::Fnv {
+            try!(write!(f, "{}:", self.algorithm.name()));
+        }
+        write!(f, "{}", self.hex())
+    }
+}
+
+
+pub enum Hasher {
+    Fnv(u64),
+    Xxh3(Box<Xxh3>),
+    Blake3(Box<blake3::Hasher>),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub fn new(algorithm: Algorithm) -> Hasher {
+        match algorithm {
+            Algorithm::Fnv => Hasher::Fnv(0xcbf29ce484222325),
+            Algorithm::Xxh3 => Hasher::Xxh3(Box::new(Xxh3::new())),
+            Algorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        match *self {
+            Hasher::Fnv(ref mut hash) => {
+                for &byte in bytes {
+                    *hash = (*hash ^ byte as u64).wrapping_mul(0x100000001b3);
+                }
+            }
+            Hasher::Xxh3(ref mut h) => h.update(bytes),
+            Hasher::Blake3(ref mut h) => {
+                h.update(bytes);
+            }
+            Hasher::Sha256(ref mut h) => h.update(bytes),
+        }
+    }
+
+    pub fn finish(self) -> Checksum {
+        let (algorithm, digest) = match self {
+            Hasher::Fnv(hash) => (Algorithm::Fnv, hash.to_be_bytes().to_vec()),
+            Hasher::Xxh3(h) => (Algorithm::Xxh3, h.digest128().to_be_bytes().to_vec()),
+            Hasher::Blake3(h) => (Algorithm::Blake3, h.finalize().as_bytes().to_vec()),
+            Hasher::Sha256(h) => (Algorithm::Sha256, h.finalize().to_vec()),
+        };
+        Checksum {
+            algorithm: algorithm,
+            digest: digest,
+        }
+    }
+}
+
+
+pub fn file(path: &Path, algorithm: Algorithm) -> io::Result<Checksum> {
+    reader(try!(File::open(path)), algorithm)
+}
+
+pub fn reader<R: Read>(mut input: R, algorithm: Algorithm) -> io::Result<Checksum> {
+    let mut hasher = Hasher::new(algorithm);
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let n = try!(input.read(&mut buf));
+        if n == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
@@ -0,0 +1,93 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// HTTP endpoint for --watch mode, for dashboards and upload flows that
+// can't talk to the control socket:
+//
+//   POST /run      trigger a run (202 Accepted)
+//   GET  /summary  summary of the last run as JSON (404 before the first)
+//
+// Every request needs an "Authorization: Bearer TOKEN" header with the token
+// from the ORGANIZE_BY_MTIME_TOKEN environment variable.
+
+use std::io;
+use std::sync::Arc;
+use std::thread;
+
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use daemon::Daemon;
+
+
+pub const TOKEN_VAR: &'static str = "ORGANIZE_BY_MTIME_TOKEN";
+
+// compares without returning early, so the token can't be guessed by timing
+fn same(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn authorized(request: &Request, token: &str) -> bool {
+    let expected = format!("Bearer {}", token);
+    request.headers()
+           .iter()
+           .any(|h| h.field.equiv("Authorization") && same(h.value.as_bytes(), expected.as_bytes()))
+}
+
+pub fn serve(addr: &str, token: String, daemon: Arc<Daemon>) -> io::Result<()> {
+    if token.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty token"));
+    }
+    let server = try!(Server::http(addr)
+                          .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string())));
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = if !authorized(&request, &token) {
+                Response::from_string("unauthorized\n").with_status_code(401)
+            } else {
+                match (request.method(), request.url()) {
+                    (&Method::Post, "/run") => {
+                        daemon.trigger();
+                        Response::from_string("run triggered\n").with_status_code(202)
+                    }
+                    (&Method::Get, "/summary") => {
+                        match daemon.last_summary() {
+                            Some(summary) => {
+                                let content_type = Header::from_bytes(&b"Content-Type"[..],
+                                                                      &b"application/json"[..])
+                                                       .unwrap();
+                                Response::from_string(summary.to_json()).with_header(content_type)
+                            }
+                            None => Response::from_string("no run yet\n").with_status_code(404),
+                        }
+                    }
+                    _ => Response::from_string("not found\n").with_status_code(404),
+                }
+            };
+            let _ = request.respond(response);
+        }
+    });
+    Ok(())
+}
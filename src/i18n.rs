@@ -0,0 +1,161 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+// Translations of the messages people read at the end of a run. A catalog
+// is a file in a subset of Fluent's syntax:
+//
+//     # comment
+//     moved-files = { $count } Dateien verschoben, { $size } in { $seconds }s
+//     total-errors = Fehler insgesamt: { $count }
+//
+// with longer messages continued on indented lines. Messages missing from
+// the catalog, or without one, are the English text in the code.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+
+// the catalog used without --messages
+pub const CATALOG_VAR: &'static str = "ORGANIZE_BY_MTIME_MESSAGES";
+
+pub type Catalog = Arc<HashMap<String, String>>;
+
+// the catalog of the run on this thread, which the threads it starts take
+// along with catalog() and install()
+thread_local!(static CATALOG: RefCell<Catalog> = RefCell::new(Catalog::default()));
+
+pub fn catalog() -> Catalog {
+    CATALOG.with(|catalog| catalog.borrow().clone())
+}
+
+// makes `catalog` that of this thread; returns the one it replaces
+pub fn install(catalog: Catalog) -> Catalog {
+    CATALOG.with(|current| current.replace(catalog))
+}
+
+// Loads the catalog at `path`, in which `{lang}` stands for the language of
+// the locale, e.g. `messages/{lang}.ftl`: first as in LANG (`pt_BR`), then
+// without the country (`pt`). No file for the language is not an error, so
+// that one setting serves every user.
+pub fn init(path: &str) -> io::Result<()> {
+    let catalog = if path.contains("{lang}") {
+        let mut found = HashMap::new();
+        for lang in languages() {
+            let candidate = path.replace("{lang}", &lang);
+            if Path::new(&candidate).is_file() {
+                found = try!(load(Path::new(&candidate)));
+                break;
+            }
+        }
+        found
+    } else {
+        try!(load(Path::new(path)))
+    };
+    install(Arc::new(catalog));
+    Ok(())
+}
+
+// the locale's language, most specific first
+fn languages() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+                     .iter()
+                     .filter_map(|var| env::var(var).ok())
+                     .find(|value| !value.is_empty())
+                     .unwrap_or_default();
+    // pt_BR.UTF-8@euro -> pt_BR
+    let locale = locale.split(|c| c == '.' || c == '@').next().unwrap_or("");
+    if locale.is_empty() || locale == "C" || locale == "POSIX" {
+        return vec![];
+    }
+    let mut langs = vec![locale.to_string()];
+    if let Some(i) = locale.find('_') {
+        langs.push(locale[..i].to_string());
+    }
+    langs
+}
+
+fn load(path: &Path) -> io::Result<HashMap<String, String>> {
+    let mut text = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut text));
+    parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn parse(text: &str) -> Result<HashMap<String, String>, String> {
+    let mut catalog = HashMap::new();
+    let mut last: Option<String> = None;
+    for (n, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        if line.starts_with(' ') || line.starts_with('\t') {
+            match last {
+                Some(ref id) => {
+                    let message: &mut String = catalog.get_mut(id).unwrap();
+                    if !message.is_empty() {
+                        message.push(' ');
+                    }
+                    message.push_str(line.trim());
+                }
+                None => return Err(format!("line {}: continues no message", n + 1)),
+            }
+            continue;
+        }
+        let eq = match line.find('=') {
+            Some(eq) => eq,
+            None => return Err(format!("line {}: expected `id = message`", n + 1)),
+        };
+        let id = line[..eq].trim().to_string();
+        catalog.insert(id.clone(), line[eq + 1..].trim().to_string());
+        last = Some(id);
+    }
+    Ok(catalog)
+}
+
+// The message `id` from the catalog, or `default`, with every `{ $name }`
+// replaced by its value in `values`.
+pub fn tr(id: &str, default: &str, values: &[(&str, String)]) -> String {
+    let catalog = catalog();
+    let message = catalog.get(id).map(|m| &m[..]).unwrap_or(default);
+    let mut out = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        out.push_str(&rest[..start]);
+        let name = rest[start + 1..end].trim().trim_start_matches('$');
+        match values.iter().find(|&&(n, _)| n == name) {
+            Some(&(_, ref value)) => out.push_str(value),
+            // left as is, so that a typo in the catalog shows
+            None => out.push_str(&rest[start..end + 1]),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
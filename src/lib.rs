@@ -0,0 +1,3346 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+extern crate rustc_serialize;
+extern crate docopt;
+extern crate walkdir;
+extern crate glob;
+extern crate filetime;
+extern crate chrono;
+extern crate flate2;
+extern crate ratatui;
+extern crate rhai;
+extern crate ssh2;
+extern crate tar;
+extern crate tiny_http;
+extern crate ureq;
+extern crate zip;
+extern crate zstd;
+extern crate libc;
+extern crate blake3;
+extern crate sha2;
+extern crate xxhash_rust;
+extern crate toml;
+#[cfg(feature = "python")]
+extern crate pyo3;
+// what the pyo3 macros expand to names ::core, which only 2018 crates have
+// without this
+#[cfg(feature = "python")]
+extern crate core;
+#[cfg(feature = "async")]
+extern crate tokio;
+
+
+macro_rules! log_error(
+    ($($arg:tt)*) => (
+        ::log::error(&format!($($arg)*))
+    )
+);
+
+macro_rules! log_warn(
+    ($($arg:tt)*) => (
+        ::log::warn(&format!($($arg)*))
+    )
+);
+
+macro_rules! log_info(
+    ($($arg:tt)*) => (
+        ::log::info(&format!($($arg)*))
+    )
+);
+
+// tr!("id", "English text with { $name }", name = value), see i18n
+macro_rules! tr(
+    ($id:expr, $default:expr) => (
+        ::i18n::tr($id, $default, &[])
+    );
+    ($id:expr, $default:expr, $($name:ident = $value:expr),+) => (
+        ::i18n::tr($id, $default, &[$((stringify!($name), $value.to_string())),+])
+    )
+);
+
+
+pub mod api;
+#[cfg(feature = "async")]
+pub mod async_api;
+mod apply;
+mod archive;
+mod checkconfig;
+mod audio;
+mod completions;
+mod config;
+mod control;
+mod daemon;
+mod dates;
+mod dedupe;
+mod doctor;
+mod email;
+mod executor;
+mod exif;
+mod expand;
+pub mod ffi;
+mod fixmtime;
+mod hash;
+mod i18n;
+mod geo;
+mod http;
+mod lock;
+mod log;
+mod manifest;
+mod metrics;
+mod notify;
+mod office;
+mod pathlen;
+mod patterns;
+mod pdf;
+mod plan;
+mod preflight;
+mod priority;
+#[cfg(feature = "python")]
+mod python;
+mod reorganize;
+mod rsync;
+mod s3;
+mod script;
+mod sftp;
+mod stats;
+mod summary;
+mod template;
+#[cfg(test)]
+mod testing;
+mod timings;
+mod tui;
+mod undo;
+mod verify;
+mod workers;
+
+
+
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::mem;
+use std::panic;
+use std::path::{Component, Path, PathBuf};
+use std::process::{self, Command};
+use std::thread::{self, JoinHandle};
+use std::sync::{mpsc, Arc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use docopt::Docopt;
+use walkdir::{DirEntry, WalkDir};
+use filetime::FileTime;
+use chrono::*;
+
+use archive::{ArchiveExecutor, ArchiveFormat};
+use daemon::Daemon;
+use executor::{CopyExecutor, Executor, LocalExecutor, MoveOp, PluginExecutor, VerifyingExecutor};
+use log::Paint;
+use hash::Checksum;
+use manifest::Manifest;
+use metrics::Metrics;
+use plan::Plan;
+use timings::{Phase, Timings};
+use rsync::RsyncExecutor;
+use s3::S3Executor;
+use script::DestScript;
+use sftp::SftpExecutor;
+use summary::{Conflict, Summary};
+use template::Template;
+
+const USAGE: &'static str = "
+Organize folders by mtime of files.
+
+Usage:
+  organize-by-time verify [--oldest | --newest] [--manifest=FILE] \
+                    [--date-source=SOURCE]... [--date-from-dirname] [--date-override=RULE]... \
+                    <output>
+  organize-by-time reorganize [--oldest | --newest] [--manifest=FILE] [--hash=ALGORITHM] \
+                    [--layout=TEMPLATE] [--group-by=KEYS] \
+                    [--date-source=SOURCE]... [--date-from-dirname] [--date-override=RULE]... \
+                    [--dry-run] [--force] <output>
+  organize-by-time plan-diff <old> <new>
+  organize-by-time apply [--manifest=FILE] [--hash=ALGORITHM] [--dry-run] [--force] [--reapply] <plan>
+  organize-by-time undo [--run=ID] [--dry-run] [--force] <manifest>
+  organize-by-time find-duplicates [--hash=ALGORITHM] <output>
+  organize-by-time fix-mtime [--pattern=PATTERN]... [--not-pattern=PATTERN]... \
+                    [--patterns-from=FILE]... [--not-patterns-from=FILE]... [--smart-case] \
+                    [--dry-run] \
+                    [--date-source=SOURCE]... [--date-from-dirname] [--date-override=RULE]... \
+                    <directory>...
+  organize-by-time organize [options] <directory>...
+  organize-by-time plan [options] <plan> <directory>...
+  organize-by-time stats [options] <directory>...
+  organize-by-time completions <shell>
+  organize-by-time check-config [options] <directory>...
+  organize-by-time doctor [--output-dir=OUTPUT] [--max-path-length=N] <directory>...
+  organize-by-time  [--oldest | --newest] \
+                    [--pattern=PATTERN]... \
+                    [--not-pattern=PATTERN]... \
+                    [--patterns-from=FILE]... \
+                    [--not-patterns-from=FILE]... \
+                    [--smart-case] \
+                    [--output-dir=OUTPUT] \
+                    [--flatten | --append-source-name | [--preserve-structure] [--strip=N | --strip-prefix=PATH | --keep-components=N]] \
+                    [--dry-run] \
+                    [--force] \
+                    [--pre-batch-cmd=CMD] \
+                    [--post-move-cmd=CMD] \
+                    [--layout=TEMPLATE | --dest-script=FILE] [--group-by=KEYS] [--keep-root-name] \
+                    [--size-buckets=LIST] [--day-folder-style=STYLE] \
+                    [--executor=CMD | --archive=FORMAT [--archive-per-month] | --compress-older-than=AGE] \
+                    [--expand-archives] \
+                    [--manifest=FILE] [--hash=ALGORITHM] \
+                    [--notify-webhook=URL] \
+                    [--notify-email=ADDRESS] \
+                    [--watch=INTERVAL [--metrics=ADDR] [--control-socket=PATH] [--http=ADDR]] \
+                    [--log-target=TARGET] \
+                    [--tui] \
+                    [--color=WHEN] [--messages=FILE] \
+                    [--sort-by=KEY] \
+                    [--stats-only] \
+                    [--save-plan=FILE] [--plan-format=FORMAT] \
+                    [--skip-duplicates [--quick-duplicates]] [--checksum] \
+                    [--on-conflict=ACTION | --update] [--merge=POLICY] \
+                    [--date-source=SOURCE]... \
+                    [--date-from-dirname] \
+                    [--date-override=RULE]... \
+                    [--cluster-events=GAP] [--max-batch-spread=AGE [--outliers=ACTION]] \
+                    [--warn-batch-spread=AGE] \
+                    [--burst] \
+                    [--round-to=PERIOD] \
+                    [--keep-recent=N] [--skip-hidden] [--min-size=SIZE] \
+                    [--set-mtime=DATE] \
+                    [--transactional] [--strict] \
+                    [--fsync] [--remove-source-after-verify | --mirror] \
+                    [--symlinks=POLICY] [--long-paths=STRATEGY] [--max-path-length=N] \
+                    [--retry-in-use] \
+                    [--timings] [-v...] [--explain=PATH]... \
+                    [--io-workers=N] \
+                    [--nice=N] [--ionice=CLASS] \
+                    <directory>...
+  organize-by-time (-h | --help)
+  organize-by-time --version
+
+Options:
+  -O OUTPUT --output-dir=OUTPUT     Output directory, [user@]host:/path or
+                                    ssh://[user@]host/path (copied with rsync),
+                                    sftp://[user@]host/path or
+                                    s3://bucket/prefix. [default: .]
+  -P PATTERN --not-pattern=PATTERN  Ignore files with this pattern.
+  -d --dry-run                      Only print, do not move any files.
+  -f --force                        Overwrite files if conflict found; for runs
+                                    deprecated, use `--on-conflict=overwrite`.
+  --reapply                         Apply a plan that the manifest shows was
+                                    applied already, once more.
+  -n --newest                       Use the newest file in the directory.
+  -o --oldest                       Use the oldest file in the directory (default).
+  -p PATTERN --pattern=PATTERN      Only consider files with this pattern; see
+                                    Patterns below.
+  --patterns-from=FILE              Read patterns from FILE, one per line, as
+                                    if given with --pattern; blank lines and
+                                    lines starting with # are skipped.
+  --not-patterns-from=FILE          The same for --not-pattern.
+  --smart-case                      Match patterns without capitals regardless
+                                    of case, the others exactly.
+  --preserve-structure              Keep the path of files below their date
+                                    folder (the default), less what the
+                                    options below take off.
+  -s N --strip N                    Strip N leftmost directories [default: 0]
+  --strip-prefix=PATH               Strip PATH from the start of source paths;
+                                    paths not below it are kept whole.
+  --keep-components=N               Keep only the last N directories of source
+                                    paths.
+  --flatten                         Put files directly into their date folder,
+                                    without any source directories (see
+                                    `--on-conflict=rename` for name clashes).
+  --append-source-name              Put files into a folder named after their
+                                    source folder below the date folder, as in
+                                    2019/beach/ for Vacation2019/beach/.
+  --pre-batch-cmd=CMD               Run CMD before moving each batch; the batch
+                                    is skipped if CMD fails.
+  --post-move-cmd=CMD               Run CMD after each file is moved.
+  --layout=TEMPLATE                 The folder files go to below OUTPUT, from
+                                    {year}, {month}, {day}, {hour}, {weekday},
+                                    {weekend_or_weekday}, {week_of_month},
+                                    {doy}, {country}, {city}, {camera} and
+                                    strftime escapes for the batch date; with
+                                    {name}, {stem}, {ext}, {parent} and {root}
+                                    of the source file, the whole path if
+                                    {name} or {stem} is used, {counter} for a
+                                    number added only where needed to keep
+                                    names apart, and {hash} (or {hash:I-J}) or
+                                    {checksum} of its checksum; `cas`
+                                    files by checksum below the year.
+                                    [default: {year}]
+  --group-by=KEYS                   Folder levels from a list of keys,
+                                    outermost first: year, month, day, hour,
+                                    type (the extension), size, owner, camera,
+                                    country or city. Without a date key in the
+                                    list, the levels go above the layout.
+  --keep-root-name                  Put files into a folder named after the
+                                    source directory they were found in, such
+                                    as the SD card, below the date folder.
+  --size-buckets=LIST               The buckets of {size} and `--group-by=size`,
+                                    each NAME:BOUND for files smaller than
+                                    BOUND (100K, 10M, 1G, ...), the last one
+                                    NAME for the rest.
+                                    [default: tiny:100K,small:10M,large:1G,huge]
+  --day-folder-style=STYLE          How {day} folders are named: nested, the
+                                    bare day as in 2023/07/15, or flat-date,
+                                    the whole date as in 2023/07/2023-07-15.
+                                    [default: nested]
+  --dest-script=FILE                Compute destinations with a rhai script.
+  --executor=CMD                    Hand moves to CMD instead of renaming files.
+  --archive=FORMAT                  Append files to YEAR.FORMAT archives instead
+                                    of moving them; FORMAT is tar.zst or zip.
+                                    A tar.zst archive is never added to; later
+                                    runs write YEAR-2.tar.zst and so on.
+  --archive-per-month               Use YEAR-MM.FORMAT archives.
+  --expand-archives                 Extract zip/tar files found in the source,
+                                    dating each member by its own mtime. Not
+                                    with --mirror, --archive, --executor or
+                                    with --remove-source-after-verify.
+  --compress-older-than=AGE         Store files last modified more than AGE ago
+                                    (e.g. 90d, 2y) zstd-compressed as NAME.zst.
+  --manifest=FILE                   Append a line for every file organized to
+                                    FILE. s3:// outputs always write one, to
+                                    organize-by-mtime.manifest unless given.
+  --hash=ALGORITHM                  Checksum files with xxh3 (fastest), blake3,
+                                    sha256, or fnv as older manifests did: for
+                                    the manifest, for verifying copies and for
+                                    duplicates. [default: xxh3]
+  --notify-webhook=URL              POST the run summary as JSON to URL when done.
+  --notify-email=ADDRESS            Mail the run summary to ADDRESS (uses
+                                    sendmail) when done.
+  --watch=INTERVAL                  Keep running, organizing again every INTERVAL
+                                    (e.g. 30min, 6h).
+  --metrics=ADDR                    With --watch, serve Prometheus metrics on
+                                    http://ADDR/metrics.
+  --control-socket=PATH             With --watch, accept JSON-RPC requests
+                                    (status, trigger-run, pause, resume,
+                                    reload-config) on a unix socket at PATH.
+  --http=ADDR                       With --watch, serve POST /run and
+                                    GET /summary on ADDR; requests must carry
+                                    the token in $ORGANIZE_BY_MTIME_TOKEN.
+  --log-target=TARGET               Where to log moves and errors: console,
+                                    syslog or journald. [default: console]
+  --tui                             Review and edit the plan in a full-screen
+                                    interface before applying it.
+  --color=WHEN                      Color the output: auto, always or never.
+                                    [default: auto]
+  --messages=FILE                   Translate the run's messages with the
+                                    catalog FILE, where {lang} is replaced by
+                                    the language of the locale (e.g.
+                                    messages/{lang}.ftl). Defaults to
+                                    $ORGANIZE_BY_MTIME_MESSAGES.
+  --sort-by=KEY                     With --dry-run, list the moves sorted by
+                                    KEY: dest, src or date.
+  --stats-only                      Only report how the files are distributed
+                                    over time, without moving anything.
+  --save-plan=FILE                  Write the planned moves to FILE as JSON, also
+                                    with --dry-run. Not for what `apply` can't
+                                    carry out: remote outputs, --mirror,
+                                    archives, executors, compression and
+                                    verified moves.
+  --plan-format=FORMAT              How a dry run prints its moves: text, json
+                                    (an object per line), csv, tsv, or
+                                    null-separated for `xargs -0 -n 2`; other
+                                    messages go to stderr then.
+                                    [default: text]
+  --skip-duplicates                 With several directories, leave files that
+                                    have an identical copy in an earlier one
+                                    where they are. Their content is compared.
+  --quick-duplicates                Take files of the same size and mtime for
+                                    identical without reading them.
+  --checksum                        With --mirror, read files of the same size
+                                    to compare them, even if their mtime is the
+                                    same too.
+  --on-conflict=ACTION              What to do with a file whose destination
+                                    exists or is that of an earlier file of
+                                    the run: fail, skip it, overwrite the
+                                    destination, overwrite it only if the file
+                                    is newer (newer), ask, quarantine it under
+                                    OUTPUT/_conflicts, or rename it to
+                                    NAME_1.EXT (or _2 and so on). Files of the
+                                    same run never overwrite each other.
+                                    [default: fail]
+  -u --update                       Overwrite destinations only with newer
+                                    files, like cp and rsync; the same as
+                                    `--on-conflict=newer`.
+  --merge=POLICY                    What to do when a date folder already has
+                                    files in it at the start of the run: merge
+                                    into it (always), ask, or use a numbered
+                                    folder beside it (suffix), as in 2019_2.
+                                    [default: always]
+  --date-source=SOURCE              Date files by SOURCE, falling back to their
+                                    mtime if it has no date; several sources
+                                    are tried in order.
+  --date-from-dirname               Date files in folders named like
+                                    `2019-07 Vacation` or `20180101_NewYear`
+                                    by the folder name instead of their mtime.
+  --date-override=RULE              Date files matching a pattern differently,
+                                    e.g. `scans/*.pdf=filename`. The first
+                                    matching rule wins.
+  --cluster-events=GAP              Split batches where files are more than GAP
+                                    (e.g. 3h) apart, into numbered event
+                                    folders like 2021-08-14_event-01.
+  --max-batch-spread=AGE            Take files dated more than AGE (e.g. 90d)
+                                    away from the rest of their batch out of
+                                    it, and organize them by --outliers.
+  --outliers=ACTION                 What to do with files taken out of a batch:
+                                    organize each by its own date (individual)
+                                    or below OUTPUT/_outliers (quarantine).
+                                    [default: individual]
+  --warn-batch-spread=AGE           Warn about batches whose files span more
+                                    than AGE (e.g. 2y), as most of them will be
+                                    filed under a date that isn't theirs.
+  --burst                           Put continuous-shooting sequences (3 or more
+                                    files less than a minute apart) into
+                                    burst-YYYYMMDD-HHMMSS folders.
+  --round-to=PERIOD                 Snap batch dates to the start of their day,
+                                    week or month.
+  --keep-recent=N                   Leave the N most recently modified files of
+                                    each directory where they are.
+  --skip-hidden                     Leave files whose name, or that of a folder
+                                    above them, starts with a dot where they
+                                    are.
+  --min-size=SIZE                   Leave files smaller than SIZE (e.g. 10K)
+                                    where they are.
+  --set-mtime=DATE                  Set the mtime of moved files to the batch
+                                    date (batch) or their own date from the
+                                    date sources (source-date).
+  --transactional                   If a file of a batch can't be moved, move
+                                    the ones moved already back, so that each
+                                    source folder is organized whole or not at
+                                    all.
+  --strict                          Fail the run on warnings too, such as an
+                                    implausible date, a file skipped as in
+                                    use or a shortened path.
+  --fsync                           Flush each file and the folders its move
+                                    changed to disk before going on, e.g. for
+                                    removable media.
+  --remove-source-after-verify      Copy every file, even within a filesystem,
+                                    and remove the source only once the copy
+                                    reads back with the same checksum.
+  --mirror                          Copy the files instead of moving them,
+                                    skipping those copied before: recorded in
+                                    the --manifest, or found at their
+                                    destination with the same size and mtime.
+  --symlinks=POLICY                 Follow symbolic links (and junctions on
+                                    Windows) in the source, or skip them.
+                                    [default: follow]
+  --long-paths=STRATEGY             What to do with destinations too long for
+                                    the output filesystem: error, truncate the
+                                    middle of the file name, or hash to end it
+                                    with a hash of the whole name instead, and
+                                    so the folder names if that isn't enough.
+                                    [default: error]
+  --max-path-length=N               The longest destination path in bytes, for
+                                    shares that take less than they report.
+  --retry-in-use                    Try files that were skipped because another
+                                    process had them open (on Windows) again
+                                    at the end of the run.
+  --timings                         Report the time spent walking, reading dates,
+                                    hashing and moving at the end of the run.
+  -v --verbose                      With -vv, report each file left out by
+                                    the patterns, --keep-recent or a broken
+                                    .organize.toml, and why; with -vvv,
+                                    explain every file as `--explain` does.
+  --explain=PATH                    Report how the file PATH is handled: the
+                                    patterns that take it, where its date comes
+                                    from, its batch and how its destination is
+                                    made.
+  --io-workers=N                    Move files on N threads of their own while
+                                    scanning goes on, for slow or remote
+                                    outputs.
+  --nice=N                          Run at CPU priority N, from -20 to 19, like
+                                    nice(1).
+  --ionice=CLASS                    Run at disk priority idle or best-effort,
+                                    like ionice(1); Linux only.
+  -h --help                         Show this screen.
+  --version                         Show version.
+
+`verify` reports files in the year folders of <output> whose dates don't fit
+the folder, and with --manifest, files missing from or unknown to it.
+`reorganize` moves those files into the year folder of their own date,
+recording the moves in the manifest if one is given.
+`organize` does what organize-by-time without a command does. `plan` only
+writes the moves it would make to <plan>, for `apply`, and `stats` only
+reports what it would organize. All three take the options below.
+`completions` prints a completion script for <shell>: bash, zsh or fish.
+`check-config` checks the options and the .organize.toml files of a run
+without moving anything, and prints the settings in effect.
+`plan-diff` lists the moves added (+), removed (-) and retargeted (~) between
+two plans saved with --save-plan; either can also be a manifest.
+`apply` makes the moves of a saved plan, which may have been edited: moves
+whose source is gone or whose destination is taken are rejected.
+`undo` moves the files of the last run recorded in <manifest> (or of run ID)
+back, unless they were modified since; --force moves them back anyway.
+`find-duplicates` lists files with the same content in the year folders of
+<output>.
+`fix-mtime` sets the mtime of files to their date from the date sources, in
+place, for files that have one.
+
+Patterns are shell globs matched against the file name, or, if they contain
+a `/`, against the path below the source directory; `**/` matches any number
+of folders and `{jpg,png}` either alternative. A --pattern starting with `!`
+excludes what it matches again; the last of them to match a file decides.
+
+Date sources are mtime, dirname, filename, pdf, office, office-modified,
+audio-tags, email and exif.
+
+Commands are run by the shell with SRC, DST and DATE set in the environment.
+For --pre-batch-cmd, SRC is the batch directory and DST its year folder.
+
+A destination script sees `src`, `path` (after --strip), `name`, `size`,
+`mtime`, and the batch `date`, `year`, `month` and `day`; it must evaluate to
+a path relative to the output directory.
+
+Exit status: 0 on success, 1 if some files couldn't be organized, 2 for
+invalid arguments, 3 if --dry-run found files to move, 4 if another run is
+organizing into OUTPUT, 5 if OUTPUT is short of space and 130 if interrupted.
+";
+
+// Exit statuses, so that scripts can tell what happened. 0 is success.
+// some files couldn't be organized
+const EXIT_ERRORS: i32 = 1;
+// invalid arguments, patterns or layout; nothing was done
+const EXIT_USAGE: i32 = 2;
+// --dry-run found files to move
+const EXIT_WOULD_MOVE: i32 = 3;
+// another run is organizing into the same output directory
+const EXIT_LOCKED: i32 = 4;
+// the output doesn't have room for the files; nothing was done
+const EXIT_PREFLIGHT: i32 = 5;
+// stopped by SIGINT or SIGTERM, after finishing the file being moved
+const EXIT_INTERRUPTED: i32 = 130;
+
+thread_local!(static LIBRARY: Cell<bool> = Cell::new(false));
+
+// What exit() unwinds with during a library call (see api.rs), which turns
+// it into an error instead of ending the program that embeds the library.
+struct Exit(i32);
+
+fn exit(status: i32) -> ! {
+    if LIBRARY.with(|library| library.get()) {
+        panic::resume_unwind(Box::new(Exit(status)));
+    }
+    process::exit(status)
+}
+
+#[derive(Debug, RustcDecodable)]
+struct Args {
+    cmd_verify: bool,
+    cmd_reorganize: bool,
+    cmd_plan_diff: bool,
+    cmd_apply: bool,
+    cmd_undo: bool,
+    cmd_organize: bool,
+    cmd_plan: bool,
+    cmd_stats: bool,
+    cmd_completions: bool,
+    cmd_check_config: bool,
+    cmd_doctor: bool,
+    arg_shell: String,
+    cmd_find_duplicates: bool,
+    cmd_fix_mtime: bool,
+    arg_directory: Vec<String>,
+    arg_output: String,
+    arg_old: String,
+    arg_new: String,
+    arg_plan: String,
+    arg_manifest: String,
+    flag_oldest: bool,
+    flag_newest: bool,
+    flag_pattern: Vec<String>,
+    flag_not_pattern: Vec<String>,
+    flag_patterns_from: Vec<String>,
+    flag_not_patterns_from: Vec<String>,
+    flag_smart_case: bool,
+    flag_output_dir: String,
+    flag_strip: usize,
+    flag_strip_prefix: Option<String>,
+    flag_keep_components: Option<usize>,
+    flag_flatten: bool,
+    flag_preserve_structure: bool,
+    flag_append_source_name: bool,
+    flag_keep_root_name: bool,
+    flag_dry_run: bool,
+    flag_force: bool,
+    flag_reapply: bool,
+    flag_pre_batch_cmd: Option<String>,
+    flag_post_move_cmd: Option<String>,
+    flag_layout: String,
+    flag_group_by: Option<String>,
+    flag_size_buckets: String,
+    flag_day_folder_style: String,
+    flag_dest_script: Option<String>,
+    flag_executor: Option<String>,
+    flag_archive: Option<String>,
+    flag_archive_per_month: bool,
+    flag_expand_archives: bool,
+    flag_compress_older_than: Option<String>,
+    flag_manifest: Option<String>,
+    flag_hash: String,
+    flag_notify_webhook: Option<String>,
+    flag_notify_email: Option<String>,
+    flag_watch: Option<String>,
+    flag_metrics: Option<String>,
+    flag_control_socket: Option<String>,
+    flag_http: Option<String>,
+    flag_log_target: String,
+    flag_tui: bool,
+    flag_color: String,
+    flag_messages: Option<String>,
+    flag_sort_by: Option<String>,
+    flag_stats_only: bool,
+    flag_save_plan: Option<String>,
+    flag_plan_format: String,
+    flag_skip_duplicates: bool,
+    flag_quick_duplicates: bool,
+    flag_checksum: bool,
+    flag_on_conflict: String,
+    flag_merge: String,
+    flag_update: bool,
+    flag_date_source: Vec<String>,
+    flag_date_from_dirname: bool,
+    flag_date_override: Vec<String>,
+    flag_cluster_events: Option<String>,
+    flag_max_batch_spread: Option<String>,
+    flag_outliers: String,
+    flag_warn_batch_spread: Option<String>,
+    flag_burst: bool,
+    flag_round_to: Option<String>,
+    flag_keep_recent: Option<usize>,
+    flag_skip_hidden: bool,
+    flag_min_size: Option<String>,
+    flag_set_mtime: Option<String>,
+    flag_run: Option<String>,
+    flag_transactional: bool,
+    flag_strict: bool,
+    flag_fsync: bool,
+    flag_remove_source_after_verify: bool,
+    flag_mirror: bool,
+    flag_symlinks: String,
+    flag_long_paths: String,
+    flag_max_path_length: Option<usize>,
+    flag_retry_in_use: bool,
+    flag_timings: bool,
+    flag_verbose: usize,
+    flag_explain: Vec<String>,
+    flag_io_workers: Option<usize>,
+    flag_nice: Option<i32>,
+    flag_ionice: Option<String>,
+    flag_version: bool,
+}
+
+
+#[derive(Clone, Copy, Debug)]
+enum AgePolicy {
+    Default,
+    Oldest,
+    Newest,
+}
+
+use AgePolicy::*;
+
+
+// files from one directory that are filed under a common date
+struct Batch {
+    // the directory of the first file
+    dir: PathBuf,
+    date: NaiveDateTime,
+    // source path and destination relative to the year folder
+    files: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Batch {
+    fn new(files: Vec<(PathBuf, PathBuf)>, date: NaiveDateTime) -> Option<Batch> {
+        let dir = match files.first() {
+            Some(first) => first.0.parent().unwrap_or(Path::new("")).to_path_buf(),
+            None => return None,
+        };
+        Some(Batch {
+            dir: dir,
+            date: date,
+            files: files,
+        })
+    }
+}
+
+
+// state shared by all source directories of a run
+struct Context {
+    layout: Template,
+    // what the layout's tokens depend on
+    template_options: template::Options,
+    // --hash, for new checksums
+    hash: hash::Algorithm,
+    script: Option<DestScript>,
+    executor: Box<dyn Executor>,
+    // with --io-workers, carries out the moves instead of `executor`
+    pool: Option<workers::Pool>,
+    manifest: Option<Manifest>,
+    // every move decided on is written here, for --save-plan
+    plan: Option<plan::Writer>,
+    // and handed to this, for the library API
+    on_plan: Option<Box<dyn FnMut(plan::Move)>>,
+    plan_format: plan::Format,
+    // files last modified before this are compressed
+    compress_before: Option<NaiveDateTime>,
+    // tried in order when no override matches, before the mtime
+    date_sources: Vec<dates::Source>,
+    date_overrides: Vec<dates::Override>,
+    // the files each --pattern, --not-pattern and --date-override matched
+    pattern_usage: patterns::Usage,
+    override_hits: Vec<usize>,
+    // --cluster-events
+    cluster_gap: Option<Duration>,
+    // --max-batch-spread, and the files it quarantined
+    max_spread: Option<Duration>,
+    outliers: HashSet<PathBuf>,
+    // --warn-batch-spread
+    warn_spread: Option<Duration>,
+    // the events numbered so far by day, starting with those in the output
+    events: HashMap<NaiveDate, usize>,
+    round_to: Option<dates::Period>,
+    // --min-size
+    min_size: Option<u64>,
+    // each file's own date, kept for --set-mtime=source-date
+    file_dates: HashMap<PathBuf, NaiveDateTime>,
+    // files to move or extract, also with --dry-run, and their size
+    planned: usize,
+    bytes_planned: u64,
+    // files moved or extracted so far, and their size
+    organized: usize,
+    bytes: u64,
+    // the destinations of this run so far
+    claimed: HashSet<PathBuf>,
+    // the files whose destination was taken, and what --on-conflict did
+    conflicts: Vec<Conflict>,
+    // the number of files left in place for each reason: "pattern mismatch",
+    // "excluded", "too new", "hidden", "too small", "broken config",
+    // "already organized", "conflict", "duplicate" or "in use"
+    skipped: BTreeMap<&'static str, usize>,
+    // --explain: the files to explain, resolved
+    explain: HashSet<PathBuf>,
+    // --on-conflict=ask: the answer given for all files
+    overwrite_all: Option<bool>,
+    // --merge: the folder files of each layout folder go to instead
+    merged: HashMap<PathBuf, PathBuf>,
+    // --merge=ask: the answer given for all folders
+    merge_all: Option<bool>,
+    // --long-paths, for a local output directory
+    long_paths: Option<(pathlen::Limits, pathlen::Strategy)>,
+    // --mirror: the files the manifest records as copied, with their size,
+    // mtime and checksum then
+    mirrored: HashMap<PathBuf, (u64, Option<NaiveDateTime>, Option<Checksum>)>,
+    // moves skipped because another process had the file open
+    in_use: Vec<InUse>,
+    // the .organize.toml files of the source directories
+    configs: config::Configs,
+    // for --timings
+    timings: Timings,
+    // see new_run_id
+    run_id: String,
+}
+
+
+// parses durations like 30s, 10min, 36h, 90d, 2w, 6mo or 2y; months and
+// years are counted as 30 and 365 days. A bare "m" could be either minutes or
+// months, and is refused.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_digit(10)).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let invalid = || format!("invalid duration {:?}, e.g. 30s, 10min, 36h, 90d, 2w, 6mo or 2y", s);
+    let n: i64 = try!(num.parse().map_err(|_| invalid()));
+    match unit {
+        "s" => Ok(Duration::seconds(n)),
+        "min" => Ok(Duration::minutes(n)),
+        "h" => Ok(Duration::hours(n)),
+        "d" => Ok(Duration::days(n)),
+        "w" => Ok(Duration::weeks(n)),
+        "mo" => Ok(Duration::days(n * 30)),
+        "y" => Ok(Duration::days(n * 365)),
+        "m" => Err(format!("{:?} is ambiguous: {}min for minutes, {}mo for months", s, n, n)),
+        _ => Err(invalid()),
+    }
+}
+
+// Timestamps are signed seconds since 1970 throughout, so files from before
+// then are dated right. Dates must fit a four-digit year folder; anything
+// outside of years 1 to 9999 is clamped to the nearest end, with a warning.
+fn timestamp_date(secs: i64, nanos: u32) -> NaiveDateTime {
+    let min = NaiveDate::from_ymd(1, 1, 1).and_hms(0, 0, 0);
+    let max = NaiveDate::from_ymd(9999, 12, 31).and_hms(23, 59, 59);
+    match NaiveDateTime::from_timestamp_opt(secs, nanos) {
+        Some(date) if date >= min && date <= max => date,
+        _ => {
+            let clamped = if secs < 0 { min } else { max };
+            log_warn!("timestamp {} out of range, using {}", secs, clamped);
+            clamped
+        }
+    }
+}
+
+// FileTime counts from 1601 on Windows
+fn unix_seconds(ft: &FileTime) -> i64 {
+    ft.seconds() as i64 - if cfg!(windows) { 11644473600 } else { 0 }
+}
+
+// The FileTime to set for `date`. filetime takes unsigned seconds, which
+// wrap around to the right time_t on Unix for dates before 1970; on Windows
+// they would overflow, so those are set to 1970 instead.
+fn to_filetime(date: &NaiveDateTime) -> FileTime {
+    let secs = date.timestamp();
+    if secs < 0 && cfg!(windows) {
+        log_warn!("can't set mtime {} before 1970, using 1970-01-01", date);
+        return FileTime::from_seconds_since_1970(0, 0);
+    }
+    FileTime::from_seconds_since_1970(secs as u64, 0)
+}
+
+fn file_mtime(md: &fs::Metadata) -> NaiveDateTime {
+    let mtime = FileTime::from_last_modification_time(md);
+    timestamp_date(unix_seconds(&mtime), mtime.nanoseconds())
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+// While `on`, SIGINT and SIGTERM only set a flag, so that a run stops after
+// the file it is moving instead of in the middle of it.
+fn catch_signals(on: bool) {
+    let handler = if on {
+        on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t
+    } else {
+        libc::SIG_DFL
+    };
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+// the flag of the api::Cancel given to the library call on this thread
+thread_local!(static CANCELLED: RefCell<Option<Arc<AtomicBool>>> = RefCell::new(None));
+
+fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst) ||
+    CANCELLED.with(|cancelled| {
+        cancelled.borrow().as_ref().map_or(false, |flag| flag.load(Ordering::SeqCst))
+    })
+}
+
+// Starts a thread for the run on this one, logging, translated and cancelled
+// as it is.
+fn spawn_for_run<T, F>(f: F) -> JoinHandle<T>
+    where T: Send + 'static,
+          F: FnOnce() -> T + Send + 'static
+{
+    let settings = log::settings();
+    let catalog = i18n::catalog();
+    let cancelled = CANCELLED.with(|cancelled| cancelled.borrow().clone());
+    thread::spawn(move || {
+        log::install(settings);
+        i18n::install(catalog);
+        CANCELLED.with(|current| *current.borrow_mut() = cancelled);
+        f()
+    })
+}
+
+// A random id for each run, recorded in the manifest and in saved plans, in
+// the form of a version 4 UUID.
+fn new_run_id() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    // every RandomState is seeded differently
+    let random = || {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(nanos);
+        hasher.write_u32(process::id());
+        hasher.finish()
+    };
+    let (a, b) = (random(), random());
+    let a = (a & !0xf000) | 0x4000;
+    let b = (b & 0x3fff_ffff_ffff_ffff) | 0x8000_0000_0000_0000;
+    format!("{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            a >> 32,
+            (a >> 16) & 0xffff,
+            a & 0xffff,
+            b >> 48,
+            b & 0xffff_ffff_ffff)
+}
+
+// files dated before this year, that of the FAT epoch, or in the future have
+// most likely been stamped by a device whose clock was reset
+const PLAUSIBLE_SINCE: i32 = 1980;
+
+fn now() -> NaiveDateTime {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    NaiveDateTime::from_timestamp(secs as i64, 0)
+}
+
+
+fn shell_command(cmd: &str) -> Command {
+    if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.arg("/C").arg(cmd);
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.arg("-c").arg(cmd);
+        c
+    }
+}
+
+// runs a user command through the shell with SRC, DST and DATE set
+fn run_hook(cmd: &str, src: &Path, dst: &Path, datetime: &NaiveDateTime) -> io::Result<()> {
+    let status = try!(shell_command(cmd)
+                          .env("SRC", src)
+                          .env("DST", dst)
+                          .env("DATE", datetime.format("%Y-%m-%dT%H:%M:%S").to_string())
+                          .status());
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::Other, format!("command failed: {}", status)))
+    }
+}
+
+
+// where a file of a batch dated `datetime` goes, `rel` being its path after
+// --strip
+fn destination(src: &Path,
+               rel: &Path,
+               datetime: &NaiveDateTime,
+               args: &Args,
+               ctx: &Context)
+               -> Result<PathBuf, String> {
+    let output_dir = Path::new(&args.flag_output_dir);
+    let fin = try!(match ctx.script {
+        Some(ref script) => script.destination(src, rel, datetime).map(|d| output_dir.join(d)),
+        None => {
+            let root = source_root(src, args);
+            let config = ctx.configs.for_dir(src.parent().unwrap_or(root), root);
+            let layout = config.as_ref().and_then(|c| c.layout.as_ref()).unwrap_or(&ctx.layout);
+            let dest = |counter| {
+                let folder = layout.expand(src, root, datetime, counter, &ctx.template_options);
+                let dest = output_dir.join(folder);
+                if layout.names_file() { dest } else { dest.join(rel) }
+            };
+            // {counter}: the first number that makes for a free destination
+            let mut fin = dest(None);
+            let mut n = 2;
+            while layout.has_counter() &&
+                  (fin.exists() || ctx.claimed.contains(&fin)) {
+                fin = dest(Some(n));
+                n += 1;
+            }
+            Ok(fin)
+        }
+    });
+    // --outliers=quarantine: the path it would have had, below _outliers
+    if ctx.outliers.contains(src) {
+        let rel = fin.strip_prefix(output_dir).unwrap_or(&fin).to_path_buf();
+        return Ok(output_dir.join("_outliers").join(rel));
+    }
+    Ok(fin)
+}
+
+// --explain: how the destination of `src` is made, `rel` being its path after
+// --strip
+fn explain_destination(src: &Path, rel: &Path, args: &Args, ctx: &Context) -> String {
+    if ctx.script.is_some() {
+        return "from the dest script".to_string();
+    }
+    let root = source_root(src, args);
+    let config = ctx.configs.for_dir(src.parent().unwrap_or(root), root);
+    let layout = if config.as_ref().map_or(false, |c| c.layout.is_some()) {
+        "the layout of .organize.toml".to_string()
+    } else {
+        format!("the layout {:?}", layout_text(args).unwrap_or_default())
+    };
+    if names_file(src, args, ctx) {
+        format!("from {}", layout)
+    } else {
+        format!("from {} and the path {:?}", layout, rel)
+    }
+}
+
+// the source directory `src` was found in
+fn source_root<'a>(src: &'a Path, args: &'a Args) -> &'a Path {
+    args.arg_directory
+        .iter()
+        .map(Path::new)
+        .find(|dir| src.starts_with(dir))
+        .unwrap_or(src.parent().unwrap_or(Path::new("")))
+}
+
+// whether the layout of `src`, that of its .organize.toml if it sets one,
+// gives the whole path of the file
+fn names_file(src: &Path, args: &Args, ctx: &Context) -> bool {
+    let root = source_root(src, args);
+    ctx.configs
+       .for_dir(src.parent().unwrap_or(root), root)
+       .and_then(|c| c.layout.as_ref().map(|l| l.names_file()))
+       .unwrap_or(ctx.layout.names_file())
+}
+
+// returns error count
+// `progress` is called before each file of the batch, with the bytes moved
+// so far in the run
+fn move_batch(batch: &Batch,
+              args: &Args,
+              ctx: &mut Context,
+              progress: &mut dyn FnMut(u64))
+              -> isize {
+    let mut errors: isize = 0;
+    let datetime = &batch.date;
+    let mut year_dir = PathBuf::from(&args.flag_output_dir);
+    year_dir.push(datetime.year().to_string());
+    if let Some(ref cmd) = args.flag_pre_batch_cmd {
+        if !args.flag_dry_run && !batch.files.is_empty() {
+            if let Err(e) = run_hook(cmd, &batch.dir, &year_dir, datetime) {
+                log_error!("Error: pre-batch command for {:?}: {}", batch.dir, e);
+                return 1;
+            }
+        }
+    }
+    // with --transactional, the files moved so far, to move back if one fails
+    let mut moved: Vec<Moved> = vec![];
+    let mut failed = false;
+    for e in batch.files.iter() {
+        if interrupted() {
+            // a --transactional batch isn't left half moved
+            if args.flag_transactional && !moved.is_empty() {
+                log_warn!("interrupted, rolling back the batch of {:?}", batch.dir);
+                failed = true;
+            }
+            break;
+        }
+        progress(ctx.bytes);
+        let src = &e.0;
+        let dst = &e.1;
+        // taken out whatever becomes of the file, so the map stays small
+        let file_date = ctx.file_dates.remove(src).unwrap_or(*datetime);
+        let explain = explaining(src, args, ctx);
+        if explain {
+            log_info!("explain {:?}: in the batch of {} files from {:?}, dated {}",
+                      src,
+                      batch.files.len(),
+                      batch.dir,
+                      datetime.format("%Y-%m-%d %H:%M:%S"));
+        }
+        let fin = match destination(src, dst, datetime, args, ctx) {
+            Ok(d) => {
+                if explain {
+                    log_info!("explain {:?}: destination {:?}, {}",
+                              src,
+                              d,
+                              explain_destination(src, dst, args, ctx));
+                }
+                merge_target(src, d, dst, args, ctx)
+            }
+            Err(e) => {
+                log_error!("Error: dest script: {:?}: {}", src, e);
+                errors += 1;
+                failed = true;
+                if args.flag_transactional {
+                    break;
+                }
+                continue;
+            }
+        };
+        let size = fs::metadata(src).map(|md| md.len()).unwrap_or(0);
+        let compress = fs::metadata(src)
+                           .map(|md| compresses(&file_mtime(&md), ctx))
+                           .unwrap_or(false);
+        let fin = if compress { zst_path(fin) } else { fin };
+        // shortened first, so that the copy and conflicts are looked for there
+        let (fin, long) = match fit_path(fin, args, ctx) {
+            Ok(fitted) => fitted,
+            Err(()) => {
+                errors += 1;
+                failed = true;
+                if args.flag_transactional {
+                    break;
+                }
+                continue;
+            }
+        };
+        if args.flag_mirror && already_mirrored(src, size, &fin, compress, args, ctx) {
+            log_info!("skip {:?}, already mirrored", src);
+            *ctx.skipped.entry("already organized").or_insert(0) += 1;
+            continue;
+        }
+        if let Some(long) = long {
+            log_warn!("{:?}: path too long, shortened to {:?}", long, fin);
+        }
+        let (fin, conflict) = match resolve_conflict(src, None, fin, args, ctx) {
+            Resolution::Move(fin, conflict) => (fin, conflict),
+            Resolution::Skip => {
+                *ctx.skipped.entry("conflict").or_insert(0) += 1;
+                continue;
+            }
+            Resolution::Fail => {
+                errors += 1;
+                failed = true;
+                if args.flag_transactional {
+                    break;
+                }
+                continue;
+            }
+        };
+        if explain {
+            log_info!("explain {:?}: goes to {:?}", src, fin);
+        }
+        // also with --dry-run, where nothing lands there to be seen, so that
+        // it reports the same conflicts and renames as the real run
+        ctx.claimed.insert(fin.clone());
+        let op = MoveOp {
+            src: src.clone(),
+            dst: fin.clone(),
+            date: *datetime,
+            compress: compress,
+            conflict: conflict,
+        };
+        let shown = ctx.executor.describe(&op);
+        errors += plan_move(&op, "move", &shown, size, args, ctx);
+        if !args.flag_dry_run && ctx.pool.is_some() {
+            let job = workers::Job {
+                force: overwrites(&op, args),
+                op: op,
+                checksum: ctx.manifest.as_ref().map(|_| ctx.hash),
+                size: size,
+                file_date: file_date,
+            };
+            let start = Instant::now();
+            ctx.pool.as_mut().unwrap().send(job);
+            ctx.timings.add(Phase::Move, start);
+            let done = ctx.pool.as_mut().unwrap().finished();
+            errors += moves_done(done, args, ctx);
+        } else if !args.flag_dry_run {
+            // of the content as it was, whatever the executor makes of it
+            let start = Instant::now();
+            let checksum = ctx.manifest
+                              .as_ref()
+                              .and_then(|_| hash::file(src, ctx.hash).ok());
+            ctx.timings.add(Phase::Hash, start);
+            // --transactional: to restore on a rollback, so it must be known
+            let mtime = if args.flag_transactional {
+                match fs::metadata(src) {
+                    Ok(md) => Some(FileTime::from_last_modification_time(&md)),
+                    Err(e) => {
+                        log_error!("Error: mtime of {:?}: {}", src, e);
+                        errors += 1;
+                        failed = true;
+                        break;
+                    }
+                }
+            } else {
+                None
+            };
+            let start = Instant::now();
+            let result = ctx.executor.execute(&op, overwrites(&op, args));
+            ctx.timings.add(Phase::Move, start);
+            if let Err(e) = result {
+                // a --transactional batch can't be completed later
+                if executor::in_use(&e) && !args.flag_transactional {
+                    log_warn!("skip {:?}: in use", src);
+                    ctx.in_use.push(InUse {
+                        op: op,
+                        size: size,
+                        checksum: checksum,
+                        file_date: file_date,
+                    });
+                } else {
+                    log_error!("Error: dest: {:?}: {}", fin, e);
+                    errors += 1;
+                }
+                failed = true;
+                if args.flag_transactional {
+                    break;
+                }
+                continue;
+            }
+            let start = Instant::now();
+            errors += finish_move(&op, false, size, checksum.as_ref(), &file_date, args, ctx);
+            ctx.timings.add(Phase::Move, start);
+            if let Some(mtime) = mtime {
+                moved.push(Moved {
+                    op: op.clone(),
+                    size: size,
+                    mtime: mtime,
+                    checksum: checksum,
+                });
+            }
+        }
+    }
+    if failed && !moved.is_empty() {
+        errors += roll_back(&moved, args, ctx);
+    }
+    errors
+}
+
+// Whether a file last modified at `mtime` is stored compressed, with
+// --compress-older-than
+fn compresses(mtime: &NaiveDateTime, ctx: &Context) -> bool {
+    ctx.compress_before.map_or(false, |before| *mtime < before)
+}
+
+// `path` with .zst added, for a file stored compressed
+fn zst_path(path: PathBuf) -> PathBuf {
+    let mut name = path.into_os_string();
+    name.push(".zst");
+    PathBuf::from(name)
+}
+
+// --long-paths: `fin` shortened to fit, and the path it was shortened from
+// if it had to be. An error is logged if it can't be.
+fn fit_path(fin: PathBuf, args: &Args, ctx: &Context) -> Result<(PathBuf, Option<PathBuf>), ()> {
+    match ctx.long_paths {
+        Some((limits, strategy)) => {
+            match pathlen::fit(&fin, Path::new(&args.flag_output_dir), limits, strategy) {
+                Ok(ref short) if *short == fin => Ok((fin, None)),
+                Ok(short) => Ok((short, Some(fin))),
+                Err(e) => {
+                    log_error!("Error: {:?}: {}", fin, e);
+                    Err(())
+                }
+            }
+        }
+        None => Ok((fin, None)),
+    }
+}
+
+// Reports the move of `op` decided on, `verb` and `shown` being how it is
+// logged: printed in the --plan-format, counted, added to the --save-plan plan
+// and handed to the library. Returns the error count.
+fn plan_move(op: &MoveOp,
+             verb: &str,
+             shown: &str,
+             size: u64,
+             args: &Args,
+             ctx: &mut Context)
+             -> isize {
+    let mut errors: isize = 0;
+    let mode = dest_mode(args, ctx);
+    if ctx.plan_format == plan::Format::Text {
+        log_info!("{} {} {}",
+                  verb,
+                  log::paint(&format!("{:?}", op.src), Paint::Source),
+                  log::paint(shown, Paint::Destination));
+    } else {
+        plan::print(op, mode, ctx.plan_format);
+    }
+    ctx.planned += 1;
+    ctx.bytes_planned += size;
+    let planned = plan::Move::new(op, mode);
+    if let Some(ref mut plan) = ctx.plan {
+        if let Err(e) = plan.push(&planned) {
+            log_error!("Error: save plan: {}", e);
+            errors += 1;
+        }
+    }
+    if let Some(ref mut on_plan) = ctx.on_plan {
+        on_plan(planned);
+    }
+    errors
+}
+
+// What follows a successful move of `op`, or its extraction from an archive
+// if `extracted`: --fsync, the counts, --set-mtime, the manifest and
+// --post-move-cmd. Returns the error count.
+fn finish_move(op: &MoveOp,
+               extracted: bool,
+               size: u64,
+               checksum: Option<&Checksum>,
+               file_date: &NaiveDateTime,
+               args: &Args,
+               ctx: &mut Context)
+               -> isize {
+    let mut errors: isize = 0;
+    if args.flag_fsync {
+        let output_dir = Path::new(&args.flag_output_dir);
+        // nothing left the folder of an archive member
+        let src = if extracted { None } else { Some(op.src.as_path()) };
+        if let Err(e) = executor::sync_move(src, &op.dst, output_dir) {
+            log_error!("Error: fsync {:?}: {}", op.dst, e);
+            errors += 1;
+        }
+    }
+    ctx.organized += 1;
+    ctx.bytes += size;
+    if args.flag_set_mtime.is_some() {
+        let ft = to_filetime(file_date);
+        if let Err(e) = filetime::set_file_times(&op.dst, ft, ft) {
+            log_error!("Error: set mtime of {:?}: {}", op.dst, e);
+            errors += 1;
+        }
+    }
+    if let Some(ref mut manifest) = ctx.manifest {
+        let recorded = if extracted {
+            manifest.record_extract(op, size, checksum)
+        } else {
+            manifest.record(op, size, checksum)
+        };
+        if let Err(e) = recorded {
+            log_error!("Error: manifest: {}", e);
+            errors += 1;
+        }
+    }
+    if let Some(ref cmd) = args.flag_post_move_cmd {
+        if let Err(e) = run_hook(cmd, &op.src, &op.dst, &op.date) {
+            log_error!("Error: post-move command for {:?}: {}", op.dst, e);
+            errors += 1;
+        }
+    }
+    errors
+}
+
+// Picks up moves finished by the --io-workers pool. Returns the error count.
+fn moves_done(done: Vec<workers::Done>, args: &Args, ctx: &mut Context) -> isize {
+    let mut errors: isize = 0;
+    for d in done {
+        let job = d.job;
+        match d.result {
+            // dropped when the run was interrupted
+            None => {}
+            Some(Ok(())) => {
+                let checksum = d.checksum.as_ref();
+                errors += finish_move(&job.op,
+                                      false,
+                                      job.size,
+                                      checksum,
+                                      &job.file_date,
+                                      args,
+                                      ctx);
+            }
+            Some(Err(ref e)) if executor::in_use(e) => {
+                log_warn!("skip {:?}: in use", job.op.src);
+                ctx.in_use.push(InUse {
+                    op: job.op,
+                    size: job.size,
+                    checksum: d.checksum,
+                    file_date: job.file_date,
+                });
+            }
+            Some(Err(e)) => {
+                log_error!("Error: dest: {:?}: {}", job.op.dst, e);
+                errors += 1;
+            }
+        }
+    }
+    errors
+}
+
+// For --retry-in-use: tries the moves skipped because another process had
+// the file open once more, at the end of the run. Those still in use stay
+// skipped. Returns the error count.
+fn retry_in_use(args: &Args, ctx: &mut Context) -> isize {
+    let mut errors: isize = 0;
+    let skipped = mem::replace(&mut ctx.in_use, vec![]);
+    for file in skipped {
+        if interrupted() {
+            ctx.in_use.push(file);
+            continue;
+        }
+        match ctx.executor.execute(&file.op, overwrites(&file.op, args)) {
+            Ok(()) => {
+                log_info!("move {:?} {:?} (retried)", file.op.src, file.op.dst);
+                errors += finish_move(&file.op,
+                                      false,
+                                      file.size,
+                                      file.checksum.as_ref(),
+                                      &file.file_date,
+                                      args,
+                                      ctx);
+            }
+            Err(ref e) if executor::in_use(e) => {
+                log_warn!("skip {:?}: still in use", file.op.src);
+                ctx.in_use.push(file);
+            }
+            Err(e) => {
+                log_error!("Error: dest: {:?}: {}", file.op.dst, e);
+                errors += 1;
+            }
+        }
+    }
+    errors
+}
+
+// a move skipped because another process had the file open, with what to
+// record if --retry-in-use gets it through
+struct InUse {
+    op: MoveOp,
+    size: u64,
+    checksum: Option<Checksum>,
+    file_date: NaiveDateTime,
+}
+
+// a file moved by a --transactional batch, and what to restore if it is
+// moved back
+struct Moved {
+    op: MoveOp,
+    size: u64,
+    mtime: FileTime,
+    checksum: Option<Checksum>,
+}
+
+// Moves the files of a failed --transactional batch back, last moved first,
+// with their mtimes, and drops the folders that leaves empty. Returns the
+// error count.
+fn roll_back(moved: &[Moved], args: &Args, ctx: &mut Context) -> isize {
+    let mut errors: isize = 0;
+    let output_dir = Path::new(&args.flag_output_dir);
+    for m in moved.iter().rev() {
+        let back = MoveOp {
+            src: m.op.dst.clone(),
+            dst: m.op.src.clone(),
+            date: m.op.date,
+            compress: false,
+            conflict: None,
+        };
+        log_warn!("roll back {:?} to {:?}", back.src, back.dst);
+        if let Err(e) = LocalExecutor.execute(&back, false) {
+            log_error!("Error: roll back {:?}: {}", back.src, e);
+            errors += 1;
+            continue;
+        }
+        ctx.organized -= 1;
+        ctx.bytes -= m.size;
+        if let Err(e) = filetime::set_file_times(&back.dst, m.mtime, m.mtime) {
+            log_error!("Error: set mtime of {:?}: {}", back.dst, e);
+            errors += 1;
+        }
+        if let Some(ref mut manifest) = ctx.manifest {
+            if let Err(e) = manifest.record_rollback(&back, m.size, m.checksum.as_ref()) {
+                log_error!("Error: manifest: {}", e);
+                errors += 1;
+            }
+        }
+        // remove_dir fails on the first folder that isn't empty
+        let mut dir = back.src.parent();
+        while let Some(d) = dir {
+            if d == output_dir || !d.starts_with(output_dir) || fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+    errors
+}
+
+// What the --on-conflict policy made of a destination
+enum Resolution {
+    // to the path given, with the policy applied if it was taken
+    Move(PathBuf, Option<&'static str>),
+    // left where it is, as the policy says
+    Skip,
+    // an error was reported
+    Fail,
+}
+
+// Applies the --on-conflict policy if `fin` exists, or if an earlier file of
+// this run goes there, which is never overwritten. Only the executor can tell
+// for remote outputs, which are therefore left to it. `newer` compares
+// `src_mtime`, or the mtime of `src` if not given, as for archive members.
+fn resolve_conflict(src: &Path,
+                    src_mtime: Option<NaiveDateTime>,
+                    fin: PathBuf,
+                    args: &Args,
+                    ctx: &mut Context)
+                    -> Resolution {
+    let taken_by_run = ctx.claimed.contains(&fin);
+    if !taken_by_run && !fin.exists() {
+        return Resolution::Move(fin, None);
+    }
+    let claimed = |path: &Path| ctx.claimed.contains(path);
+    let (resolved, action) = match &args.flag_on_conflict[..] {
+        // keep the path it would have had, below the quarantine folder
+        "quarantine" => {
+            let output_dir = Path::new(&args.flag_output_dir);
+            let mut quarantined = output_dir.join("_conflicts")
+                                            .join(fin.strip_prefix(output_dir).unwrap_or(&fin));
+            if quarantined.exists() || claimed(&quarantined) {
+                quarantined = free_name(&quarantined, &claimed);
+            }
+            log_warn!("{}",
+                      tr!("conflict-quarantined",
+                          "conflict { $dest }, quarantined as { $quarantined }",
+                          dest = format!("{:?}", fin),
+                          quarantined = format!("{:?}", quarantined)));
+            (Some(quarantined), "quarantine")
+        }
+        "rename" => {
+            let renamed = free_name(&fin, &claimed);
+            log_warn!("{}",
+                      tr!("conflict-renamed",
+                          "conflict { $dest }, renamed to { $renamed }",
+                          dest = format!("{:?}", fin),
+                          renamed = format!("{:?}", renamed)));
+            (Some(renamed), "rename")
+        }
+        "skip" => (None, "skip"),
+        "overwrite" if !taken_by_run => (Some(fin.clone()), "overwrite"),
+        // strictly newer by mtime, like cp and rsync; the date sources would
+        // date the destination by the folder it is in
+        "newer" if !taken_by_run => {
+            let mtime = |path: &Path| fs::metadata(path).ok().map(|md| file_mtime(&md));
+            let newer = match (src_mtime.or_else(|| mtime(src)), mtime(&fin)) {
+                (Some(file), Some(existing)) => file > existing,
+                (_, None) => true,
+                (None, _) => false,
+            };
+            if newer {
+                (Some(fin.clone()), "newer")
+            } else {
+                (None, "newer")
+            }
+        }
+        "ask" if !taken_by_run => {
+            if ask_overwrite(src, &fin, args, ctx) {
+                (Some(fin.clone()), "ask")
+            } else {
+                (None, "ask")
+            }
+        }
+        _ if taken_by_run => {
+            log_error!("{}",
+                       tr!("conflict-same-destination",
+                           "Error: conflict: { $src } goes to { $dest } like an earlier file",
+                           src = format!("{:?}", src),
+                           dest = format!("{:?}", fin)));
+            return Resolution::Fail;
+        }
+        // fail: the executor reports it
+        _ => return Resolution::Move(fin, None),
+    };
+    ctx.conflicts.push(Conflict {
+        src: src.to_string_lossy().into_owned(),
+        dst: fin.to_string_lossy().into_owned(),
+        action: action.to_string(),
+        moved_to: resolved.as_ref().map(|p| p.to_string_lossy().into_owned()),
+    });
+    match resolved {
+        Some(path) => Resolution::Move(path, Some(action)),
+        None => {
+            log_info!("{}",
+                      tr!("conflict-skipped",
+                          "skip { $src }, { $dest } exists",
+                          src = format!("{:?}", src),
+                          dest = format!("{:?}", fin)));
+            Resolution::Skip
+        }
+    }
+}
+
+// --on-conflict=ask: whether to overwrite `dst`; the answers to all are
+// remembered for the rest of the run. Nothing is asked with --dry-run, which
+// plans the move.
+fn ask_overwrite(src: &Path, dst: &Path, args: &Args, ctx: &mut Context) -> bool {
+    if let Some(all) = ctx.overwrite_all {
+        return all;
+    }
+    if args.flag_dry_run {
+        return true;
+    }
+    loop {
+        eprint!("{}",
+                tr!("ask-overwrite",
+                    "overwrite { $dest } with { $src }? [y]es, [n]o, [a]ll, n[o]ne: ",
+                    dest = format!("{:?}", dst),
+                    src = format!("{:?}", src)));
+        let mut answer = String::new();
+        match io::stdin().read_line(&mut answer) {
+            // end of input: nothing more will be overwritten
+            Ok(0) | Err(_) => {
+                ctx.overwrite_all = Some(false);
+                return false;
+            }
+            Ok(_) => {}
+        }
+        match answer.trim() {
+            "y" | "yes" => return true,
+            "n" | "no" | "" => return false,
+            "a" | "all" => {
+                ctx.overwrite_all = Some(true);
+                return true;
+            }
+            "o" | "none" => {
+                ctx.overwrite_all = Some(false);
+                return false;
+            }
+            _ => {}
+        }
+    }
+}
+
+// --merge: `fin` in the folder chosen for its layout folder, which is `fin`
+// without `rel`, or without the file name for a layout that names files. A
+// folder that has files in it when first seen is merged into, or replaced by
+// a numbered one beside it, for the rest of the run.
+fn merge_target(src: &Path,
+                fin: PathBuf,
+                rel: &Path,
+                args: &Args,
+                ctx: &mut Context)
+                -> PathBuf {
+    if args.flag_merge == "always" {
+        return fin;
+    }
+    let depth = if names_file(src, args, ctx) { 1 } else { rel.components().count() };
+    let folder = match fin.ancestors().nth(depth) {
+        Some(folder) => folder.to_path_buf(),
+        None => return fin,
+    };
+    if !ctx.merged.contains_key(&folder) {
+        let used = fs::read_dir(&folder).map(|mut d| d.next().is_some()).unwrap_or(false);
+        let merge = !used || args.flag_merge == "ask" && ask_merge(&folder, args, ctx);
+        let target = if merge {
+            folder.clone()
+        } else {
+            let taken: HashSet<&PathBuf> = ctx.merged.values().collect();
+            let mut n = 2;
+            loop {
+                let name = folder.file_name().unwrap_or_default().to_string_lossy();
+                let candidate = folder.with_file_name(format!("{}_{}", name, n));
+                if !candidate.exists() && !taken.contains(&candidate) {
+                    break candidate;
+                }
+                n += 1;
+            }
+        };
+        if target != folder {
+            log_info!("{}",
+                      tr!("merge-suffix",
+                          "{ $folder } already has files, using { $target }",
+                          folder = format!("{:?}", folder),
+                          target = format!("{:?}", target)));
+        }
+        ctx.merged.insert(folder.clone(), target);
+    }
+    let target = &ctx.merged[&folder];
+    target.join(fin.strip_prefix(&folder).unwrap_or(&fin))
+}
+
+// --merge=ask: whether to merge into `folder`, or else use a new one
+fn ask_merge(folder: &Path, args: &Args, ctx: &mut Context) -> bool {
+    if let Some(all) = ctx.merge_all {
+        return all;
+    }
+    if args.flag_dry_run {
+        return true;
+    }
+    loop {
+        eprint!("{}",
+                tr!("ask-merge",
+                    "{ $folder } already has files; merge into it? [y]es, [n]ew folder, \
+                     [a]ll, n[o]ne: ",
+                    folder = format!("{:?}", folder)));
+        let mut answer = String::new();
+        match io::stdin().read_line(&mut answer) {
+            // end of input: nothing more is merged into
+            Ok(0) | Err(_) => {
+                ctx.merge_all = Some(false);
+                return false;
+            }
+            Ok(_) => {}
+        }
+        match answer.trim() {
+            "y" | "yes" | "" => return true,
+            "n" | "no" => return false,
+            "a" | "all" => {
+                ctx.merge_all = Some(true);
+                return true;
+            }
+            "o" | "none" => {
+                ctx.merge_all = Some(false);
+                return false;
+            }
+            _ => {}
+        }
+    }
+}
+
+// Whether the executor may replace the destination of `op`
+fn overwrites(op: &MoveOp, args: &Args) -> bool {
+    args.flag_on_conflict == "overwrite" || op.conflict == Some("newer") ||
+    op.conflict == Some("ask")
+}
+
+// Whether --mirror copied `src` before: as the manifest records, or as found
+// at `dst`. A copy of the same size and mtime is taken to be one without
+// reading it, unless --checksum; one of the same size but another mtime is
+// compared. Compressed copies only go by their mtime. A source changed since
+// it was copied is copied again.
+fn already_mirrored(src: &Path,
+                    size: u64,
+                    dst: &Path,
+                    compress: bool,
+                    args: &Args,
+                    ctx: &Context)
+                    -> bool {
+    if let Some(&(copied_size, ref mtime, ref checksum)) = ctx.mirrored.get(src) {
+        let unchanged = if args.flag_checksum {
+            checksum.as_ref().map_or(false, |c| {
+                hash::file(src, c.algorithm()).map(|now| now == *c).unwrap_or(false)
+            })
+        } else {
+            mtime.is_some() && fs::metadata(src).ok().map(|md| file_mtime(&md)) == *mtime
+        };
+        if copied_size == size && unchanged {
+            return true;
+        }
+    }
+    let (s, d) = match (fs::metadata(src), fs::metadata(dst)) {
+        (Ok(s), Ok(d)) => (s, d),
+        _ => return false,
+    };
+    let same_mtime = file_mtime(&s) == file_mtime(&d);
+    if compress {
+        same_mtime
+    } else if s.len() != d.len() {
+        false
+    } else if same_mtime && !args.flag_checksum {
+        true
+    } else {
+        dedupe::same_content(src, dst).unwrap_or(false)
+    }
+}
+
+// The first of NAME_1.EXT, NAME_2.EXT, ... next to `path` that doesn't exist
+// and that no other file of the run goes to
+fn free_name(path: &Path, claimed: &dyn Fn(&Path) -> bool) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let ext = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    let mut n = 1;
+    loop {
+        let candidate = path.with_file_name(format!("{}_{}{}", stem, n, ext));
+        if !candidate.exists() && !claimed(&candidate) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+// Makes the batch of `files`, dated by the oldest or newest of their `dates`,
+// or with --cluster-events one for every run of files whose dates are at most
+// the gap apart. Each of those goes to a numbered folder of its own, which is
+// prepended to the files' relative paths.
+fn make_batches(mut files: Vec<(PathBuf, PathBuf)>,
+                dates: Vec<NaiveDateTime>,
+                policy: AgePolicy,
+                args: &Args,
+                ctx: &mut Context)
+                -> Vec<Batch> {
+    if args.flag_burst {
+        mark_bursts(&mut files, &dates);
+    }
+    let gap = match ctx.cluster_gap {
+        Some(gap) => gap,
+        None => return dated_batches(files, dates, policy, None, args, ctx),
+    };
+    let mut files: Vec<_> = dates.into_iter().zip(files).collect();
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut clusters: Vec<Vec<(NaiveDateTime, (PathBuf, PathBuf))>> = vec![];
+    for file in files {
+        let split = match clusters.last().and_then(|c| c.last()) {
+            Some(last) => file.0.signed_duration_since(last.0) > gap,
+            None => true,
+        };
+        if split {
+            clusters.push(vec![]);
+        }
+        clusters.last_mut().unwrap().push(file);
+    }
+    let mut batches = vec![];
+    for cluster in clusters {
+        let event = {
+            let number = ctx.events.entry(cluster[0].0.date()).or_insert(0);
+            *number += 1;
+            format!("{}_event-{:02}", cluster[0].0.format("%Y-%m-%d"), number)
+        };
+        let (dates, files) = cluster.into_iter().unzip();
+        batches.extend(dated_batches(files, dates, policy, Some(Path::new(&event)), args, ctx));
+    }
+    batches
+}
+
+// The batch of `files`, dated by the oldest or newest of their `dates`, below
+// `folder` if one is given; and with --max-batch-spread, a batch of its own
+// for each of the outliers taken out of it. --warn-batch-spread looks at what
+// is left.
+fn dated_batches(mut files: Vec<(PathBuf, PathBuf)>,
+                 mut dates: Vec<NaiveDateTime>,
+                 policy: AgePolicy,
+                 folder: Option<&Path>,
+                 args: &Args,
+                 ctx: &mut Context)
+                 -> Vec<Batch> {
+    let round_to = ctx.round_to;
+    let round = |date: NaiveDateTime| match round_to {
+        Some(period) => dates::round_to(&date, period),
+        None => date,
+    };
+    let outliers = match ctx.max_spread {
+        Some(spread) => split_outliers(&mut files, &mut dates, spread, args, ctx),
+        None => vec![],
+    };
+    if let Some(spread) = ctx.warn_spread {
+        warn_spread(&files, &dates, spread);
+    }
+    if let Some(folder) = folder {
+        for file in &mut files {
+            file.1 = folder.join(&file.1);
+        }
+    }
+    let datetime = match policy {
+        Newest => dates.iter().max(),
+        _ => dates.iter().min(),
+    };
+    let mut batches: Vec<Batch> = match datetime {
+        Some(&datetime) => Batch::new(files, round(datetime)).into_iter().collect(),
+        None => vec![],
+    };
+    // on their own, outside of the event they were taken out of
+    for (file, date) in outliers {
+        batches.extend(Batch::new(vec![file], round(date)));
+    }
+    batches
+}
+
+// The highest event number of each day among the event folders already below
+// `output`, so that a later run goes on numbering them rather than merging
+// new events into old ones.
+fn existing_events(output: &Path) -> HashMap<NaiveDate, usize> {
+    let mut events = HashMap::new();
+    let folders = WalkDir::new(output)
+                      .into_iter()
+                      .filter_map(Result::ok)
+                      .filter(|e| e.file_type().is_dir());
+    for entry in folders {
+        let name = entry.file_name().to_string_lossy();
+        let mut fields = name.splitn(2, "_event-");
+        let date = fields.next().and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok());
+        let number = fields.next().and_then(|n| n.parse::<usize>().ok());
+        if let (Some(date), Some(number)) = (date, number) {
+            let last = events.entry(date).or_insert(0);
+            *last = cmp::max(*last, number);
+        }
+    }
+    events
+}
+
+// --warn-batch-spread: warns when the files of a batch are more than `spread`
+// apart, since a single date can then only be right for a few of them
+fn warn_spread(files: &[(PathBuf, PathBuf)], dates: &[NaiveDateTime], spread: Duration) {
+    let (oldest, newest) = match (dates.iter().min(), dates.iter().max()) {
+        (Some(&oldest), Some(&newest)) => (oldest, newest),
+        _ => return,
+    };
+    if newest.signed_duration_since(oldest) > spread {
+        let dir = files[0].0.parent().unwrap_or(Path::new(""));
+        log_warn!("{:?}: batch spans {} to {}, so most of its files may be misfiled; \
+                   see --max-batch-spread and --cluster-events",
+                  dir,
+                  oldest.format("%Y-%m-%d"),
+                  newest.format("%Y-%m-%d"));
+    }
+}
+
+// --max-batch-spread: takes the files dated more than `spread` from the
+// median date of the batch out of it, with their dates; the median, as the
+// oldest or newest date may be the outlier itself. With --outliers=quarantine,
+// they are remembered for `destination`.
+fn split_outliers(files: &mut Vec<(PathBuf, PathBuf)>,
+                  dates: &mut Vec<NaiveDateTime>,
+                  spread: Duration,
+                  args: &Args,
+                  ctx: &mut Context)
+                  -> Vec<((PathBuf, PathBuf), NaiveDateTime)> {
+    let mut sorted = dates.clone();
+    sorted.sort();
+    let median = match sorted.get(sorted.len() / 2) {
+        Some(&median) => median,
+        None => return vec![],
+    };
+    let (mut kept, mut kept_dates, mut outliers) = (vec![], vec![], vec![]);
+    for (file, date) in files.drain(..).zip(dates.drain(..)) {
+        let off = date.signed_duration_since(median);
+        if off > spread || -off > spread {
+            let quarantine = args.flag_outliers == "quarantine";
+            log_info!("{:?} is dated {}, {} days from the rest of its batch, {}",
+                      file.0,
+                      date.format("%Y-%m-%d"),
+                      off.num_days().abs(),
+                      if quarantine { "quarantined" } else { "on its own" });
+            if quarantine {
+                ctx.outliers.insert(file.0.clone());
+            }
+            outliers.push((file, date));
+        } else {
+            kept.push(file);
+            kept_dates.push(date);
+        }
+    }
+    *files = kept;
+    *dates = kept_dates;
+    outliers
+}
+
+// The options that can't go together, which `organize`, `plan` and `stats`
+// take in any combination
+fn check_exclusive(args: &Args) -> Result<(), String> {
+    let shortened = [args.flag_strip > 0,
+                     args.flag_strip_prefix.is_some(),
+                     args.flag_keep_components.is_some()];
+    if shortened.iter().filter(|&&b| b).count() > 1 {
+        return Err("only one of --strip, --strip-prefix and --keep-components can be given"
+                       .to_string());
+    }
+    if args.flag_flatten && (args.flag_preserve_structure || shortened.contains(&true)) {
+        return Err("--flatten can't be combined with --preserve-structure, --strip, \
+                    --strip-prefix or --keep-components"
+                       .to_string());
+    }
+    let layout = args.flag_layout != "{year}" || args.flag_group_by.is_some() ||
+                 args.flag_append_source_name || args.flag_keep_root_name;
+    if args.flag_dest_script.is_some() && layout {
+        return Err("--layout, --group-by, --append-source-name and --keep-root-name can't be \
+                    combined with --dest-script"
+                       .to_string());
+    }
+    let targets = [args.flag_executor.is_some(),
+                   args.flag_archive.is_some(),
+                   args.flag_compress_older_than.is_some()];
+    if targets.iter().filter(|&&b| b).count() > 1 {
+        return Err("only one of --executor, --archive and --compress-older-than can be given"
+                       .to_string());
+    }
+    if args.flag_archive_per_month && args.flag_archive.is_none() {
+        return Err("--archive-per-month needs --archive".to_string());
+    }
+    // `apply` makes every move as a local rename
+    if args.flag_save_plan.is_some() &&
+       (args.flag_compress_older_than.is_some() || args.flag_archive.is_some() ||
+        args.flag_mirror || args.flag_executor.is_some() || args.flag_remove_source_after_verify ||
+        is_remote(&args.flag_output_dir)) {
+        return Err("plans can't be saved with --compress-older-than, --archive, --mirror, \
+                    --executor, --remove-source-after-verify or a remote output"
+                       .to_string());
+    }
+    if args.flag_watch.is_none() &&
+       (args.flag_metrics.is_some() || args.flag_control_socket.is_some() ||
+        args.flag_http.is_some()) {
+        return Err("--metrics, --control-socket and --http need --watch".to_string());
+    }
+    if args.flag_plan_format != "text" &&
+       (!args.flag_dry_run || args.flag_tui || args.flag_stats_only) {
+        return Err("--plan-format needs --dry-run, and no --tui or --stats-only".to_string());
+    }
+    Ok(())
+}
+
+// The source directories without those given twice or inside another one,
+// whose files would otherwise be seen twice and, the second time, conflict
+// with themselves. Paths are compared once resolved, so `photos` and
+// `./photos/` are the same; the first given is kept.
+fn distinct_roots(dirs: &[String]) -> Vec<String> {
+    let resolve = |d: &String| fs::canonicalize(d).unwrap_or_else(|_| PathBuf::from(d));
+    let resolved: Vec<PathBuf> = dirs.iter().map(resolve).collect();
+    let mut kept = vec![];
+    for (i, dir) in dirs.iter().enumerate() {
+        let same = (0..i).find(|&j| resolved[j] == resolved[i]);
+        let outer = (0..dirs.len()).find(|&j| {
+            resolved[j] != resolved[i] && resolved[i].starts_with(&resolved[j])
+        });
+        match (same, outer) {
+            (Some(j), _) => log_warn!("{:?} is the same as {:?}, skipped", dir, dirs[j]),
+            (_, Some(j)) => log_warn!("{:?} is inside {:?}, skipped", dir, dirs[j]),
+            _ => kept.push(dir.clone()),
+        }
+    }
+    kept
+}
+
+// The entries below a source directory, sorted so that batches and output
+// don't depend on readdir order. Links, which include junctions on Windows,
+// are walked through, or left out with --symlinks=skip.
+fn walk_source(dir: &str, args: &Args) -> Box<dyn Iterator<Item = DirEntry> + Send> {
+    let follow = args.flag_symlinks == "follow";
+    let walker = WalkDir::new(dir)
+                     .follow_links(follow)
+                     .sort_by(|a, b| a.file_name().cmp(b.file_name()));
+    Box::new(walker.into_iter()
+                   .filter_map(Result::ok)
+                   .filter(move |e| follow || !e.path_is_symlink())
+                   .filter(|e| !executor::is_temp(e.path()) && !lock::is_lock(e.path())))
+}
+
+// folder entries read ahead of the run at most
+const WALK_QUEUE: usize = 4096;
+
+// The entries of `walk_source`, read on a thread of their own and handed over
+// through a bounded queue, so that reading folders goes on while files are
+// moved, without holding more than WALK_QUEUE entries of a huge tree. The
+// thread stops once the entries are dropped.
+fn walk_queued(dir: &str, args: &Args) -> Box<dyn Iterator<Item = DirEntry>> {
+    let (entries, queue) = mpsc::sync_channel(WALK_QUEUE);
+    let walker = walk_source(dir, args);
+    spawn_for_run(move || {
+        for entry in walker {
+            if entries.send(entry).is_err() {
+                break;
+            }
+        }
+    });
+    Box::new(queue.into_iter())
+}
+
+// The path of `path` below its destination folder: with the leftmost
+// --strip directories or the --strip-prefix removed, or only the last
+// --keep-components directories kept, or none with --flatten.
+fn dest_rel(path: &Path, args: &Args) -> PathBuf {
+    if args.flag_flatten {
+        return path.file_name().map(PathBuf::from).unwrap_or_default();
+    }
+    if let Some(n) = args.flag_keep_components {
+        // only names, so a kept `..` or root can't lead out of the output
+        let components: Vec<_> = path.components()
+                                     .filter(|c| match *c {
+                                         Component::Normal(_) => true,
+                                         _ => false,
+                                     })
+                                     .collect();
+        // the file name, and up to n directories above it
+        let keep = cmp::min(n + 1, components.len());
+        return components[components.len() - keep..].iter().collect();
+    }
+    if let Some(ref prefix) = args.flag_strip_prefix {
+        // compared by component, so `./photos` and `photos/` strip `photos/a`
+        let mut components = path.components().filter(|c| *c != Component::CurDir);
+        let matched = Path::new(prefix)
+            .components()
+            .filter(|c| *c != Component::CurDir)
+            .all(|c| components.next() == Some(c));
+        if matched {
+            return components.collect();
+        }
+        return path.to_path_buf();
+    }
+    // strip leftmost directories if neccessary
+    let mut components = path.components();
+    for _ in 0..args.flag_strip {
+        components.next();
+    }
+    components.as_path().to_path_buf()
+}
+
+// How destinations are made from source paths, as recorded in saved plans:
+// below the layout folder by their (stripped) path, or by name alone with
+// --flatten; or entirely by a layout naming the file, or by a dest script.
+fn dest_mode(args: &Args, ctx: &Context) -> &'static str {
+    if ctx.script.is_some() {
+        "script"
+    } else if ctx.layout.names_file() {
+        "layout"
+    } else if args.flag_flatten {
+        "flatten"
+    } else {
+        "structure"
+    }
+}
+
+// The date of `path` from the first --date-override matching it, or else the
+// date sources, those of its .organize.toml if that sets any, if any of them
+// has one. `root` is the source directory.
+fn source_date(path: &Path,
+               root: &Path,
+               md: &fs::Metadata,
+               ctx: &Context)
+               -> Option<NaiveDateTime> {
+    dated_by(path, root, md, ctx).map(|(date, _)| date)
+}
+
+// source_date, and for --explain where the date comes from
+fn dated_by(path: &Path,
+            root: &Path,
+            md: &fs::Metadata,
+            ctx: &Context)
+            -> Option<(NaiveDateTime, String)> {
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    let config = ctx.configs.for_dir(path.parent().unwrap_or(root), root);
+    let sources = config.as_ref()
+                        .and_then(|c| c.date_sources.as_ref())
+                        .unwrap_or(&ctx.date_sources);
+    match dates::override_for(&ctx.date_overrides, rel) {
+        Some(source) => {
+            dates::date_by(source, path, root, md)
+                .map(|date| (date, format!("{}, by --date-override", source.name())))
+        }
+        None => {
+            sources.iter()
+                   .filter_map(|&source| {
+                       dates::date_by(source, path, root, md)
+                           .map(|date| (date, source.name().to_string()))
+                   })
+                   .next()
+        }
+    }
+}
+
+// whether to explain what becomes of `path`, see --explain
+fn explaining(path: &Path, args: &Args, ctx: &Context) -> bool {
+    args.flag_verbose >= 3 ||
+    !ctx.explain.is_empty() &&
+    fs::canonicalize(path).map_or(false, |p| ctx.explain.contains(&p))
+}
+
+// files shot less than this apart are part of a burst
+const BURST_SPACING_SECS: i64 = 60;
+// and it takes this many to make one
+const BURST_MIN_FRAMES: usize = 3;
+
+// --burst: moves the frames of continuous-shooting sequences into a folder
+// named after the first one, next to where they would go otherwise
+fn mark_bursts(files: &mut [(PathBuf, PathBuf)], dates: &[NaiveDateTime]) {
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by_key(|&i| dates[i]);
+    let mut start = 0;
+    for end in 1..order.len() + 1 {
+        let continues = end < order.len() &&
+                        dates[order[end]].signed_duration_since(dates[order[end - 1]]) <
+                        Duration::seconds(BURST_SPACING_SECS);
+        if continues {
+            continue;
+        }
+        if end - start >= BURST_MIN_FRAMES {
+            let folder = dates[order[start]].format("burst-%Y%m%d-%H%M%S").to_string();
+            for &i in &order[start..end] {
+                let rel = &mut files[i].1;
+                let name = rel.file_name().unwrap_or_default().to_os_string();
+                let dir = rel.parent().unwrap_or(Path::new("")).join(&folder);
+                *rel = dir.join(name);
+            }
+        }
+        start = end;
+    }
+}
+
+// Counts a file left in place for `reason`, and with -vv reports it; for the
+// reasons not reported otherwise
+fn skip_file(path: &Path, reason: &'static str, args: &Args, ctx: &mut Context) {
+    *ctx.skipped.entry(reason).or_insert(0) += 1;
+    if args.flag_verbose >= 2 || explaining(path, args, ctx) {
+        log_info!("skip {:?}: {}", path, reason);
+    }
+}
+
+// Walks `dir` and hands every batch to `handle`, which returns its error
+// count. Returns the total error count.
+fn process_dir(dir: &str,
+               policy: AgePolicy,
+               args: &Args,
+               ctx: &mut Context,
+               handle: &mut dyn FnMut(Batch, &mut Context) -> isize)
+               -> isize {
+    let wanted = patterns::filter(args);
+    let included = patterns::included(args);
+    let excluded = patterns::excluded(args);
+    let rel = |path: &Path| path.strip_prefix(dir).unwrap_or(path).to_path_buf();
+
+    // the batch to move
+    let mut curfiles: Vec<(PathBuf, PathBuf)> = vec![];
+    // and the date of each file, the oldest or newest of which dates the batch
+    let mut curdates: Vec<NaiveDateTime> = vec![];
+
+    let mut errors: isize = 0;
+
+    // --keep-recent: the files to leave alone, newest first
+    let keep: HashSet<PathBuf> = match args.flag_keep_recent {
+        Some(n) => {
+            // the oldest on top, dropped as newer ones come in
+            let mut files: BinaryHeap<cmp::Reverse<(NaiveDateTime, PathBuf)>> = BinaryHeap::new();
+            for entry in walk_source(dir, args) {
+                if entry.file_type().is_file() && wanted(&rel(entry.path())) {
+                    if let Ok(md) = entry.metadata() {
+                        files.push(cmp::Reverse((file_mtime(&md), entry.path().to_path_buf())));
+                        if files.len() > n {
+                            files.pop();
+                        }
+                    }
+                }
+            }
+            files.into_iter().map(|cmp::Reverse((_, path))| path).collect()
+        }
+        None => HashSet::new(),
+    };
+
+    let mut walker = walk_queued(dir, args);
+    loop {
+        let start = Instant::now();
+        let entry = match walker.next() {
+            Some(entry) => entry,
+            None => break,
+        };
+        ctx.timings.add(Phase::Walk, start);
+        if interrupted() {
+            break;
+        }
+        if entry.path().is_file() {
+            if entry.file_name() == config::FILE_NAME {
+                continue;
+            }
+            let below = rel(entry.path());
+            ctx.pattern_usage.record(&below);
+            for (i, rule) in ctx.date_overrides.iter().enumerate() {
+                if rule.matches(&below) {
+                    ctx.override_hits[i] += 1;
+                }
+            }
+            let is_archive = args.flag_expand_archives && expand::is_archive(entry.path());
+            let folder = entry.path().parent().unwrap_or(Path::new(dir));
+            let config = ctx.configs.for_dir(folder, Path::new(dir));
+            if config.as_ref().map_or(false, |c| c.broken) {
+                skip_file(entry.path(), "broken config", args, ctx);
+                continue;
+            }
+            let matched = config.as_ref()
+                                .and_then(|c| c.wanted(entry.path()))
+                                .unwrap_or_else(|| wanted(&rel(entry.path())));
+            let explain = explaining(entry.path(), args, ctx);
+            if explain {
+                let patterns = match config.as_ref().and_then(|c| c.wanted(entry.path())) {
+                    Some(true) => "taken by the patterns of .organize.toml".to_string(),
+                    Some(false) => "left out by the patterns of .organize.toml".to_string(),
+                    None => patterns::explain(args, &rel(entry.path())),
+                };
+                log_info!("explain {:?}: {}", entry.path(), patterns);
+            }
+            let matched = if is_archive {
+                // its members are matched one by one
+                config.as_ref().and_then(|c| c.wanted(entry.path())).unwrap_or(true) &&
+                !excluded(&below)
+            } else {
+                matched
+            };
+            if !matched {
+                let reason = match config.as_ref().and_then(|c| c.wanted(entry.path())) {
+                    None if included(&rel(entry.path())) => "excluded",
+                    _ => "pattern mismatch",
+                };
+                skip_file(entry.path(), reason, args, ctx);
+                continue;
+            }
+            if keep.contains(entry.path()) {
+                skip_file(entry.path(), "too new", args, ctx);
+                continue;
+            }
+            if args.flag_skip_hidden && below.components().any(|c| match c {
+                Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+                _ => false,
+            }) {
+                skip_file(entry.path(), "hidden", args, ctx);
+                continue;
+            }
+            // get current mtime
+            let start = Instant::now();
+            let md = fs::metadata(&*entry.path().to_string_lossy()).unwrap();
+            if ctx.min_size.map_or(false, |min| md.len() < min) {
+                skip_file(entry.path(), "too small", args, ctx);
+                continue;
+            }
+            // dated by its source if it has a date there, else by its mtime
+            let (dt, source) = dated_by(entry.path(), Path::new(dir), &md, ctx).unwrap_or_else(|| {
+                (file_mtime(&md), "mtime, as no date source has one".to_string())
+            });
+            if dt > now() + Duration::days(1) || dt.year() < PLAUSIBLE_SINCE {
+                log_warn!("{:?}: implausible date {}",
+                          entry.path(),
+                          dt.format("%Y-%m-%d %H:%M:%S"));
+            }
+            if explain {
+                log_info!("explain {:?}: dated {} by {}",
+                          entry.path(),
+                          dt.format("%Y-%m-%d %H:%M:%S"),
+                          source);
+            }
+            ctx.timings.add(Phase::Dates, start);
+            let output = dest_rel(entry.path(), args);
+            if is_archive {
+                let start = Instant::now();
+                errors += expand::expand_archive(entry.path(),
+                                                 &below,
+                                                 &output,
+                                                 args,
+                                                 ctx,
+                                                 &wanted);
+                ctx.timings.add(Phase::Move, start);
+                continue;
+            }
+            // add file to the batch
+            curfiles.push((PathBuf::from(entry.path()), output));
+            curdates.push(dt);
+            if args.flag_set_mtime.as_ref().map_or(false, |s| s == "source-date") {
+                ctx.file_dates.insert(PathBuf::from(entry.path()), dt);
+            }
+
+        } else if entry.path().is_dir() {
+            // if back to depth 2, create folders and move paths
+            if entry.depth() <= 2 {
+                let (files, dates) = (curfiles.split_off(0), curdates.split_off(0));
+                for batch in make_batches(files, dates, policy, args, ctx) {
+                    errors += handle(batch, ctx);
+                }
+            }
+        }
+    }
+    // move after exiting the loop
+    for batch in make_batches(curfiles, curdates, policy, args, ctx) {
+        errors += handle(batch, ctx);
+    }
+    errors + ctx.configs.take_errors()
+}
+
+
+// For --skip-duplicates: removes the files that have an identical copy in an
+// earlier root from `batches`, dropping batches left empty. Returns the files
+// removed, each with the copy that is kept. With `quick`, files of the same
+// size and mtime are taken to be identical without reading them.
+fn drop_duplicates(batches: &mut Vec<Batch>,
+                   roots: &[String],
+                   quick: bool,
+                   algorithm: hash::Algorithm)
+                   -> Vec<(PathBuf, PathBuf)> {
+    let root_of = |path: &Path| roots.iter().position(|r| path.starts_with(r));
+    let files: Vec<PathBuf> = batches.iter().flat_map(|b| b.files.iter().map(|f| f.0.clone())).collect();
+    let mut dropped = vec![];
+    for (_, group) in dedupe::duplicates(&files, quick, algorithm) {
+        // the copy found first is in the earliest root
+        let kept = root_of(&group[0]);
+        for file in &group[1..] {
+            if root_of(file) != kept {
+                dropped.push((file.clone(), group[0].clone()));
+            }
+        }
+    }
+    for batch in batches.iter_mut() {
+        batch.files.retain(|f| !dropped.iter().any(|d| d.0 == f.0));
+    }
+    batches.retain(|b| !b.files.is_empty());
+    dropped
+}
+
+
+// The layout template, with the levels of --group-by: a list with a date key
+// in it gives the whole folder, e.g. "type,year" "{ext}/{year}", otherwise
+// its levels go in front of the layout. --keep-root-name adds the source
+// directory, and --append-source-name ends it with the source folder and the
+// file name.
+fn layout_text(args: &Args) -> Result<String, String> {
+    let layout = template::preset(&args.flag_layout);
+    let mut levels = vec![];
+    if let Some(ref keys) = args.flag_group_by {
+        try!(group_by_levels(keys, layout, &mut levels));
+    } else {
+        levels.push(layout.to_string());
+    }
+    let names_file = layout.contains("{name}") || layout.contains("{stem}");
+    if (args.flag_keep_root_name || args.flag_append_source_name) && names_file {
+        return Err("--keep-root-name and --append-source-name can't be combined with a \
+                    layout naming files"
+                       .to_string());
+    }
+    if args.flag_keep_root_name {
+        levels.push("{root}".to_string());
+    }
+    if args.flag_append_source_name {
+        levels.push("{parent}/{name}".to_string());
+    }
+    Ok(levels.join("/"))
+}
+
+fn group_by_levels(keys: &str, layout: &str, levels: &mut Vec<String>) -> Result<(), String> {
+    let mut dated = false;
+    for key in keys.split(',').map(|k| k.trim()) {
+        let token = match key {
+            "year" | "month" | "day" | "hour" => {
+                dated = true;
+                key
+            }
+            "type" => "ext",
+            "size" | "owner" | "camera" | "country" | "city" => key,
+            _ => return Err(format!("unknown --group-by key {:?}", key)),
+        };
+        levels.push(format!("{{{}}}", token));
+    }
+    if !dated {
+        levels.push(layout.to_string());
+    } else if layout != "{year}" {
+        return Err("--group-by with a date key can't be combined with --layout".to_string());
+    }
+    Ok(())
+}
+
+// whether the output directory is on another machine, and so can't be locked
+fn is_remote(output: &str) -> bool {
+    sftp::is_sftp_url(output) || s3::is_s3_url(output) || rsync::is_remote_spec(output)
+}
+
+// Locks `output` for the rest of the process; exits if another run holds it.
+fn lock_output(output: &Path) -> lock::Lock {
+    match lock::acquire(output) {
+        Ok(Some(lock)) => lock,
+        Ok(None) => {
+            log_error!("{}",
+                       tr!("locked",
+                           "Error: another run is organizing into { $output }",
+                           output = &output.display().to_string()));
+            exit(EXIT_LOCKED);
+        }
+        Err(e) => {
+            log_error!("Error: lock {}: {}", output.display(), e);
+            exit(EXIT_ERRORS);
+        }
+    }
+}
+
+// locks the output directories that `paths` are in, see lock::outputs_above
+fn lock_outputs<'a, I>(paths: I) -> Vec<lock::Lock>
+    where I: IntoIterator<Item = &'a Path>
+{
+    lock::outputs_above(paths).iter().map(|output| lock_output(output)).collect()
+}
+
+// the executor for moves into the output directory, local or remote; each
+// --io-workers thread has one of its own
+fn output_executor(args: &Args) -> Box<dyn Executor + Send> {
+    if sftp::is_sftp_url(&args.flag_output_dir) && !args.flag_dry_run {
+        Box::new(SftpExecutor::connect(&args.flag_output_dir).unwrap_or_else(|e| {
+            log_error!("Error: {}: {}", args.flag_output_dir, e);
+            exit(EXIT_ERRORS);
+        }))
+    } else if s3::is_s3_url(&args.flag_output_dir) && !args.flag_dry_run {
+        Box::new(S3Executor::new(&args.flag_output_dir).unwrap_or_else(|e| {
+            log_error!("Error: {}: {}", args.flag_output_dir, e);
+            exit(EXIT_ERRORS);
+        }))
+    } else if rsync::is_remote_spec(&args.flag_output_dir) {
+        Box::new(RsyncExecutor::new(&args.flag_output_dir))
+    } else if args.flag_remove_source_after_verify {
+        Box::new(VerifyingExecutor(hash_algorithm(args)))
+    } else if args.flag_mirror {
+        Box::new(CopyExecutor)
+    } else {
+        Box::new(LocalExecutor)
+    }
+}
+
+// sets up the executor, manifest etc. for a run; exits on invalid options
+fn make_context(args: &Args) -> Context {
+    let script = args.flag_dest_script.as_ref().map(|path| {
+        DestScript::load(Path::new(path)).unwrap_or_else(|e| {
+            log_error!("Error: dest script: {}", e);
+            exit(EXIT_USAGE);
+        })
+    });
+
+    let remote = is_remote(&args.flag_output_dir);
+    if remote && (args.flag_archive.is_some() || args.flag_expand_archives) {
+        log_error!("Error: --archive and --expand-archives need a local output directory");
+        exit(EXIT_USAGE);
+    }
+    match &args.flag_on_conflict[..] {
+        "fail" | "overwrite" => {}
+        "ask" if args.flag_watch.is_some() || args.flag_tui || !io::stdin().is_terminal() => {
+            log_error!("Error: --on-conflict=ask needs a terminal, and no --watch or --tui");
+            exit(EXIT_USAGE);
+        }
+        // only a local output can be checked for existing files
+        "skip" | "newer" | "ask" | "quarantine" | "rename"
+            if !remote && args.flag_archive.is_none() => {}
+        "skip" | "newer" | "ask" | "quarantine" | "rename" => {
+            log_error!("Error: --on-conflict={} needs a local output directory",
+                       args.flag_on_conflict);
+            exit(EXIT_USAGE);
+        }
+        other => {
+            log_error!("Error: unknown conflict action {:?}", other);
+            exit(EXIT_USAGE);
+        }
+    }
+    match &args.flag_merge[..] {
+        "always" => {}
+        "ask" if args.flag_watch.is_some() || args.flag_tui || !io::stdin().is_terminal() => {
+            log_error!("Error: --merge=ask needs a terminal, and no --watch or --tui");
+            exit(EXIT_USAGE);
+        }
+        "ask" | "suffix" if !remote && args.flag_archive.is_none() && script.is_none() => {}
+        "ask" | "suffix" => {
+            log_error!("Error: --merge={} needs a local output directory, and no --archive \
+                        or --dest-script",
+                       args.flag_merge);
+            exit(EXIT_USAGE);
+        }
+        other => {
+            log_error!("Error: unknown merge policy {:?}", other);
+            exit(EXIT_USAGE);
+        }
+    }
+    let strategy = pathlen::Strategy::parse(&args.flag_long_paths).unwrap_or_else(|| {
+        log_error!("Error: --long-paths must be error, truncate or hash");
+        exit(EXIT_USAGE);
+    });
+    // archive members and remote paths have limits of their own
+    let long_paths = if remote || args.flag_archive.is_some() {
+        None
+    } else {
+        let limits = pathlen::limits(Path::new(&args.flag_output_dir), args.flag_max_path_length);
+        Some((limits, strategy))
+    };
+    if args.flag_transactional &&
+       (remote || args.flag_archive.is_some() || args.flag_executor.is_some() ||
+        args.flag_compress_older_than.is_some()) {
+        log_error!("Error: --transactional needs plain moves into a local output directory");
+        exit(EXIT_USAGE);
+    }
+    if args.flag_remove_source_after_verify &&
+       (remote || args.flag_archive.is_some() || args.flag_executor.is_some()) {
+        log_error!("Error: --remove-source-after-verify needs a local output directory");
+        exit(EXIT_USAGE);
+    }
+    // a rollback would move the copies onto their sources
+    if args.flag_mirror &&
+       (remote || args.flag_archive.is_some() || args.flag_executor.is_some() ||
+        args.flag_transactional) {
+        log_error!("Error: --mirror needs a local output directory and no --transactional");
+        exit(EXIT_USAGE);
+    }
+    if args.flag_symlinks != "skip" && args.flag_symlinks != "follow" {
+        log_error!("Error: --symlinks must be skip or follow");
+        exit(EXIT_USAGE);
+    }
+    if args.flag_fsync && (remote || args.flag_archive.is_some() || args.flag_executor.is_some()) {
+        log_error!("Error: --fsync needs a local output directory");
+        exit(EXIT_USAGE);
+    }
+    match args.flag_set_mtime.as_ref().map(|s| &s[..]) {
+        None => {}
+        Some("batch") | Some("source-date") if !remote && args.flag_archive.is_none() => {}
+        Some("batch") | Some("source-date") => {
+            log_error!("Error: --set-mtime needs a local output directory");
+            exit(EXIT_USAGE);
+        }
+        Some(other) => {
+            log_error!("Error: --set-mtime must be batch or source-date, not {:?}", other);
+            exit(EXIT_USAGE);
+        }
+    }
+    match args.flag_sort_by {
+        Some(ref key) if !args.flag_dry_run || !["dest", "src", "date"].contains(&&key[..]) => {
+            log_error!("Error: --sort-by needs --dry-run and one of dest, src or date");
+            exit(EXIT_USAGE);
+        }
+        _ => {}
+    }
+    // archives are extracted while scanning, before the plan could be reviewed
+    if args.flag_tui && (args.flag_expand_archives || args.flag_watch.is_some()) {
+        log_error!("Error: --tui can't be combined with --expand-archives or --watch");
+        exit(EXIT_USAGE);
+    }
+    if args.flag_stats_only && args.flag_expand_archives {
+        log_error!("Error: --stats-only can't be combined with --expand-archives");
+        exit(EXIT_USAGE);
+    }
+    // the archive is removed once extracted, which copies and verified moves
+    // promise not to do; and its members can't be handed to an executor
+    if args.flag_expand_archives &&
+       (args.flag_mirror || args.flag_remove_source_after_verify || args.flag_archive.is_some() ||
+        args.flag_executor.is_some()) {
+        log_error!("Error: --expand-archives can't be combined with --mirror, \
+                    --remove-source-after-verify, --archive or --executor");
+        exit(EXIT_USAGE);
+    }
+
+    match args.flag_io_workers {
+        Some(0) => {
+            log_error!("Error: --io-workers must be at least 1");
+            exit(EXIT_USAGE);
+        }
+        // the archive and the executor program take one move at a time
+        Some(_) if args.flag_archive.is_some() || args.flag_executor.is_some() ||
+                   args.flag_transactional => {
+            log_error!("Error: --io-workers can't be combined with --archive, --executor or \
+                        --transactional");
+            exit(EXIT_USAGE);
+        }
+        _ => {}
+    }
+
+    let pool = args.flag_io_workers.filter(|_| !args.flag_dry_run).map(|n| {
+        workers::Pool::new((0..n).map(|_| output_executor(args)).collect())
+    });
+    let executor: Box<dyn Executor> = match (&args.flag_executor, &args.flag_archive) {
+        (&Some(ref cmd), _) if !args.flag_dry_run => {
+            Box::new(PluginExecutor::spawn(cmd).unwrap_or_else(|e| {
+                log_error!("Error: executor {:?}: {}", cmd, e);
+                exit(EXIT_ERRORS);
+            }))
+        }
+        (_, &Some(ref format)) => {
+            let format = ArchiveFormat::parse(format).unwrap_or_else(|| {
+                log_error!("Error: unknown archive format {:?}", format);
+                exit(EXIT_USAGE);
+            });
+            Box::new(ArchiveExecutor::new(Path::new(&args.flag_output_dir),
+                                          format,
+                                          args.flag_archive_per_month))
+        }
+        // the workers make the moves, on as many connections as there are of
+        // them; one of the output's own is only made for --retry-in-use, once
+        // they are done
+        _ if pool.is_some() => Box::new(LocalExecutor),
+        _ => output_executor(args),
+    };
+    let run_id = new_run_id();
+    // uploads to s3 are always journaled so they can be traced back
+    let manifest_path = args.flag_manifest.clone().or_else(|| {
+        if s3::is_s3_url(&args.flag_output_dir) {
+            Some("organize-by-mtime.manifest".to_string())
+        } else {
+            None
+        }
+    });
+    let mut mirrored = HashMap::new();
+    match manifest_path {
+        Some(ref path) if args.flag_mirror && Path::new(path).exists() => {
+            let entries = manifest::read(Path::new(path)).unwrap_or_else(|e| {
+                log_error!("Error: manifest {:?}: {}", path, e);
+                exit(EXIT_ERRORS);
+            });
+            // the last copy of each source counts
+            for entry in manifest::without_rollbacks(entries) {
+                if let Some(size) = entry.size {
+                    mirrored.insert(entry.src, (size, entry.mtime, entry.checksum));
+                }
+            }
+        }
+        _ => {}
+    }
+    let manifest = manifest_path.as_ref().filter(|_| !args.flag_dry_run).map(|path| {
+        Manifest::open(Path::new(path), &run_id).unwrap_or_else(|e| {
+            log_error!("Error: manifest {:?}: {}", path, e);
+            exit(EXIT_ERRORS);
+        })
+    });
+    let (patterns, not_patterns) = patterns::cli_patterns(args);
+    let configs = config::Configs::new(config::Cli {
+        patterns: patterns,
+        not_patterns: not_patterns,
+        smart_case: args.flag_smart_case,
+    });
+    let plan = args.flag_save_plan.as_ref().map(|path| {
+        plan::Writer::create(Path::new(path), &run_id).unwrap_or_else(|e| {
+            log_error!("Error: save plan {:?}: {}", path, e);
+            exit(EXIT_ERRORS);
+        })
+    });
+    let plan_format = plan::Format::parse(&args.flag_plan_format).unwrap_or_else(|| {
+        log_error!("Error: --plan-format must be text, json, csv, tsv or null-separated");
+        exit(EXIT_USAGE);
+    });
+    // check_exclusive made sure it is a dry run
+    if plan_format != plan::Format::Text {
+        log::reserve_stdout();
+    }
+    let compress_before = args.flag_compress_older_than.as_ref().map(|age| {
+        match parse_duration(age) {
+            Ok(d) => now() - d,
+            Err(e) => {
+                log_error!("Error: --compress-older-than: {}", e);
+                exit(EXIT_USAGE);
+            }
+        }
+    });
+    let mut date_sources = vec![];
+    if args.flag_date_from_dirname {
+        date_sources.push(dates::Source::Dirname);
+    }
+    for name in args.flag_date_source.iter().flat_map(|s| s.split(',')) {
+        date_sources.push(dates::Source::parse(name).unwrap_or_else(|| {
+            log_error!("Error: unknown date source {:?}", name);
+            exit(EXIT_USAGE);
+        }));
+    }
+    let date_overrides = args.flag_date_override.iter().map(|rule| {
+        dates::Override::parse(rule).unwrap_or_else(|e| {
+            log_error!("Error: date override {:?}: {}", rule, e);
+            exit(EXIT_USAGE);
+        })
+    }).collect();
+    let cluster_gap = args.flag_cluster_events.as_ref().map(|gap| {
+        parse_duration(gap).unwrap_or_else(|e| {
+            log_error!("Error: --cluster-events: {}", e);
+            exit(EXIT_USAGE);
+        })
+    });
+    let max_spread = args.flag_max_batch_spread.as_ref().map(|age| {
+        parse_duration(age).unwrap_or_else(|e| {
+            log_error!("Error: --max-batch-spread: {}", e);
+            exit(EXIT_USAGE);
+        })
+    });
+    let min_size = args.flag_min_size.as_ref().map(|size| {
+        stats::parse_size(size).unwrap_or_else(|| {
+            log_error!("Error: invalid size {:?}", size);
+            exit(EXIT_USAGE);
+        })
+    });
+    let warn_spread = args.flag_warn_batch_spread.as_ref().map(|age| {
+        parse_duration(age).unwrap_or_else(|e| {
+            log_error!("Error: --warn-batch-spread: {}", e);
+            exit(EXIT_USAGE);
+        })
+    });
+    if args.flag_outliers != "individual" && args.flag_outliers != "quarantine" {
+        log_error!("Error: --outliers must be individual or quarantine");
+        exit(EXIT_USAGE);
+    }
+    let round_to = args.flag_round_to.as_ref().map(|period| {
+        dates::Period::parse(period).unwrap_or_else(|| {
+            log_error!("Error: --round-to must be day, week or month");
+            exit(EXIT_USAGE);
+        })
+    });
+    let hash = hash_algorithm(args);
+    let template_options = template::Options::new(&args.flag_day_folder_style,
+                                                  &args.flag_size_buckets,
+                                                  hash)
+                               .unwrap_or_else(|e| {
+                                   log_error!("Error: {}", e);
+                                   exit(EXIT_USAGE);
+                               });
+    let layout = layout_text(args).and_then(|text| Template::parse(&text)).unwrap_or_else(|e| {
+        log_error!("Error: layout: {}", e);
+        exit(EXIT_USAGE);
+    });
+    if layout.names_file() && (args.flag_flatten || args.flag_preserve_structure) {
+        log_error!("Error: a layout with {{name}} or {{stem}} can't be combined with --flatten or \
+                    --preserve-structure");
+        exit(EXIT_USAGE);
+    }
+    Context {
+        layout: layout,
+        template_options: template_options,
+        hash: hash,
+        script: script,
+        executor: executor,
+        pool: pool,
+        manifest: manifest,
+        plan: plan,
+        on_plan: None,
+        plan_format: plan_format,
+        compress_before: compress_before,
+        date_sources: date_sources,
+        date_overrides: date_overrides,
+        pattern_usage: patterns::Usage::new(args),
+        override_hits: vec![0; args.flag_date_override.len()],
+        cluster_gap: cluster_gap,
+        max_spread: max_spread,
+        outliers: HashSet::new(),
+        warn_spread: warn_spread,
+        events: if cluster_gap.is_some() {
+            existing_events(Path::new(&args.flag_output_dir))
+        } else {
+            HashMap::new()
+        },
+        round_to: round_to,
+        min_size: min_size,
+        file_dates: HashMap::new(),
+        planned: 0,
+        bytes_planned: 0,
+        organized: 0,
+        bytes: 0,
+        claimed: HashSet::new(),
+        conflicts: vec![],
+        skipped: BTreeMap::new(),
+        explain: args.flag_explain
+                     .iter()
+                     .map(|p| fs::canonicalize(p).unwrap_or_else(|_| PathBuf::from(p)))
+                     .collect(),
+        overwrite_all: None,
+        merged: HashMap::new(),
+        merge_all: None,
+        long_paths: long_paths,
+        mirrored: mirrored,
+        in_use: vec![],
+        configs: configs,
+        timings: Timings::new(),
+        run_id: run_id,
+    }
+}
+
+// organizes all source directories once
+// `on_plan` is given every move decided on
+fn run(args: &Args,
+       agepolicy: AgePolicy,
+       on_plan: Option<Box<dyn FnMut(plan::Move)>>)
+       -> Summary {
+    let started = Instant::now();
+    let warnings = log::warnings();
+    let mut ctx = make_context(args);
+    ctx.on_plan = on_plan;
+    let mut summary = Summary::new(&ctx.run_id,
+                                   &args.arg_directory,
+                                   &args.flag_output_dir,
+                                   args.flag_dry_run);
+
+    let mut errors: isize = 0;
+
+    if args.flag_stats_only {
+        let mut batches = vec![];
+        for dir in &args.arg_directory {
+            errors += process_dir(dir, agepolicy, args, &mut ctx, &mut |batch, _| {
+                batches.push(batch);
+                0
+            });
+        }
+        stats::report(&batches);
+    } else if let Some(ref key) = args.flag_sort_by {
+        // list the plan in the requested order; only for --dry-run, so
+        // handling each file as its own batch changes nothing else
+        let mut files = vec![];
+        for dir in &args.arg_directory {
+            errors += process_dir(dir, agepolicy, args, &mut ctx, &mut |batch, _| {
+                for file in batch.files {
+                    files.push(Batch::new(vec![file], batch.date).unwrap());
+                }
+                0
+            });
+        }
+        match &key[..] {
+            "src" => files.sort_by(|a, b| a.files[0].0.cmp(&b.files[0].0)),
+            "date" => files.sort_by(|a, b| (a.date, &a.files[0].0).cmp(&(b.date, &b.files[0].0))),
+            _ => {
+                let ctx = &ctx;
+                files.sort_by_key(|b| {
+                    destination(&b.files[0].0, &b.files[0].1, &b.date, args, ctx).unwrap_or_default()
+                })
+            }
+        }
+        for batch in &files {
+            errors += move_batch(batch, args, &mut ctx, &mut |_| {});
+        }
+    } else if args.flag_tui || args.flag_skip_duplicates {
+        // the whole plan is needed up front
+        let mut batches = vec![];
+        for dir in &args.arg_directory {
+            errors += process_dir(dir, agepolicy, args, &mut ctx, &mut |batch, _| {
+                batches.push(batch);
+                0
+            });
+        }
+        if args.flag_skip_duplicates {
+            let start = Instant::now();
+            let dropped = drop_duplicates(&mut batches,
+                                          &args.arg_directory,
+                                          args.flag_quick_duplicates,
+                                          ctx.hash);
+            ctx.timings.add(Phase::Hash, start);
+            for (dup, kept) in dropped {
+                log_info!("skip {:?}, same as {:?}", dup, kept);
+                summary.duplicates.push(dup.to_string_lossy().into_owned());
+                *ctx.skipped.entry("duplicate").or_insert(0) += 1;
+            }
+        }
+        if !args.flag_tui {
+            for batch in &batches {
+                errors += move_batch(batch, args, &mut ctx, &mut |_| {});
+            }
+        } else {
+            match tui::run(batches, args, &mut ctx) {
+                Ok(n) => errors += n,
+                Err(e) => {
+                    log_error!("Error: tui: {}", e);
+                    errors += 1;
+                }
+            }
+        }
+    } else {
+        for dir in &args.arg_directory {
+            errors += process_dir(dir,
+                                  agepolicy,
+                                  args,
+                                  &mut ctx,
+                                  &mut |batch, ctx| move_batch(&batch, args, ctx, &mut |_| {}));
+        }
+    }
+
+    if let Some(mut pool) = ctx.pool.take() {
+        let start = Instant::now();
+        let (done, result) = pool.finish();
+        ctx.timings.add(Phase::Move, start);
+        errors += moves_done(done, args, &mut ctx);
+        if let Err(e) = result {
+            log_error!("Error: executor: {}", e);
+            errors += 1;
+        }
+    }
+    if args.flag_retry_in_use && !ctx.in_use.is_empty() {
+        if args.flag_io_workers.is_some() && !args.flag_dry_run {
+            ctx.executor = output_executor(args);
+        }
+        errors += retry_in_use(args, &mut ctx);
+    }
+    if !ctx.in_use.is_empty() {
+        log_warn!("{}",
+                  tr!("skipped-in-use",
+                      "{ $count } files skipped (in use)",
+                      count = ctx.in_use.len()));
+    }
+    summary.in_use = ctx.in_use.iter().map(|f| f.op.src.to_string_lossy().into_owned()).collect();
+    summary.conflicts = mem::replace(&mut ctx.conflicts, vec![]);
+    if !ctx.in_use.is_empty() {
+        ctx.skipped.insert("in use", ctx.in_use.len());
+    }
+    summary.skipped = ctx.skipped.iter().map(|(&reason, &n)| (reason.to_string(), n)).collect();
+
+    if let Err(e) = ctx.executor.finish() {
+        log_error!("Error: executor: {}", e);
+        errors += 1;
+    }
+
+    if let Some(ref mut plan) = ctx.plan {
+        if let Err(e) = plan.finish() {
+            log_error!("Error: save plan: {}", e);
+            errors += 1;
+        }
+    }
+
+    // a mistyped pattern does nothing at all; with --watch, no file to match
+    // is only to be expected
+    if args.flag_watch.is_none() && !interrupted() {
+        for pattern in ctx.pattern_usage.unmatched() {
+            log_warn!("{} matched no file", pattern);
+        }
+        for (rule, &hits) in args.flag_date_override.iter().zip(&ctx.override_hits) {
+            if hits == 0 {
+                log_warn!("--date-override {:?} matched no file", rule);
+            }
+        }
+    }
+
+    let warned = log::warnings() - warnings;
+    if args.flag_strict && warned > 0 {
+        // one error, as a file may well be warned about more than once
+        log_error!("Error: {} warnings, which fail the run with --strict", warned);
+        errors += 1;
+    }
+
+    let elapsed = started.elapsed();
+    let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+    summary.finish(ctx.planned, ctx.bytes_planned, ctx.organized, ctx.bytes, seconds, errors);
+    if args.flag_timings {
+        ctx.timings.report();
+    }
+    if ctx.organized > 0 {
+        log_info!("{}",
+                  tr!("moved-files",
+                      "moved { $count } files, { $size } in { $seconds }s ({ $rate }/s)",
+                      count = ctx.organized,
+                      size = stats::human_size(ctx.bytes),
+                      seconds = format!("{:.1}", seconds),
+                      rate = stats::human_size(summary.bytes_per_second)));
+    }
+    if !ctx.skipped.is_empty() {
+        let reasons: Vec<String> = ctx.skipped
+                                      .iter()
+                                      .map(|(reason, n)| format!("{} {}", n, reason))
+                                      .collect();
+        log_info!("{}",
+                  tr!("skipped-files",
+                      "left { $count } files in place: { $reasons }",
+                      count = ctx.skipped.values().sum::<usize>(),
+                      reasons = reasons.join(", ")));
+    }
+    if let Some(ref url) = args.flag_notify_webhook {
+        if let Err(e) = notify::post_webhook(url, &summary) {
+            log_error!("Error: notify webhook: {}", e);
+        }
+    }
+    if let Some(ref address) = args.flag_notify_email {
+        if let Err(e) = notify::send_email(address, &summary) {
+            log_error!("Error: notify email: {}", e);
+        }
+    }
+    summary
+}
+
+
+pub fn main() {
+    let mut args: Args = Docopt::new(USAGE)
+                         .and_then(|d| d.decode())
+                         .unwrap_or_else(|e| {
+                             if e.fatal() {
+                                 eprintln!("{}", e);
+                                 exit(EXIT_USAGE);
+                             }
+                             e.exit()
+                         });
+
+    if args.flag_version {
+        println!("organize-by-mtime v1.0.0");
+        return;
+    }
+    // `plan` is a dry run too, but only succeeds or fails
+    let dry_run = args.flag_dry_run;
+    let agepolicy = setup(&mut args);
+
+    if args.cmd_completions {
+        match completions::script(&args.arg_shell, USAGE) {
+            Some(script) => print!("{}", script),
+            None => {
+                log_error!("Error: unknown shell {:?}, not bash, zsh or fish", args.arg_shell);
+                exit(EXIT_USAGE);
+            }
+        }
+        return;
+    }
+    if args.cmd_check_config {
+        // so that no file is opened, no program started and no server
+        // connected to
+        args.flag_dry_run = true;
+        let save_plan = args.flag_save_plan.take();
+        let ctx = make_context(&args);
+        if checkconfig::run(&args, &ctx, save_plan.as_ref()) > 0 {
+            exit(EXIT_USAGE);
+        }
+        return;
+    }
+
+    if args.cmd_doctor {
+        if doctor::run(&args) > 0 {
+            exit(EXIT_ERRORS);
+        }
+        return;
+    }
+
+    if args.cmd_verify {
+        // only for dating files; the manifest is read, not opened to record
+        args.flag_dry_run = true;
+        let ctx = make_context(&args);
+        let manifest = args.flag_manifest.as_ref().map(Path::new);
+        if verify::run(Path::new(&args.arg_output), agepolicy, &ctx, manifest) > 0 {
+            exit(1);
+        }
+        return;
+    }
+    if args.cmd_plan_diff {
+        let load = |path: &str| {
+            Plan::load(Path::new(path)).unwrap_or_else(|e| {
+                log_error!("Error: plan {:?}: {}", path, e);
+                exit(2);
+            })
+        };
+        if plan::diff(&load(&args.arg_old), &load(&args.arg_new)) > 0 {
+            exit(1);
+        }
+        return;
+    }
+    if args.cmd_apply {
+        let manifest = args.flag_manifest.as_ref().map(Path::new);
+        let _locks = if args.flag_dry_run {
+            vec![]
+        } else {
+            match Plan::load(Path::new(&args.arg_plan)) {
+                Ok(plan) => lock_outputs(plan.moves.iter().map(|m| Path::new(&m.dst))),
+                // apply reports it
+                Err(_) => vec![],
+            }
+        };
+        let errors = apply::run(Path::new(&args.arg_plan),
+                                args.flag_dry_run,
+                                args.flag_force,
+                                args.flag_reapply,
+                                manifest,
+                                hash_algorithm(&args));
+        if errors > 0 {
+            exit(EXIT_ERRORS);
+        }
+        return;
+    }
+    if args.cmd_undo {
+        let _locks = if args.flag_dry_run {
+            vec![]
+        } else {
+            match manifest::read(Path::new(&args.arg_manifest)) {
+                Ok(entries) => lock_outputs(entries.iter().map(|e| e.dst.as_path())),
+                // undo reports it
+                Err(_) => vec![],
+            }
+        };
+        let errors = undo::run(Path::new(&args.arg_manifest),
+                               args.flag_run.as_ref().map(|s| &s[..]),
+                               args.flag_dry_run,
+                               args.flag_force);
+        if errors > 0 {
+            log_warn!("{}", tr!("total-errors", "total errors: { $count }", count = errors));
+            exit(EXIT_ERRORS);
+        }
+        return;
+    }
+    if args.cmd_find_duplicates {
+        if dedupe::report(Path::new(&args.arg_output), hash_algorithm(&args)) > 0 {
+            exit(1);
+        }
+        return;
+    }
+    if args.cmd_fix_mtime {
+        let ctx = make_context(&args);
+        // dating files by their mtime would change nothing
+        let dated = ctx.date_sources.iter().any(|&s| s != dates::Source::Mtime) ||
+                    ctx.date_overrides.iter().any(|o| o.source() != dates::Source::Mtime);
+        if !dated {
+            log_error!("Error: fix-mtime needs a date source other than mtime, from \
+                        --date-source, --date-from-dirname or --date-override");
+            exit(EXIT_USAGE);
+        }
+        let _locks = if args.flag_dry_run {
+            vec![]
+        } else {
+            lock_outputs(args.arg_directory.iter().map(Path::new))
+        };
+        let errors = fixmtime::run(&args, &ctx);
+        if errors > 0 {
+            log_warn!("{}", tr!("total-errors", "total errors: { $count }", count = errors));
+            exit(EXIT_ERRORS);
+        }
+        return;
+    }
+    if args.cmd_reorganize {
+        let _lock = if args.flag_dry_run {
+            None
+        } else {
+            Some(lock_output(Path::new(&args.arg_output)))
+        };
+        let mut ctx = make_context(&args);
+        let errors = reorganize::run(&args, &mut ctx, agepolicy);
+        if errors > 0 {
+            log_warn!("{}", tr!("total-errors", "total errors: { $count }", count = errors));
+            exit(EXIT_ERRORS);
+        }
+        return;
+    }
+
+    // before anything is read, and for every run of --watch
+    if let Some(n) = args.flag_nice {
+        if let Err(e) = priority::set_nice(n) {
+            log_error!("Error: --nice={}: {}", n, e);
+            exit(EXIT_USAGE);
+        }
+    }
+    if let Some(ref class) = args.flag_ionice {
+        if let Err(e) = priority::set_ionice(class) {
+            log_error!("Error: --ionice={}: {}", class, e);
+            exit(EXIT_USAGE);
+        }
+    }
+
+    // held until the process exits
+    let local = !is_remote(&args.flag_output_dir);
+    let _lock = if !args.flag_dry_run && !args.flag_stats_only && local {
+        Some(lock_output(Path::new(&args.flag_output_dir)))
+    } else {
+        None
+    };
+
+    let watch = args.flag_watch.as_ref().map(|interval| {
+        parse_duration(interval)
+            .and_then(|d| d.to_std().map_err(|_| format!("invalid interval {:?}", interval)))
+            .unwrap_or_else(|e| {
+                log_error!("Error: --watch: {}", e);
+                exit(EXIT_USAGE);
+            })
+    });
+
+    // once, however many runs --watch makes
+    if let Some(header) = plan::Format::parse(&args.flag_plan_format).and_then(|f| f.header()) {
+        println!("{}", header);
+    }
+
+    if let Some(interval) = watch {
+        let metrics = Arc::new(Metrics::new());
+        if let Some(ref addr) = args.flag_metrics {
+            if let Err(e) = metrics::serve(addr, metrics.clone()) {
+                log_error!("Error: metrics {}: {}", addr, e);
+                exit(EXIT_ERRORS);
+            }
+        }
+        let daemon = Arc::new(Daemon::new());
+        if let Some(ref path) = args.flag_control_socket {
+            if let Err(e) = control::serve(path, daemon.clone()) {
+                log_error!("Error: control socket {}: {}", path, e);
+                exit(EXIT_ERRORS);
+            }
+        }
+        if let Some(ref addr) = args.flag_http {
+            let token = env::var(http::TOKEN_VAR).unwrap_or_default();
+            if let Err(e) = http::serve(addr, token, daemon.clone()) {
+                log_error!("Error: http {} (is ${} set?): {}", addr, http::TOKEN_VAR, e);
+                exit(EXIT_ERRORS);
+            }
+        }
+        loop {
+            daemon.start_run();
+            // only a run is stopped gracefully; waiting, signals act as usual
+            catch_signals(true);
+            let summary = run(&args, agepolicy, None);
+            catch_signals(false);
+            metrics.record(&summary);
+            if summary.errors > 0 {
+                log_warn!("{}",
+                          tr!("total-errors", "total errors: { $count }", count = summary.errors));
+            }
+            daemon.finish_run(summary);
+            if interrupted() {
+                log_warn!("{}", tr!("interrupted", "interrupted"));
+                exit(EXIT_INTERRUPTED);
+            }
+            daemon.wait(interval);
+        }
+    }
+
+    if !args.flag_dry_run && !args.flag_stats_only && args.flag_archive.is_none() && local {
+        if let Err(e) = preflight::check(&args) {
+            log_error!("Error: {}", e);
+            exit(EXIT_PREFLIGHT);
+        }
+    }
+
+    catch_signals(true);
+    let summary = run(&args, agepolicy, None);
+    if interrupted() {
+        log_warn!("{}", tr!("interrupted", "interrupted"));
+        exit(EXIT_INTERRUPTED);
+    }
+    if summary.errors > 0 {
+        log_warn!("{}", tr!("total-errors", "total errors: { $count }", count = summary.errors));
+        exit(EXIT_ERRORS);
+    }
+    // tells wrappers there is a plan to review
+    if dry_run && summary.files_planned > 0 {
+        exit(EXIT_WOULD_MOVE);
+    }
+}
+
+// --hash
+fn hash_algorithm(args: &Args) -> hash::Algorithm {
+    hash::Algorithm::parse(&args.flag_hash).unwrap_or_else(|| {
+        log_error!("Error: unknown hash {:?}, not fnv, xxh3, blake3 or sha256", args.flag_hash);
+        exit(EXIT_USAGE);
+    })
+}
+
+// Applies and checks the options that hold for every subcommand, and those
+// docopt can't check itself. Shared by main and the library API.
+fn setup(args: &mut Args) -> AgePolicy {
+    hash_algorithm(args);
+
+    let messages = args.flag_messages.clone().or_else(|| env::var(i18n::CATALOG_VAR).ok());
+    if let Some(messages) = messages {
+        if let Err(e) = i18n::init(&messages) {
+            log_error!("Error: messages {}: {}", messages, e);
+            exit(EXIT_USAGE);
+        }
+    }
+
+    let agepolicy: AgePolicy = match (args.flag_oldest, args.flag_newest) {
+        (false, false) => Default,
+        (true, false) => Oldest,
+        (false, true) => Newest,
+        // only possible with a subcommand taking [options]
+        (true, true) => {
+            log_error!("Error: --oldest and --newest can't be combined");
+            exit(EXIT_USAGE);
+        }
+    };
+
+    match log::Target::parse(&args.flag_log_target) {
+        Some(target) => {
+            log::set_target(target);
+        }
+        None => {
+            log_error!("Error: unknown log target {:?}", args.flag_log_target);
+            exit(EXIT_USAGE);
+        }
+    }
+
+    if let Err(e) = log::set_color(&args.flag_color) {
+        log_error!("Error: {}", e);
+        exit(EXIT_USAGE);
+    }
+    args.arg_directory = distinct_roots(&args.arg_directory);
+
+    // the subcommands that are the main run with options set
+    if args.cmd_plan {
+        args.flag_dry_run = true;
+        args.flag_save_plan = Some(args.arg_plan.clone());
+    }
+    if args.cmd_stats {
+        args.flag_stats_only = true;
+    }
+    // docopt only checks them for the run without a subcommand
+    if let Err(e) = check_exclusive(&args) {
+        log_error!("Error: {}", e);
+        exit(EXIT_USAGE);
+    }
+    // --force keeps its meaning for the subcommands that take it
+    if args.flag_force && !(args.cmd_apply || args.cmd_undo || args.cmd_reorganize) {
+        if args.flag_on_conflict != "fail" {
+            log_error!("Error: --force can't be combined with --on-conflict");
+            exit(EXIT_USAGE);
+        }
+        log_warn!("--force is deprecated, use --on-conflict=overwrite");
+        args.flag_on_conflict = "overwrite".to_string();
+    }
+    if args.flag_update {
+        if args.flag_on_conflict != "fail" {
+            log_error!("Error: --update can't be combined with --on-conflict or --force");
+            exit(EXIT_USAGE);
+        }
+        args.flag_on_conflict = "newer".to_string();
+    }
+    agepolicy
+}
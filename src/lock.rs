@@ -0,0 +1,98 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Keeps two runs from organizing into the same output directory at once.
+// A run holds an exclusive lock on OUTPUT/.organize-by-mtime.lock, which the
+// system releases when the process exits, however it exits. Commands that
+// aren't given OUTPUT, like apply and undo, lock the output directories they
+// touch, known by the lock file an earlier run left in them.
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+pub const FILE_NAME: &'static str = ".organize-by-mtime.lock";
+
+
+pub struct Lock {
+    // locked for as long as it is open
+    _file: File,
+}
+
+// Ok(None) if another process holds the lock
+pub fn acquire(output: &Path) -> io::Result<Option<Lock>> {
+    try!(fs::create_dir_all(output));
+    let file = try!(OpenOptions::new().create(true).write(true).open(output.join(FILE_NAME)));
+    if !try!(try_lock(&file)) {
+        return Ok(None);
+    }
+    Ok(Some(Lock { _file: file }))
+}
+
+// the lock file itself, which the scans of an output directory pass over
+pub fn is_lock(path: &Path) -> bool {
+    path.file_name().map_or(false, |n| n == FILE_NAME)
+}
+
+// The output directories holding `paths`, or the paths themselves if they
+// are one: the nearest folder above each with a lock file in it.
+pub fn outputs_above<'a, I>(paths: I) -> BTreeSet<PathBuf>
+    where I: IntoIterator<Item = &'a Path>
+{
+    let mut checked = BTreeSet::new();
+    let mut outputs = BTreeSet::new();
+    for path in paths {
+        for dir in path.ancestors() {
+            if !checked.insert(dir.to_path_buf()) {
+                break;
+            }
+            if dir.join(FILE_NAME).is_file() {
+                outputs.insert(dir.to_path_buf());
+                break;
+            }
+        }
+    }
+    outputs
+}
+
+#[cfg(unix)]
+fn try_lock(file: &File) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let e = io::Error::last_os_error();
+    if e.kind() == io::ErrorKind::WouldBlock {
+        Ok(false)
+    } else {
+        Err(e)
+    }
+}
+
+// elsewhere, runs aren't kept apart
+#[cfg(not(unix))]
+fn try_lock(_: &File) -> io::Result<bool> {
+    Ok(true)
+}
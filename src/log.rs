@@ -0,0 +1,233 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Where run records go: the console by default (moves on stdout, errors on
+// stderr), or the system log with matching priorities for daemon and cron
+// use. Syslog and journald are spoken to directly over their unix sockets.
+
+use std::cell::RefCell;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    Console = 0,
+    Syslog = 1,
+    Journald = 2,
+    // kept in memory for the TUI to show, see take_captured()
+    Capture = 3,
+}
+
+impl Target {
+    pub fn parse(s: &str) -> Option<Target> {
+        match s {
+            "console" => Some(Target::Console),
+            "syslog" => Some(Target::Syslog),
+            "journald" => Some(Target::Journald),
+            _ => None,
+        }
+    }
+}
+
+// How a run logs. Each thread has its own, so that library calls made at the
+// same time log as each was asked to; the threads a run starts take its
+// settings along with settings() and install().
+#[derive(Clone, Default)]
+pub struct Settings {
+    target: Target,
+    color: bool,
+    // whether stdout is kept for --plan-format, with messages on stderr
+    stdout_reserved: bool,
+    // the warnings logged so far, for --strict
+    warnings: Arc<AtomicUsize>,
+}
+
+impl Default for Target {
+    fn default() -> Target {
+        Target::Console
+    }
+}
+
+thread_local!(static SETTINGS: RefCell<Settings> = RefCell::new(Settings::default()));
+
+pub fn settings() -> Settings {
+    SETTINGS.with(|settings| settings.borrow().clone())
+}
+
+// makes `settings` those of this thread; returns the ones they replace
+pub fn install(settings: Settings) -> Settings {
+    SETTINGS.with(|current| current.replace(settings))
+}
+
+// sets the target; returns the one it replaces
+pub fn set_target(target: Target) -> Target {
+    SETTINGS.with(|settings| mem::replace(&mut settings.borrow_mut().target, target))
+}
+
+fn target() -> Target {
+    SETTINGS.with(|settings| settings.borrow().target)
+}
+
+// "auto" colors when stdout is a terminal and NO_COLOR isn't set
+pub fn set_color(mode: &str) -> Result<(), String> {
+    let color = match mode {
+        "always" => true,
+        "never" => false,
+        "auto" => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        _ => return Err(format!("unknown color mode {:?}", mode)),
+    };
+    SETTINGS.with(|settings| settings.borrow_mut().color = color);
+    Ok(())
+}
+
+fn color() -> bool {
+    SETTINGS.with(|settings| {
+        let settings = settings.borrow();
+        settings.color && settings.target == Target::Console
+    })
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Paint {
+    Source,
+    Destination,
+}
+
+// wraps `text` in the ANSI color for its role, if coloring is on
+pub fn paint(text: &str, what: Paint) -> String {
+    if !color() {
+        return text.to_string();
+    }
+    let code = match what {
+        Paint::Source => "36",
+        Paint::Destination => "32",
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+static CAPTURED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// returns the messages logged since the last call with Target::Capture
+pub fn take_captured() -> Vec<String> {
+    mem::replace(&mut *CAPTURED.lock().unwrap(), vec![])
+}
+
+
+// syslog severities
+const ERR: u8 = 3;
+const WARNING: u8 = 4;
+const INFO: u8 = 6;
+
+pub fn error(msg: &str) {
+    log(ERR, msg)
+}
+
+pub fn warnings() -> usize {
+    SETTINGS.with(|settings| settings.borrow().warnings.load(Ordering::Relaxed))
+}
+
+pub fn warn(msg: &str) {
+    SETTINGS.with(|settings| settings.borrow().warnings.fetch_add(1, Ordering::Relaxed));
+    log(WARNING, msg)
+}
+
+pub fn info(msg: &str) {
+    log(INFO, msg)
+}
+
+pub fn reserve_stdout() {
+    SETTINGS.with(|settings| settings.borrow_mut().stdout_reserved = true);
+}
+
+fn stdout_reserved() -> bool {
+    SETTINGS.with(|settings| settings.borrow().stdout_reserved)
+}
+
+fn log(severity: u8, msg: &str) {
+    let sent = match target() {
+        Target::Console => Err(io::Error::new(io::ErrorKind::Other, "console")),
+        Target::Syslog => send_syslog(severity, msg),
+        Target::Journald => send_journald(severity, msg),
+        Target::Capture => {
+            CAPTURED.lock().unwrap().push(msg.to_string());
+            Ok(())
+        }
+    };
+    // the console is also the fallback when the system log is unreachable
+    if sent.is_err() {
+        if severity == INFO && !stdout_reserved() {
+            println!("{}", msg);
+        } else {
+            let msg = if color() {
+                format!("\x1b[{}m{}\x1b[0m", if severity == ERR { "1;31" } else { "33" }, msg)
+            } else {
+                msg.to_string()
+            };
+            match writeln!(&mut io::stderr(), "{}", msg) {
+                Ok(_) => {}
+                Err(x) => panic!("Unable to write to stderr: {}", x),
+            }
+        }
+    }
+}
+
+const IDENT: &'static str = "organize-by-mtime";
+
+#[cfg(unix)]
+fn send_syslog(severity: u8, msg: &str) -> io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+    use std::process;
+
+    // facility "user"
+    let line = format!("<{}>{}[{}]: {}", 8 + severity, IDENT, process::id(), msg);
+    let socket = try!(UnixDatagram::unbound());
+    try!(socket.send_to(line.as_bytes(), "/dev/log"));
+    Ok(())
+}
+
+#[cfg(unix)]
+fn send_journald(severity: u8, msg: &str) -> io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let entry = format!("PRIORITY={}\nSYSLOG_IDENTIFIER={}\nMESSAGE={}\n",
+                        severity,
+                        IDENT,
+                        msg.replace('\n', " "));
+    let socket = try!(UnixDatagram::unbound());
+    try!(socket.send_to(entry.as_bytes(), "/run/systemd/journal/socket"));
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_syslog(_: u8, _: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "syslog is not available"))
+}
+
+#[cfg(not(unix))]
+fn send_journald(_: u8, _: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "journald is not available"))
+}
@@ -0,0 +1,179 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// The manifest is a tab-separated log of every file organized, appended to
+// across runs:
+//
+//   DATE  SRC  DST  SIZE  ENCODING  RUN  CHECKSUM  CONFLICT  KIND  MTIME
+//
+// where DATE is the batch date, ENCODING is "-" for files moved as they are
+// or "zstd" for files compressed on the way (see --compress-older-than), RUN
+// the id of the run that moved the file, CHECKSUM that of its content
+// before the move (see hash.rs), or "-" if it couldn't be read, and CONFLICT the
+// --on-conflict action taken because DST was taken, or "-". KIND is
+// "rollback" for a file a failed --transactional batch moved back, which
+// cancels its move earlier in the run, "extract" for an archive member
+// extracted by --expand-archives, SRC being its path inside the archive, or
+// "-". MTIME is that of SRC where it
+// is still there after the move, as with --mirror, or "-". Older manifests
+// lack the last five.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDateTime;
+
+use executor::MoveOp;
+use file_mtime;
+use hash::Checksum;
+
+// down to the nanosecond, so that a file changed within a second is told
+const MTIME_FORMAT: &'static str = "%Y-%m-%dT%H:%M:%S%.f";
+
+
+pub struct Entry {
+    pub date: String,
+    pub src: PathBuf,
+    pub dst: PathBuf,
+    pub size: Option<u64>,
+    pub compressed: bool,
+    pub run: Option<String>,
+    pub checksum: Option<Checksum>,
+    pub conflict: Option<String>,
+    pub rollback: bool,
+    pub extracted: bool,
+    pub mtime: Option<NaiveDateTime>,
+}
+
+// reads back all entries, in the order they were recorded; malformed lines
+// are skipped
+pub fn read(path: &Path) -> io::Result<Vec<Entry>> {
+    let file = try!(File::open(path));
+    let mut entries = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = try!(line);
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 3 {
+            continue;
+        }
+        entries.push(Entry {
+            date: fields[0].to_string(),
+            src: PathBuf::from(fields[1]),
+            dst: PathBuf::from(fields[2]),
+            size: fields.get(3).and_then(|s| s.parse().ok()),
+            compressed: fields.get(4) == Some(&"zstd"),
+            run: fields.get(5).map(|s| s.to_string()),
+            checksum: fields.get(6).and_then(|s| Checksum::parse(s)),
+            conflict: fields.get(7).filter(|s| **s != "-").map(|s| s.to_string()),
+            rollback: fields.get(8) == Some(&"rollback"),
+            extracted: fields.get(8) == Some(&"extract"),
+            mtime: fields.get(9).and_then(|s| NaiveDateTime::parse_from_str(s, MTIME_FORMAT).ok()),
+        });
+    }
+    Ok(entries)
+}
+
+// the entries of files that stayed moved: without rollbacks, nor the moves
+// they cancel
+pub fn without_rollbacks(entries: Vec<Entry>) -> Vec<Entry> {
+    let mut cancelled = vec![false; entries.len()];
+    for (i, e) in entries.iter().enumerate() {
+        if !e.rollback {
+            continue;
+        }
+        cancelled[i] = true;
+        let undone = (0..i).rev().find(|&j| {
+            !cancelled[j] && entries[j].run == e.run && entries[j].src == e.dst &&
+            entries[j].dst == e.src
+        });
+        if let Some(j) = undone {
+            cancelled[j] = true;
+        }
+    }
+    entries.into_iter().zip(cancelled).filter(|&(_, c)| !c).map(|(e, _)| e).collect()
+}
+
+
+pub struct Manifest {
+    file: File,
+    run: String,
+}
+
+impl Manifest {
+    // entries are recorded as moved by the run `run`
+    pub fn open(path: &Path, run: &str) -> io::Result<Manifest> {
+        let file = try!(OpenOptions::new().create(true).append(true).open(path));
+        Ok(Manifest {
+            file: file,
+            run: run.to_string(),
+        })
+    }
+
+    pub fn record(&mut self,
+                  op: &MoveOp,
+                  size: u64,
+                  checksum: Option<&Checksum>)
+                  -> io::Result<()> {
+        self.write(op, size, checksum, "-")
+    }
+
+    // `op` moved a file of a failed --transactional batch back
+    pub fn record_rollback(&mut self,
+                           op: &MoveOp,
+                           size: u64,
+                           checksum: Option<&Checksum>)
+                           -> io::Result<()> {
+        self.write(op, size, checksum, "rollback")
+    }
+
+    // `op` extracted an archive member, with --expand-archives
+    pub fn record_extract(&mut self,
+                          op: &MoveOp,
+                          size: u64,
+                          checksum: Option<&Checksum>)
+                          -> io::Result<()> {
+        self.write(op, size, checksum, "extract")
+    }
+
+    fn write(&mut self,
+             op: &MoveOp,
+             size: u64,
+             checksum: Option<&Checksum>,
+             kind: &str)
+             -> io::Result<()> {
+        let mtime = fs::metadata(&op.src).ok().map(|md| file_mtime(&md));
+        writeln!(self.file,
+                 "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                 op.date.format("%Y-%m-%dT%H:%M:%S"),
+                 op.src.display(),
+                 op.dst.display(),
+                 size,
+                 if op.compress { "zstd" } else { "-" },
+                 self.run,
+                 checksum.map_or("-".to_string(), |c| c.to_string()),
+                 op.conflict.unwrap_or("-"),
+                 kind,
+                 mtime.map_or("-".to_string(), |t| t.format(MTIME_FORMAT).to_string()))
+    }
+}
@@ -0,0 +1,103 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Prometheus metrics for --watch mode, served over plain http by a
+// background thread.
+
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+
+use tiny_http::{Header, Response, Server};
+
+use now;
+use summary::Summary;
+
+
+pub struct Metrics {
+    runs: AtomicUsize,
+    files: AtomicUsize,
+    bytes: AtomicU64,
+    errors: AtomicUsize,
+    last_run: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            runs: AtomicUsize::new(0),
+            files: AtomicUsize::new(0),
+            bytes: AtomicU64::new(0),
+            errors: AtomicUsize::new(0),
+            last_run: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, summary: &Summary) {
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        self.files.fetch_add(summary.files_organized, Ordering::Relaxed);
+        self.bytes.fetch_add(summary.bytes_organized, Ordering::Relaxed);
+        self.errors.fetch_add(summary.errors as usize, Ordering::Relaxed);
+        self.last_run.store(now().timestamp() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let metrics: [(&str, &str, &str, u64); 5] =
+            [("organize_runs_total", "counter", "Completed runs.",
+              self.runs.load(Ordering::Relaxed) as u64),
+             ("organize_files_organized_total", "counter", "Files moved or extracted.",
+              self.files.load(Ordering::Relaxed) as u64),
+             ("organize_bytes_moved_total", "counter", "Bytes of the files organized.",
+              self.bytes.load(Ordering::Relaxed)),
+             ("organize_errors_total", "counter", "Errors over all runs.",
+              self.errors.load(Ordering::Relaxed) as u64),
+             ("organize_last_run_timestamp_seconds", "gauge", "When the last run finished.",
+              self.last_run.load(Ordering::Relaxed))];
+        for &(name, kind, help, value) in metrics.iter() {
+            out.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n{} {}\n",
+                                  name, help, name, kind, name, value));
+        }
+        out
+    }
+}
+
+pub fn serve(addr: &str, metrics: Arc<Metrics>) -> io::Result<()> {
+    let server = try!(Server::http(addr)
+                          .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string())));
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = if request.url() == "/metrics" {
+                let content_type = Header::from_bytes(&b"Content-Type"[..],
+                                                      &b"text/plain; version=0.0.4"[..])
+                                       .unwrap();
+                Response::from_string(metrics.render()).with_header(content_type)
+            } else {
+                Response::from_string("not found\n").with_status_code(404)
+            };
+            let _ = request.respond(response);
+        }
+    });
+    Ok(())
+}
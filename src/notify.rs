@@ -0,0 +1,65 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Completion notifications: the run summary is POSTed to a webhook and/or
+// mailed through the local sendmail.
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use ureq;
+
+use summary::Summary;
+
+
+pub fn post_webhook(url: &str, summary: &Summary) -> io::Result<()> {
+    ureq::post(url)
+        .set("Content-Type", "application/json")
+        .send_string(&summary.to_json())
+        .map(|_| ())
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+}
+
+pub fn send_email(address: &str, summary: &Summary) -> io::Result<()> {
+    let mut child = try!(Command::new("sendmail")
+                             .arg("-t")
+                             .stdin(Stdio::piped())
+                             .spawn());
+    {
+        let stdin = child.stdin.as_mut().unwrap();
+        try!(write!(stdin,
+                    "To: {}\nSubject: organize-by-mtime: {} ({} files, {} errors)\n\
+                     Content-Type: application/json\n\n{}\n",
+                    address,
+                    summary.status,
+                    summary.files_organized,
+                    summary.errors,
+                    summary.to_json()));
+    }
+    let status = try!(child.wait());
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::Other, format!("sendmail failed: {}", status)))
+    }
+}
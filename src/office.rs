@@ -0,0 +1,153 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Creation and last-save dates of office documents.
+//
+// docx/xlsx/pptx (and the other Office Open XML formats) are zip files whose
+// docProps/core.xml holds dcterms:created and dcterms:modified.
+//
+// The legacy doc/xls/ppt formats are OLE compound files with a
+// "\x05SummaryInformation" property set stream. Rather than following the
+// compound file's sector chains, the property set is looked up by its format
+// id and read in place, which works because writers store this small stream
+// in one piece; when they don't, no date is found and the next source is
+// used.
+
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use chrono::*;
+use zip;
+
+use dates::{digits_after, find, from_digits};
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Which {
+    Created,
+    Modified,
+}
+
+pub const OLE_MAGIC: &'static [u8] = b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1";
+// F29F85E0-4FF9-1068-AB91-08002B27B3D9, as stored
+const SUMMARY_FMTID: &'static [u8] = b"\xE0\x85\x9F\xF2\xF9\x4F\x68\x10\xAB\x91\x08\x00\x2B\x27\xB3\xD9";
+const PIDSI_CREATE_DTM: u32 = 0x0C;
+const PIDSI_LASTSAVE_DTM: u32 = 0x0D;
+const VT_FILETIME: u32 = 0x40;
+
+pub fn date(path: &Path, which: Which) -> Option<NaiveDateTime> {
+    let mut data = vec![];
+    if File::open(path).and_then(|mut f| f.read_to_end(&mut data)).is_err() {
+        return None;
+    }
+    if data.starts_with(b"PK") {
+        ooxml_date(&data, which)
+    } else if data.starts_with(OLE_MAGIC) {
+        ole_date(&data, which)
+    } else {
+        None
+    }
+}
+
+fn ooxml_date(data: &[u8], which: Which) -> Option<NaiveDateTime> {
+    let mut zip = match zip::ZipArchive::new(Cursor::new(data)) {
+        Ok(zip) => zip,
+        Err(_) => return None,
+    };
+    let mut core = vec![];
+    match zip.by_name("docProps/core.xml") {
+        Ok(mut f) => {
+            if f.read_to_end(&mut core).is_err() {
+                return None;
+            }
+        }
+        Err(_) => return None,
+    }
+    let key: &[u8] = match which {
+        Which::Created => b"<dcterms:created",
+        Which::Modified => b"<dcterms:modified",
+    };
+    // skip the xsi:type attribute to the element's text
+    let start = match find(&core, key).and_then(|i| find(&core[i..], b">").map(|j| i + j)) {
+        Some(i) => i,
+        None => return None,
+    };
+    digits_after(&core[start..], b">", b"", b"-T:").and_then(|d| from_digits(&d))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 4).map(|b| {
+        b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24
+    })
+}
+
+fn ole_date(data: &[u8], which: Which) -> Option<NaiveDateTime> {
+    // the stream starts with byte order, version, system id and class id,
+    // then the count of sections and the first one's format id and offset
+    let stream = match find(data, SUMMARY_FMTID) {
+        Some(i) if i >= 28 => i - 28,
+        _ => return None,
+    };
+    let section = match u32_at(data, stream + 44) {
+        Some(offset) => stream + offset as usize,
+        None => return None,
+    };
+    let count = match u32_at(data, section + 4) {
+        Some(count) => count as usize,
+        None => return None,
+    };
+    let wanted = match which {
+        Which::Created => PIDSI_CREATE_DTM,
+        Which::Modified => PIDSI_LASTSAVE_DTM,
+    };
+    for i in 0..count {
+        let entry = section + 8 + i * 8;
+        if u32_at(data, entry) != Some(wanted) {
+            continue;
+        }
+        let value = match u32_at(data, entry + 4) {
+            Some(offset) => section + offset as usize,
+            None => return None,
+        };
+        if u32_at(data, value) != Some(VT_FILETIME) {
+            return None;
+        }
+        let low = u32_at(data, value + 4);
+        let high = u32_at(data, value + 8);
+        return match (low, high) {
+            (Some(low), Some(high)) => from_filetime((high as u64) << 32 | low as u64),
+            _ => None,
+        };
+    }
+    None
+}
+
+// FILETIMEs count 100ns intervals since 1601-01-01; unset ones are zero
+pub fn from_filetime(ft: u64) -> Option<NaiveDateTime> {
+    const UNIX_EPOCH_OFFSET: i64 = 11644473600;
+    if ft == 0 {
+        return None;
+    }
+    NaiveDateTime::from_timestamp_opt((ft / 10000000) as i64 - UNIX_EPOCH_OFFSET, 0)
+}
@@ -0,0 +1,211 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+// --long-paths: destinations longer than the output filesystem takes, which
+// deep source trees below a few levels of date folders easily make on SMB
+// shares, are caught before the move and either reported or shortened: by
+// their file name first, then by the folders below the top one if need be.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use hash;
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    // report the file and leave it
+    Error,
+    // cut the middle out of the name, "holiday_in_the_alps_2019_001.jpg"
+    // shortened by 10 bytes becoming "holiday_i~2019_001.jpg"
+    Truncate,
+    // end the file name with a hash of the whole name instead
+    Hash,
+}
+
+impl Strategy {
+    pub fn parse(name: &str) -> Option<Strategy> {
+        match name {
+            "error" => Some(Strategy::Error),
+            "truncate" => Some(Strategy::Truncate),
+            "hash" => Some(Strategy::Hash),
+            _ => None,
+        }
+    }
+}
+
+// the longest path and file name, in bytes
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub path: usize,
+    pub name: usize,
+}
+
+// the limits of the filesystem `dir` (or the nearest existing folder above
+// it) is on, `max_path` taking the place of its path limit if given
+pub fn limits(dir: &Path, max_path: Option<usize>) -> Limits {
+    let dir = absolute(dir);
+    let existing = dir.ancestors().find(|d| d.is_dir()).unwrap_or(&dir);
+    let (path, name) = fs_limits(existing);
+    Limits {
+        path: max_path.unwrap_or(path),
+        name: name,
+    }
+}
+
+#[cfg(unix)]
+fn fs_limits(dir: &Path) -> (usize, usize) {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let conf = |name| {
+        CString::new(dir.as_os_str().as_bytes())
+            .ok()
+            .map(|c| unsafe { libc::pathconf(c.as_ptr(), name) })
+            .filter(|&n| n > 0)
+            .map(|n| n as usize)
+    };
+    // PATH_MAX counts the terminating NUL
+    (conf(libc::_PC_PATH_MAX).map_or(4095, |n| n - 1), conf(libc::_PC_NAME_MAX).unwrap_or(255))
+}
+
+// MAX_PATH, without long path support
+#[cfg(not(unix))]
+fn fs_limits(_: &Path) -> (usize, usize) {
+    (259, 255)
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().map(|d| d.join(path)).unwrap_or(path.to_path_buf())
+    }
+}
+
+// shortened names are kept at least this long, so they stay recognizable
+const MIN_NAME: usize = 24;
+
+// `path`, a destination below `output`, if it is within `limits`, or else
+// shortened by `strategy`. Names over the name limit are shortened to it;
+// then, while the path is over its limit, the file name and after it the
+// folders, deepest first, down to MIN_NAME bytes. The top folder below
+// `output`, the year folder of most layouts, is never changed.
+pub fn fit(path: &Path,
+           output: &Path,
+           limits: Limits,
+           strategy: Strategy)
+           -> Result<PathBuf, String> {
+    let rel = path.strip_prefix(output).unwrap_or(path);
+    let mut names: Vec<String> = rel.components()
+                                    .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                                    .collect();
+    let length = |names: &[String]| {
+        absolute(output).as_os_str().len() + names.iter().map(|n| n.len() + 1).sum::<usize>()
+    };
+    let over_path = length(&names).saturating_sub(limits.path);
+    let over_name = names.iter().map(|n| n.len().saturating_sub(limits.name)).max().unwrap_or(0);
+    let excess = over_path.max(over_name);
+    if excess == 0 {
+        return Ok(path.to_path_buf());
+    }
+    let too_long = || {
+        format!("path too long by {} bytes ({} at most, {} for a file name)",
+                excess,
+                limits.path,
+                limits.name)
+    };
+    if strategy == Strategy::Error || names.len() < 2 {
+        return Err(too_long());
+    }
+    let last = names.len() - 1;
+    for i in 1..names.len() {
+        if names[i].len() > limits.name {
+            let over = names[i].len() - limits.name;
+            names[i] = try!(shorten(&names[i], over, i == last, strategy).ok_or_else(too_long));
+        }
+    }
+    // the file name, then the folders from the deepest up
+    for i in (1..names.len()).rev() {
+        let over = length(&names).saturating_sub(limits.path);
+        if over == 0 {
+            break;
+        }
+        let over = over.min(names[i].len().saturating_sub(MIN_NAME));
+        if over > 0 {
+            if let Some(short) = shorten(&names[i], over, i == last, strategy) {
+                names[i] = short;
+            }
+        }
+    }
+    if length(&names) > limits.path {
+        return Err(too_long());
+    }
+    let mut fitted = output.to_path_buf();
+    fitted.extend(names);
+    Ok(fitted)
+}
+
+// `name` made `excess` bytes shorter by `strategy`, keeping the extension of
+// a file name; None if it is too short for that
+fn shorten(name: &str, excess: usize, is_file: bool, strategy: Strategy) -> Option<String> {
+    let (stem, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 && is_file => (&name[..dot], &name[dot..]),
+        _ => (name, ""),
+    };
+    match strategy {
+        Strategy::Truncate => {
+            // one byte of the excess goes to the ~ that marks the cut
+            stem.len().checked_sub(excess + 1).map(|keep| {
+                let head = prefix(stem, (keep + 1) / 2);
+                let tail = suffix(stem, keep - head.len());
+                format!("{}~{}{}", head, tail, ext)
+            })
+        }
+        _ => {
+            // the same name is always shortened the same way
+            let mut hasher = hash::Hasher::new(hash::Algorithm::Fnv);
+            hasher.update(name.as_bytes());
+            let digest = format!("_{}", hasher.finish().hex());
+            stem.len()
+                .checked_sub(excess + digest.len())
+                .map(|keep| format!("{}{}{}", prefix(stem, keep), digest, ext))
+        }
+    }
+}
+
+// the longest start of `s` within `bytes`, on a character boundary
+fn prefix(s: &str, bytes: usize) -> &str {
+    let mut end = bytes.min(s.len());
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+// the longest end of `s` within `bytes`
+fn suffix(s: &str, bytes: usize) -> &str {
+    let mut start = s.len() - bytes.min(s.len());
+    while !s.is_char_boundary(start) {
+        start += 1;
+    }
+    &s[start..]
+}
@@ -0,0 +1,301 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+
+// --pattern and --not-pattern: shell globs matched against the file name, or,
+// if they contain a `/`, against its path below the source directory, where
+// `*` stops at folders and `**/` stands for any number of them
+// (`raw/**/*.cr2`). `{jpg,png}` matches either alternative. With
+// --smart-case, patterns in lower case ignore case, as in ripgrep.
+//
+// A --pattern starting with `!` takes back what the patterns before it
+// matched, and the last pattern matching a file decides, as in .gitignore:
+// `-p '*.jpg' -p '!thumbs/**' -p 'thumbs/keep-*'`. A literal leading `!` is
+// written `\!`.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Component, Path};
+
+use glob::{MatchOptions, Pattern};
+
+use {exit, Args, EXIT_USAGE};
+
+
+struct Glob {
+    pattern: Pattern,
+    // matched against the relative path rather than the name
+    path: bool,
+    case_sensitive: bool,
+    // a --pattern starting with `!`
+    negated: bool,
+}
+
+impl Glob {
+    fn matches(&self, name: &str, rel: &str) -> bool {
+        let options = MatchOptions {
+            case_sensitive: self.case_sensitive,
+            require_literal_separator: self.path,
+            require_literal_leading_dot: false,
+        };
+        self.pattern.matches_with(if self.path { rel } else { name }, &options)
+    }
+}
+
+// `pattern` with each `{a,b,...}` replaced by its alternatives, e.g.
+// `*.{jpg,png}` by `*.jpg` and `*.png`; sets can be nested
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let open = match pattern.find('{') {
+        Some(i) => i,
+        None => return vec![pattern.to_string()],
+    };
+    // the matching brace, and the commas at its level
+    let mut depth = 0;
+    let mut commas = vec![];
+    let mut close = None;
+    for (i, c) in pattern[open..].char_indices().map(|(i, c)| (i + open, c)) {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(i);
+                    break;
+                }
+            }
+            ',' if depth == 1 => commas.push(i),
+            _ => {}
+        }
+    }
+    let close = match close {
+        Some(i) => i,
+        // unbalanced, taken as is
+        None => return vec![pattern.to_string()],
+    };
+    let (head, tail) = (&pattern[..open], &pattern[close + 1..]);
+    let mut bounds = vec![open];
+    bounds.extend(commas);
+    bounds.push(close);
+    let mut expanded = vec![];
+    for pair in bounds.windows(2) {
+        let alternative = &pattern[pair[0] + 1..pair[1]];
+        expanded.extend(expand_braces(&format!("{}{}{}", head, alternative, tail)));
+    }
+    expanded
+}
+
+fn compile(pattern: &str, smart_case: bool, negated: bool) -> Result<Vec<Glob>, String> {
+    let case_sensitive = !smart_case || pattern.chars().any(char::is_uppercase);
+    let mut globs = vec![];
+    for p in expand_braces(pattern) {
+        // a leading `./` or `/` only anchors the pattern at the source directory
+        let path = p.contains('/');
+        let p = p.trim_start_matches("./").trim_start_matches('/');
+        globs.push(Glob {
+            pattern: try!(Pattern::new(p).map_err(|e| e.to_string())),
+            path: path,
+            case_sensitive: case_sensitive,
+            negated: negated,
+        });
+    }
+    Ok(globs)
+}
+
+// the patterns of a --patterns-from file: one per line, without blank lines
+// and # comments; leading and trailing spaces are dropped
+fn read(path: &str) -> io::Result<Vec<String>> {
+    let mut text = String::new();
+    try!(File::open(path).and_then(|mut f| f.read_to_string(&mut text)));
+    Ok(text.lines()
+           .map(str::trim)
+           .filter(|line| !line.is_empty() && !line.starts_with('#'))
+           .map(String::from)
+           .collect())
+}
+
+// the patterns given on the command line and in files
+fn gather(patterns: &[String], files: &[String]) -> Vec<String> {
+    let mut all = patterns.to_vec();
+    for path in files {
+        all.extend(read(path).unwrap_or_else(|e| {
+            log_error!("Error: patterns from {:?}: {}", path, e);
+            exit(EXIT_USAGE);
+        }));
+    }
+    all
+}
+
+// --pattern and --not-pattern, with those read from files
+pub fn cli_patterns(args: &Args) -> (Vec<String>, Vec<String>) {
+    (gather(&args.flag_pattern, &args.flag_patterns_from),
+     gather(&args.flag_not_pattern, &args.flag_not_patterns_from))
+}
+
+// Whether a file is matched by --pattern and not by --not-pattern, given its
+// path below the source directory; exits on an invalid pattern.
+pub fn filter(args: &Args) -> Box<dyn Fn(&Path) -> bool> {
+    let (matching, not_matching) = cli_patterns(args);
+    build(&matching, &not_matching, args.flag_smart_case).unwrap_or_else(|e| {
+        log_error!("Error: {}", e);
+        exit(EXIT_USAGE);
+    })
+}
+
+// Whether a file is taken by some --pattern, whether or not a `!` pattern or
+// --not-pattern leaves it out again; tells files excluded from those never
+// matched.
+pub fn included(args: &Args) -> Box<dyn Fn(&Path) -> bool> {
+    let (matching, _) = cli_patterns(args);
+    let positive: Vec<String> = matching.into_iter().filter(|p| !p.starts_with('!')).collect();
+    build(&positive, &[], args.flag_smart_case).unwrap_or_else(|e| {
+        log_error!("Error: {}", e);
+        exit(EXIT_USAGE);
+    })
+}
+
+// Whether a file is left out by --not-pattern alone; archives are matched by
+// their members, but can still be excluded as a whole.
+pub fn excluded(args: &Args) -> Box<dyn Fn(&Path) -> bool> {
+    let (_, not_matching) = cli_patterns(args);
+    let not_matching = compile_all(&not_matching, false, args.flag_smart_case).unwrap_or_else(|e| {
+        log_error!("Error: {}", e);
+        exit(EXIT_USAGE);
+    });
+    Box::new(move |rel: &Path| {
+        let (name, rel) = name_and_rel(rel);
+        not_matching.iter().any(|g| g.matches(&name, &rel))
+    })
+}
+
+// The filter of `filter` for any lists of patterns
+pub fn build(matching: &[String],
+             not_matching: &[String],
+             smart_case: bool)
+             -> Result<Box<dyn Fn(&Path) -> bool>, String> {
+    let matching = try!(compile_all(matching, true, smart_case));
+    let not_matching = try!(compile_all(not_matching, false, smart_case));
+    // with only negated patterns, what they don't take back is matched
+    let by_default = matching.iter().all(|g| g.negated);
+    Ok(Box::new(move |rel: &Path| {
+        let (name, rel) = name_and_rel(rel);
+        let matched = match matching.iter().rev().find(|g| g.matches(&name, &rel)) {
+            Some(g) => !g.negated,
+            None => by_default,
+        };
+        matched && !not_matching.iter().any(|g| g.matches(&name, &rel))
+    }))
+}
+
+// --explain: which of --pattern and --not-pattern decide about `rel`, the
+// way `filter` goes about it
+pub fn explain(args: &Args, rel: &Path) -> String {
+    let (matching, not_matching) = cli_patterns(args);
+    let smart_case = args.flag_smart_case;
+    let (matching, not_matching) = match (compile_all(&matching, true, smart_case),
+                                          compile_all(&not_matching, false, smart_case)) {
+        (Ok(m), Ok(n)) => (m, n),
+        _ => return "invalid patterns".to_string(),
+    };
+    let (name, rel) = name_and_rel(rel);
+    let mut text = match matching.iter().rev().find(|g| g.matches(&name, &rel)) {
+        Some(g) if g.negated => format!("left out by --pattern \"!{}\"", g.pattern.as_str()),
+        Some(g) => format!("taken by --pattern {:?}", g.pattern.as_str()),
+        None if matching.is_empty() => "taken, no --pattern given".to_string(),
+        None if matching.iter().all(|g| g.negated) => {
+            "taken, no `!` pattern matches".to_string()
+        }
+        None => "matched by no --pattern".to_string(),
+    };
+    if let Some(g) = not_matching.iter().find(|g| g.matches(&name, &rel)) {
+        text.push_str(&format!(", left out by --not-pattern {:?}", g.pattern.as_str()));
+    }
+    text
+}
+
+// How many files each --pattern and --not-pattern matched, so that those
+// matching none, most likely mistyped, can be reported
+pub struct Usage {
+    rules: Vec<(String, Vec<Glob>, usize)>,
+}
+
+impl Usage {
+    pub fn new(args: &Args) -> Usage {
+        let (matching, not_matching) = cli_patterns(args);
+        let mut rules = vec![];
+        for (option, patterns, negatable) in vec![("--pattern", matching, true),
+                                                  ("--not-pattern", not_matching, false)] {
+            for p in patterns {
+                // invalid patterns are reported by `filter`
+                if let Ok(globs) = compile_all(&[p.clone()], negatable, args.flag_smart_case) {
+                    rules.push((format!("{} {:?}", option, p), globs, 0));
+                }
+            }
+        }
+        Usage { rules: rules }
+    }
+
+    // counts the patterns matching `rel`, whether they decide about it or not
+    pub fn record(&mut self, rel: &Path) {
+        let (name, rel) = name_and_rel(rel);
+        for &mut (_, ref globs, ref mut hits) in &mut self.rules {
+            if globs.iter().any(|g| g.matches(&name, &rel)) {
+                *hits += 1;
+            }
+        }
+    }
+
+    // the options with the patterns that matched no file, e.g. `--pattern "*.jgp"`
+    pub fn unmatched(&self) -> Vec<&str> {
+        self.rules.iter().filter(|r| r.2 == 0).map(|r| &r.0[..]).collect()
+    }
+}
+
+// a leading `!` only negates in --pattern
+fn compile_all(patterns: &[String],
+               negatable: bool,
+               smart_case: bool)
+               -> Result<Vec<Glob>, String> {
+    let mut globs = vec![];
+    for p in patterns {
+        let (negated, glob) = match p.chars().next() {
+            Some('!') if negatable => (true, &p[1..]),
+            Some('\\') if negatable && p[1..].starts_with('!') => (false, &p[1..]),
+            _ => (false, &p[..]),
+        };
+        globs.extend(try!(compile(glob, smart_case, negated)
+                              .map_err(|e| format!("pattern {:?}: {}", p, e))));
+    }
+    Ok(globs)
+}
+
+// the file name and the path, with `/` between folders whatever the platform
+fn name_and_rel(rel: &Path) -> (String, String) {
+    let name = rel.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let rel: Vec<_> = rel.components()
+                         .filter_map(|c| match c {
+                             Component::Normal(c) => Some(c.to_string_lossy()),
+                             _ => None,
+                         })
+                         .collect();
+    (name, rel.join("/"))
+}
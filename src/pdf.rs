@@ -0,0 +1,67 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// The creation date of PDF documents, from the document info dictionary
+// (/CreationDate (D:20190714123456+02'00')) or XMP metadata
+// (<xmp:CreateDate>2019-07-14T12:34:56+02:00</xmp:CreateDate>).
+//
+// Both are searched for as plain text near the start and the end of the file,
+// where writers put them; documents that keep them in compressed object
+// streams are dated by the next source.
+
+use std::cmp;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use chrono::*;
+
+use dates::{digits_after, from_digits};
+
+
+const WINDOW: u64 = 1024 * 1024;
+
+// the first and last WINDOW bytes of the file
+fn head_and_tail(path: &Path) -> io::Result<Vec<u8>> {
+    let mut file = try!(File::open(path));
+    let len = try!(file.metadata()).len();
+    let mut buf = vec![];
+    try!((&mut file).take(WINDOW).read_to_end(&mut buf));
+    if len > WINDOW {
+        try!(file.seek(SeekFrom::Start(cmp::max(WINDOW, len - WINDOW))));
+        try!(file.read_to_end(&mut buf));
+    }
+    Ok(buf)
+}
+
+pub fn creation_date(path: &Path) -> Option<NaiveDateTime> {
+    let data = match head_and_tail(path) {
+        Ok(ref data) if data.starts_with(b"%PDF") => data.clone(),
+        _ => return None,
+    };
+    // the time zone offset is left out; dates are kept as local time, like
+    // mtimes
+    digits_after(&data, b"/CreationDate", b" (D:", b"")
+        .or_else(|| digits_after(&data, b"xmp:CreateDate", b">=\"", b"-T:"))
+        .and_then(|d| from_digits(&d))
+}
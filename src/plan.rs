@@ -0,0 +1,260 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Saved plans: the moves a run decided on, written as JSON by --save-plan,
+// and `plan-diff` to compare two of them. Either side of a diff can also be
+// the manifest of a run that was carried out. --plan-format prints the moves
+// of a dry run for other tools instead.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use rustc_serialize::json;
+
+use executor::MoveOp;
+use manifest;
+
+
+#[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
+pub struct Move {
+    pub src: String,
+    pub dst: String,
+    // the date the batch was filed under
+    pub date: String,
+    // how dst was made from src: structure, flatten, layout or script; not
+    // known for moves read from a manifest
+    pub mode: Option<String>,
+    // the --on-conflict action taken because the destination was taken
+    pub conflict: Option<String>,
+}
+
+#[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
+pub struct Plan {
+    // the run that made the plan; `apply` records its moves under this id,
+    // and so can tell a plan that was applied already
+    pub id: Option<String>,
+    pub moves: Vec<Move>,
+}
+
+// Writes a plan for --save-plan move by move as the run decides on them, so
+// that the moves of a large run are never all held in memory. They go to a
+// hidden temporary file next to it, which only replaces an earlier plan once
+// finished, so a run that stops early leaves that plan as it was.
+pub struct Writer {
+    file: BufWriter<File>,
+    path: PathBuf,
+    tmp: PathBuf,
+    moves: usize,
+}
+
+// --plan-format: how a dry run prints its moves on stdout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    // "move SRC DST", for people
+    Text,
+    // a Move per line
+    Json,
+    Csv,
+    Tsv,
+    // SRC and DST, each ended by a NUL, for `xargs -0 -n 2`
+    NullSeparated,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            "tsv" => Some(Format::Tsv),
+            "null-separated" => Some(Format::NullSeparated),
+            _ => None,
+        }
+    }
+
+    // the line naming the columns, printed before the moves
+    pub fn header(&self) -> Option<&'static str> {
+        match *self {
+            Format::Csv => Some("src,dst,date,mode,conflict"),
+            Format::Tsv => Some("src\tdst\tdate\tmode\tconflict"),
+            _ => None,
+        }
+    }
+}
+
+// prints `op`, whose destination was made by `mode`, in `format`; not for
+// Format::Text, which goes to the log
+pub fn print(op: &MoveOp, mode: &str, format: Format) {
+    let m = Move {
+        src: op.src.to_string_lossy().into_owned(),
+        dst: op.dst.to_string_lossy().into_owned(),
+        date: op.date.format("%Y-%m-%dT%H:%M:%S").to_string(),
+        mode: Some(mode.to_string()),
+        conflict: op.conflict.map(|c| c.to_string()),
+    };
+    let fields = [&m.src[..], &m.dst[..], &m.date[..], mode, op.conflict.unwrap_or("")];
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let _ = match format {
+        Format::Text => Ok(()),
+        Format::Json => writeln!(out, "{}", json::encode(&m).unwrap()),
+        Format::Csv => {
+            let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+            writeln!(out, "{}", fields.join(","))
+        }
+        Format::Tsv => {
+            let fields: Vec<String> = fields.iter().map(|f| tsv_field(f)).collect();
+            writeln!(out, "{}", fields.join("\t"))
+        }
+        Format::NullSeparated => {
+            // the raw paths, whatever bytes they are made of
+            out.write_all(path_bytes(&op.src).as_ref())
+               .and_then(|_| out.write_all(b"\0"))
+               .and_then(|_| out.write_all(path_bytes(&op.dst).as_ref()))
+               .and_then(|_| out.write_all(b"\0"))
+        }
+    };
+}
+
+// quoted if it has to be, with quotes doubled, as in RFC 4180
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// with tabs, line breaks and backslashes escaped, as in PostgreSQL's COPY
+fn tsv_field(field: &str) -> String {
+    field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Cow<[u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Cow<[u8]> {
+    Cow::Owned(path.to_string_lossy().into_owned().into_bytes())
+}
+
+impl Writer {
+    pub fn create(path: &Path, id: &str) -> io::Result<Writer> {
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let tmp = path.with_file_name(format!(".{}.organize-tmp", name));
+        let mut file = BufWriter::new(try!(File::create(&tmp)));
+        try!(write!(file, "{{\n  \"id\": {},\n  \"moves\": [", json::encode(&id).unwrap()));
+        Ok(Writer {
+            file: file,
+            path: path.to_path_buf(),
+            tmp: tmp,
+            moves: 0,
+        })
+    }
+
+    pub fn push(&mut self, m: &Move) -> io::Result<()> {
+        let sep = if self.moves == 0 { "" } else { "," };
+        self.moves += 1;
+        write!(self.file, "{}\n    {}", sep, json::encode(m).unwrap())
+    }
+
+    // closes the JSON and puts the plan in place
+    pub fn finish(&mut self) -> io::Result<()> {
+        try!(write!(self.file, "\n  ]\n}}\n"));
+        try!(self.file.flush());
+        fs::rename(&self.tmp, &self.path)
+    }
+}
+
+impl Move {
+    pub fn new(op: &MoveOp, mode: &str) -> Move {
+        Move {
+            src: op.src.to_string_lossy().into_owned(),
+            dst: op.dst.to_string_lossy().into_owned(),
+            date: op.date.format("%Y-%m-%dT%H:%M:%S").to_string(),
+            mode: Some(mode.to_string()),
+            conflict: op.conflict.map(|c| c.to_string()),
+        }
+    }
+}
+
+impl Plan {
+    // reads a plan saved with --save-plan, or a manifest
+    pub fn load(path: &Path) -> Result<Plan, String> {
+        let mut text = String::new();
+        try!(File::open(path).and_then(|mut f| f.read_to_string(&mut text)).map_err(|e| e.to_string()));
+        if text.trim_start().starts_with('{') {
+            return json::decode(&text).map_err(|e| e.to_string());
+        }
+        let entries = try!(manifest::read(path).map_err(|e| e.to_string()));
+        let entries = manifest::without_rollbacks(entries);
+        Ok(Plan {
+            id: None,
+            moves: entries.into_iter()
+                          .map(|e| {
+                              Move {
+                                  src: e.src.to_string_lossy().into_owned(),
+                                  dst: e.dst.to_string_lossy().into_owned(),
+                                  date: e.date,
+                                  mode: None,
+                                  conflict: e.conflict,
+                              }
+                          })
+                          .collect(),
+        })
+    }
+
+    // destination by source; a later move of the same source wins
+    fn by_src(&self) -> BTreeMap<&str, &str> {
+        self.moves.iter().map(|m| (&m.src[..], &m.dst[..])).collect()
+    }
+}
+
+// prints the moves added, removed and retargeted from `old` to `new`;
+// returns the number of differences
+pub fn diff(old: &Plan, new: &Plan) -> usize {
+    let old = old.by_src();
+    let new = new.by_src();
+    let mut differences = 0;
+    for (src, dst) in &old {
+        match new.get(src) {
+            None => println!("- {:?} {:?}", src, dst),
+            Some(new_dst) if new_dst != dst => println!("~ {:?} {:?} -> {:?}", src, dst, new_dst),
+            Some(_) => continue,
+        }
+        differences += 1;
+    }
+    for (src, dst) in &new {
+        if !old.contains_key(src) {
+            println!("+ {:?} {:?}", src, dst);
+            differences += 1;
+        }
+    }
+    differences
+}
@@ -0,0 +1,105 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Checks before a run that the output has room for the files it has to copy
+// there: those on another filesystem, as moves within one are renames and
+// take no space. Runs that would fail halfway for lack of space fail up
+// front instead, with nothing moved.
+
+use std::path::Path;
+
+use stats::human_size;
+use {patterns, walk_source, Args};
+
+pub fn check(args: &Args) -> Result<(), String> {
+    let output = Path::new(&args.flag_output_dir);
+    // the output may not exist yet; the space is that of its filesystem
+    let existing = match output.ancestors().find(|p| p.exists()) {
+        Some(p) => if p.as_os_str().is_empty() { Path::new(".") } else { p },
+        None => return Ok(()),
+    };
+    let (device, free) = match space(existing) {
+        Some(space) => space,
+        None => return Ok(()),
+    };
+    let wanted = patterns::filter(args);
+    let mut needed = 0;
+    for dir in &args.arg_directory {
+        // the common case, with no need to look at every file
+        if device_of(Path::new(dir)) == Some(device) {
+            continue;
+        }
+        for entry in walk_source(dir, args) {
+            if !entry.file_type().is_file() || !wanted(entry.path().strip_prefix(dir).unwrap_or(entry.path())) {
+                continue;
+            }
+            if device_of(entry.path()) != Some(device) {
+                needed += entry.metadata().map(|md| md.len()).unwrap_or(0);
+            }
+        }
+    }
+    if needed > free {
+        return Err(format!("{} to copy to {}, but only {} free",
+                           human_size(needed),
+                           existing.display(),
+                           human_size(free)));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+pub fn device_of(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    path.metadata().ok().map(|md| md.dev())
+}
+
+// the device of `path` and the bytes free there, also for `doctor`
+#[cfg(unix)]
+pub fn space(path: &Path) -> Option<(u64, u64)> {
+    use std::ffi::CString;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(p) => p,
+        Err(_) => return None,
+    };
+    let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    let free = stat.f_bavail as u64 * stat.f_frsize as u64;
+    device_of(path).map(|device| (device, free))
+}
+
+// elsewhere, the check is skipped
+#[cfg(not(unix))]
+pub fn device_of(_: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(not(unix))]
+pub fn space(_: &Path) -> Option<(u64, u64)> {
+    None
+}
@@ -0,0 +1,78 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+
+// --nice and --ionice: lowers the CPU and disk priority of the process from
+// inside, for runs started by systemd timers and the like, where wrapping
+// the command in nice(1) and ionice(1) is awkward.
+
+use std::io;
+
+
+// like nice(1), from -20 (first) to 19 (last); going below 0 takes root
+#[cfg(unix)]
+pub fn set_nice(n: i32) -> io::Result<()> {
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS as _, 0, n) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+pub fn set_nice(_: i32) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "--nice is only supported on Unix"))
+}
+
+// the I/O scheduling classes of ionice(1) that don't need root
+#[cfg(target_os = "linux")]
+fn ioprio(class: &str) -> Option<libc::c_long> {
+    const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+    match class {
+        "idle" => Some(3 << IOPRIO_CLASS_SHIFT),
+        // at the lowest priority within the class
+        "best-effort" => Some(2 << IOPRIO_CLASS_SHIFT | 7),
+        _ => None,
+    }
+}
+
+// `class` is idle, to use the disk only when nothing else does, or
+// best-effort, which still yields to everything at the default priority
+#[cfg(target_os = "linux")]
+pub fn set_ionice(class: &str) -> io::Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+    let prio = try!(ioprio(class).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput,
+                       format!("unknown I/O class {:?}, not idle or best-effort", class))
+    }));
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, prio) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_ionice(_: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "--ionice is only supported on Linux"))
+}
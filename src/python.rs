@@ -0,0 +1,182 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// The Python module, built with the `python` feature (see pyproject.toml):
+//
+//     import organize_by_mtime
+//     plan = organize_by_mtime.plan(["--layout={year}/{month}", "-O", "out", "photos"])
+//     plan.moves = [m for m in plan.moves if not m.src.endswith(".tmp")]
+//     errors = organize_by_mtime.execute(plan, manifest="moves.log")
+//
+// plan() and execute() are api::plan and api::execute; invalid arguments
+// raise ValueError, and a run that would have exited raises RuntimeError.
+
+use std::path::PathBuf;
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+use api::{self, Error};
+use plan;
+
+
+#[pyclass(name = "Move", get_all, set_all)]
+#[derive(Clone)]
+struct Move {
+    src: String,
+    dst: String,
+    date: String,
+    mode: Option<String>,
+    conflict: Option<String>,
+}
+
+#[pymethods]
+impl Move {
+    #[new]
+    #[pyo3(signature = (src, dst, date, mode=None, conflict=None))]
+    fn new(src: String,
+           dst: String,
+           date: String,
+           mode: Option<String>,
+           conflict: Option<String>)
+           -> Move {
+        Move {
+            src: src,
+            dst: dst,
+            date: date,
+            mode: mode,
+            conflict: conflict,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Move({:?}, {:?}, {:?})", self.src, self.dst, self.date)
+    }
+}
+
+#[pyclass(name = "Plan", get_all, set_all)]
+struct Plan {
+    id: Option<String>,
+    moves: Vec<Move>,
+}
+
+#[pymethods]
+impl Plan {
+    #[new]
+    #[pyo3(signature = (moves, id=None))]
+    fn new(moves: Vec<Move>, id: Option<String>) -> Plan {
+        Plan {
+            id: id,
+            moves: moves,
+        }
+    }
+
+    // reads a plan saved with --save-plan, or a manifest
+    #[staticmethod]
+    fn load(path: PathBuf) -> PyResult<Plan> {
+        plan::Plan::load(&path).map(Plan::from).map_err(PyValueError::new_err)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<Plan {} with {} moves>",
+                self.id.as_ref().map(|s| &s[..]).unwrap_or("-"),
+                self.moves.len())
+    }
+}
+
+impl From<plan::Plan> for Plan {
+    fn from(plan: plan::Plan) -> Plan {
+        Plan {
+            id: plan.id,
+            moves: plan.moves
+                       .into_iter()
+                       .map(|m| {
+                           Move {
+                               src: m.src,
+                               dst: m.dst,
+                               date: m.date,
+                               mode: m.mode,
+                               conflict: m.conflict,
+                           }
+                       })
+                       .collect(),
+        }
+    }
+}
+
+impl<'a> From<&'a Plan> for plan::Plan {
+    fn from(plan: &'a Plan) -> plan::Plan {
+        plan::Plan {
+            id: plan.id.clone(),
+            moves: plan.moves
+                       .iter()
+                       .map(|m| {
+                           plan::Move {
+                               src: m.src.clone(),
+                               dst: m.dst.clone(),
+                               date: m.date.clone(),
+                               mode: m.mode.clone(),
+                               conflict: m.conflict.clone(),
+                           }
+                       })
+                       .collect(),
+        }
+    }
+}
+
+fn py_err(e: Error) -> PyErr {
+    match e {
+        Error::Usage(message) => PyValueError::new_err(message),
+        Error::Exit(_) => PyRuntimeError::new_err(e.to_string()),
+    }
+}
+
+// the arguments of `organize-by-mtime plan`, without <plan>
+#[pyfunction]
+#[pyo3(name = "plan")]
+fn make_plan(py: Python, args: Vec<String>) -> PyResult<Plan> {
+    py.allow_threads(|| api::plan(&args)).map(Plan::from).map_err(py_err)
+}
+
+// returns the number of moves rejected or failed
+#[pyfunction]
+#[pyo3(signature = (plan, dry_run=false, force=false, reapply=false, manifest=None))]
+fn execute(py: Python,
+           plan: &Plan,
+           dry_run: bool,
+           force: bool,
+           reapply: bool,
+           manifest: Option<PathBuf>)
+           -> PyResult<usize> {
+    let plan = plan::Plan::from(plan);
+    let manifest = manifest.as_ref().map(PathBuf::as_path);
+    py.allow_threads(|| api::execute(&plan, dry_run, force, reapply, manifest)).map_err(py_err)
+}
+
+#[pymodule]
+fn organize_by_mtime(m: &Bound<PyModule>) -> PyResult<()> {
+    try!(m.add_class::<Move>());
+    try!(m.add_class::<Plan>());
+    try!(m.add_function(try!(wrap_pyfunction!(python::make_plan, m))));
+    m.add_function(try!(wrap_pyfunction!(python::execute, m)))
+}
@@ -0,0 +1,170 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// `reorganize OUTPUT_DIR` moves the files `verify` finds misfiled to where
+// the layout puts files of their own date, keeping their path below the
+// layout folder.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::*;
+
+use executor::{Executor, LocalExecutor, MoveOp};
+use hash;
+use log::{self, Paint};
+use verify;
+use {AgePolicy, Args, Context};
+
+
+// Where the layout puts `path`, dated `date`. `rel` is its path below the
+// year folder, which must be the first folder of the layout; the others are
+// left out of it.
+fn destination(path: &Path,
+               rel: &Path,
+               date: &NaiveDateTime,
+               output: &Path,
+               ctx: &Context)
+               -> Result<PathBuf, String> {
+    let layout = &ctx.layout;
+    let folder = layout.expand(path, output, date, None, &ctx.template_options);
+    let year = date.year().to_string();
+    if folder.components().next().map(|c| c.as_os_str()) != Some(year.as_ref()) {
+        return Err(format!("the layout puts it in {:?}, not in a year folder", folder));
+    }
+    if layout.names_file() {
+        return Ok(output.join(folder));
+    }
+    let depth = folder.components().count();
+    Ok(output.join(&folder).join(rel.components().skip(depth - 1).collect::<PathBuf>()))
+}
+
+// returns error count
+pub fn run(args: &Args, ctx: &mut Context, policy: AgePolicy) -> isize {
+    let output = Path::new(&args.arg_output);
+    let mut executor = LocalExecutor;
+    let mut errors: isize = 0;
+
+    for m in verify::misfiled(output, policy, ctx) {
+        let dst = match m.path.strip_prefix(&m.top) {
+            Ok(rel) => destination(&m.path, rel, &m.date, output, ctx),
+            Err(_) => Err(format!("it isn't in {:?}", m.top)),
+        };
+        let dst = match dst {
+            Ok(dst) => dst,
+            Err(e) => {
+                log_error!("Error: {:?}: {}", m.path, e);
+                errors += 1;
+                continue;
+            }
+        };
+        let op = MoveOp {
+            src: m.path.clone(),
+            dst: dst,
+            date: m.date,
+            compress: false,
+            conflict: None,
+        };
+        log_info!("move {} {}",
+                  log::paint(&format!("{:?}", op.src), Paint::Source),
+                  log::paint(&executor.describe(&op), Paint::Destination));
+        if args.flag_dry_run {
+            continue;
+        }
+        let size = fs::metadata(&op.src).map(|md| md.len()).unwrap_or(0);
+        let checksum = ctx.manifest
+                          .as_ref()
+                          .and_then(|_| hash::file(&op.src, ctx.hash).ok());
+        if let Err(e) = executor.execute(&op, args.flag_force) {
+            log_error!("Error: dest: {:?}: {}", op.dst, e);
+            errors += 1;
+            continue;
+        }
+        if let Some(ref mut manifest) = ctx.manifest {
+            if let Err(e) = manifest.record(&op, size, checksum.as_ref()) {
+                log_error!("Error: manifest: {}", e);
+                errors += 1;
+            }
+        }
+        // drop the folders the move left empty; remove_dir fails on the
+        // first one that isn't
+        let mut dir = op.src.parent();
+        while let Some(d) = dir {
+            if !d.starts_with(&m.top) || fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+    errors
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::path::Path;
+
+    use chrono::*;
+    use filetime;
+
+    use testing::{scratch, with_context};
+    use to_filetime;
+    use super::run;
+
+    fn touch(path: &Path, date: NaiveDate) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(path).unwrap();
+        let date = to_filetime(&date.and_hms(12, 0, 0));
+        filetime::set_file_times(path, date, date).unwrap();
+    }
+
+    // a file misfiled in a YEAR_N folder, as made by --merge=suffix
+    #[test]
+    fn moves_out_of_suffixed_year_folders() {
+        let output = scratch("reorganize-suffixed");
+        touch(&output.join("2019_2/trip/a.jpg"), NaiveDate::from_ymd(2017, 6, 1));
+
+        let argv = ["reorganize", output.to_str().unwrap()];
+        assert_eq!(with_context(&argv, run), Ok(0));
+        assert!(output.join("2017/trip/a.jpg").is_file());
+        assert!(!output.join("2019_2").exists());
+        fs::remove_dir_all(&output).unwrap();
+    }
+
+    // dated by its folder name, and moved within the layout it was filed by
+    #[test]
+    fn dates_and_files_as_the_run_does() {
+        let output = scratch("reorganize-layout");
+        touch(&output.join("2019/06/2017-03-05 party/a.jpg"), NaiveDate::from_ymd(2019, 6, 1));
+
+        let argv = ["reorganize",
+                    "--layout={year}/{month}",
+                    "--date-from-dirname",
+                    output.to_str().unwrap()];
+        assert_eq!(with_context(&argv, run), Ok(0));
+        assert!(output.join("2017/03/2017-03-05 party/a.jpg").is_file());
+        assert!(!output.join("2019").exists());
+        fs::remove_dir_all(&output).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Rsync executor for --output-dir=[user@]host:/path or ssh://[user@]host/path.
+// Scanning, batching and
+// conflict decisions stay local; only the transfer of each file is handed to
+// rsync over ssh, which also creates the remote directory and removes the
+// local file once it has been copied.
+
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use executor::{Executor, MoveOp};
+
+
+const SCHEME: &'static str = "ssh://";
+
+// host:/path, user@host:path or ssh://[user@]host/path, but not C:\path, any
+// other url, or a local file that happens to have a colon in its name
+pub fn is_remote_spec(s: &str) -> bool {
+    split_spec(s).is_some()
+}
+
+// [user@]host and the path on it
+fn split_spec(s: &str) -> Option<(&str, &str)> {
+    if s.starts_with(SCHEME) {
+        let rest = &s[SCHEME.len()..];
+        let i = rest.find('/').unwrap_or(rest.len());
+        return if host_name(&rest[..i]) { Some((&rest[..i], &rest[i..])) } else { None };
+    }
+    let i = match s.find(':') {
+        Some(i) => i,
+        None => return None,
+    };
+    if !host_name(&s[..i]) || s[i..].starts_with("://") || Path::new(s).exists() {
+        return None;
+    }
+    Some((&s[..i], &s[i + 1..]))
+}
+
+// [user@]host: letters, digits, dots, dashes and underscores only, and more
+// than the single letter of a drive
+fn host_name(s: &str) -> bool {
+    let host = s.rsplit('@').next().unwrap_or(s);
+    let plain = |part: &str| {
+        !part.is_empty() &&
+        part.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_')
+    };
+    host.len() > 1 && s.split('@').count() <= 2 && s.split('@').all(plain)
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+
+pub struct RsyncExecutor {
+    // the output directory as given; destinations start with it
+    prefix: String,
+    host: String,
+    root: String,
+}
+
+impl RsyncExecutor {
+    pub fn new(spec: &str) -> RsyncExecutor {
+        let (host, root) = split_spec(spec).unwrap();
+        RsyncExecutor {
+            prefix: spec.trim_end_matches('/').to_string(),
+            host: host.to_string(),
+            root: root.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn remote_path(&self, dst: &Path) -> io::Result<String> {
+        let dst = dst.to_string_lossy();
+        let rel = try!(dst.strip_prefix(&self.prefix[..]).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput,
+                           "destination outside the remote output directory")
+        }));
+        let rel = rel.replace('\\', "/");
+        let rel = rel.trim_start_matches('/');
+        if self.root.is_empty() {
+            Ok(rel.to_string())
+        } else {
+            Ok(format!("{}/{}", self.root, rel))
+        }
+    }
+}
+
+impl Executor for RsyncExecutor {
+    fn execute(&mut self, op: &MoveOp, force: bool) -> io::Result<()> {
+        if op.compress {
+            return Err(io::Error::new(io::ErrorKind::Other,
+                                      "compression is not supported for host:path outputs"));
+        }
+        let remote = try!(self.remote_path(&op.dst));
+        let parent = match remote.rfind('/') {
+            Some(i) => &remote[..i],
+            None => ".",
+        };
+        let mut command = Command::new("rsync");
+        command.arg("--times")
+               .arg("--protect-args")
+               .arg("--remove-source-files")
+               .arg(format!("--rsync-path=mkdir -p {} && rsync", shell_quote(parent)));
+        if !force {
+            command.arg("--ignore-existing");
+        }
+        let status = try!(command.arg(&op.src)
+                                 .arg(format!("{}:{}", self.host, remote))
+                                 .status());
+        if !status.success() {
+            return Err(io::Error::new(io::ErrorKind::Other, format!("rsync failed: {}", status)));
+        }
+        // rsync skips existing files silently and then keeps the source
+        if op.src.exists() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                      "destination file already exists"));
+        }
+        Ok(())
+    }
+}
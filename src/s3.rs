@@ -0,0 +1,149 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// S3 executor for --output-dir=s3://bucket/prefix. Transfers are done by the
+// `aws` command line tool, which takes care of credentials, S3-compatible
+// endpoints (AWS_ENDPOINT_URL) and multipart uploads of large files. The
+// local file is removed once its upload has completed.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use filetime::FileTime;
+use zstd;
+
+use executor::{Executor, MoveOp};
+use unix_seconds;
+
+
+pub const SCHEME: &'static str = "s3://";
+
+pub fn is_s3_url(s: &str) -> bool {
+    s.starts_with(SCHEME)
+}
+
+
+pub struct S3Executor {
+    // the output directory url as given; destinations start with it
+    prefix: String,
+    bucket: String,
+    key_prefix: String,
+}
+
+impl S3Executor {
+    pub fn new(url: &str) -> io::Result<S3Executor> {
+        let rest = &url[SCHEME.len()..];
+        let (bucket, key_prefix) = match rest.find('/') {
+            Some(i) => (&rest[..i], rest[i + 1..].trim_end_matches('/')),
+            None => (rest, ""),
+        };
+        if bucket.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no bucket in url"));
+        }
+        // fail early rather than on the first file
+        let status = Command::new("aws")
+                         .arg("--version")
+                         .stdout(Stdio::null())
+                         .stderr(Stdio::null())
+                         .status();
+        if !status.map(|s| s.success()).unwrap_or(false) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "the aws command is not available"));
+        }
+        Ok(S3Executor {
+            prefix: url.trim_end_matches('/').to_string(),
+            bucket: bucket.to_string(),
+            key_prefix: key_prefix.to_string(),
+        })
+    }
+
+    fn key(&self, dst: &Path) -> io::Result<String> {
+        let dst = dst.to_string_lossy();
+        let rel = try!(dst.strip_prefix(&self.prefix[..]).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput,
+                           "destination outside the s3 output directory")
+        }));
+        let rel = rel.replace('\\', "/");
+        let rel = rel.trim_start_matches('/');
+        if self.key_prefix.is_empty() {
+            Ok(rel.to_string())
+        } else {
+            Ok(format!("{}/{}", self.key_prefix, rel))
+        }
+    }
+
+    fn exists(&self, key: &str) -> bool {
+        Command::new("aws")
+            .args(&["s3api", "head-object", "--bucket", &self.bucket, "--key", key])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|s| s.success())
+            .unwrap_or(false)
+    }
+}
+
+fn check(status: io::Result<::std::process::ExitStatus>) -> io::Result<()> {
+    let status = try!(status);
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::Other, format!("aws s3 cp failed: {}", status)))
+    }
+}
+
+impl Executor for S3Executor {
+    fn execute(&mut self, op: &MoveOp, force: bool) -> io::Result<()> {
+        let key = try!(self.key(&op.dst));
+        if !force && self.exists(&key) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                      "destination object already exists"));
+        }
+        let url = format!("{}{}/{}", SCHEME, self.bucket, key);
+        let md = try!(fs::metadata(&op.src));
+        let mtime = unix_seconds(&FileTime::from_last_modification_time(&md));
+        let metadata = format!("mtime={}", mtime);
+
+        let mut command = Command::new("aws");
+        command.args(&["s3", "cp", "--only-show-errors", "--metadata", &metadata]);
+        if op.compress {
+            // stream the compressed data through stdin
+            let mut child = try!(command.arg("-")
+                                        .arg(&url)
+                                        .stdin(Stdio::piped())
+                                        .spawn());
+            let copied = {
+                let input = try!(File::open(&op.src));
+                let stdin = child.stdin.take().unwrap();
+                zstd::stream::copy_encode(input, stdin, 0)
+            };
+            let status = child.wait();
+            try!(copied);
+            try!(check(status));
+        } else {
+            try!(check(command.arg(&op.src).arg(&url).status()));
+        }
+        fs::remove_file(&op.src)
+    }
+}
@@ -0,0 +1,87 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Destination scripts: a user-supplied rhai script that computes where a
+// file should go, for naming schemes the built-in layout can't express.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::*;
+use rhai::{Engine, Scope, AST};
+
+use file_mtime;
+
+
+pub struct DestScript {
+    engine: Engine,
+    ast: AST,
+}
+
+impl DestScript {
+    pub fn load(path: &Path) -> Result<DestScript, String> {
+        let engine = Engine::new();
+        let ast = try!(engine.compile_file(path.to_path_buf())
+                             .map_err(|e| format!("{}: {}", path.display(), e)));
+        Ok(DestScript {
+            engine: engine,
+            ast: ast,
+        })
+    }
+
+    // Evaluates the script for a single file and returns the destination
+    // relative to the output directory.
+    //
+    // The script sees `src` (the source path), `path` (the source path after
+    // --strip), `name`, `size`, `mtime` (the file's own mtime) and `date`
+    // together with `year`, `month` and `day` (the batch date).
+    pub fn destination(&self,
+                       src: &Path,
+                       rel: &Path,
+                       datetime: &NaiveDateTime)
+                       -> Result<PathBuf, String> {
+        let md = try!(fs::metadata(src).map_err(|e| e.to_string()));
+        let mtime = file_mtime(&md);
+        let name = src.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+
+        let mut scope = Scope::new();
+        scope.push("src", src.to_string_lossy().into_owned());
+        scope.push("path", rel.to_string_lossy().into_owned());
+        scope.push("name", name);
+        scope.push("size", md.len() as i64);
+        scope.push("mtime", mtime.format("%Y-%m-%dT%H:%M:%S").to_string());
+        scope.push("date", datetime.format("%Y-%m-%dT%H:%M:%S").to_string());
+        scope.push("year", datetime.year() as i64);
+        scope.push("month", datetime.month() as i64);
+        scope.push("day", datetime.day() as i64);
+
+        let result: String = try!(self.engine
+                                      .eval_ast_with_scope(&mut scope, &self.ast)
+                                      .map_err(|e| e.to_string()));
+        let dest = PathBuf::from(result);
+        if dest.as_os_str().is_empty() || dest.is_absolute() {
+            return Err(format!("script returned an invalid destination {:?}", dest));
+        }
+        Ok(dest)
+    }
+}
@@ -0,0 +1,219 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// SFTP executor for --output-dir=sftp://[user@]host[:port]/path: uploads each
+// file to the remote server and removes the local copy once it's there.
+//
+// Authentication goes through the ssh agent, falling back to the default
+// key files in ~/.ssh. The server's host key must be in ~/.ssh/known_hosts.
+
+use std::cmp;
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+
+use filetime::FileTime;
+use ssh2::{self, CheckResult, KnownHostFileKind, Session, Sftp};
+use zstd;
+
+use executor::{Executor, MoveOp};
+use unix_seconds;
+
+
+pub const SCHEME: &'static str = "sftp://";
+
+pub fn is_sftp_url(s: &str) -> bool {
+    s.starts_with(SCHEME)
+}
+
+struct Url {
+    user: String,
+    host: String,
+    port: u16,
+    path: String,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg.to_string())
+}
+
+fn parse_url(url: &str) -> io::Result<Url> {
+    let rest = try!(url.strip_prefix(SCHEME).ok_or_else(|| invalid("not an sftp:// url")));
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (user, hostport) = match authority.rfind('@') {
+        Some(i) => (authority[..i].to_string(), &authority[i + 1..]),
+        None => {
+            (try!(env::var("USER")
+                      .or_else(|_| env::var("USERNAME"))
+                      .map_err(|_| invalid("no user in url and $USER not set"))),
+             authority)
+        }
+    };
+    let (host, port) = match hostport.rfind(':') {
+        Some(i) => {
+            (&hostport[..i],
+             try!(hostport[i + 1..].parse().map_err(|_| invalid("invalid port in url"))))
+        }
+        None => (hostport, 22),
+    };
+    if host.is_empty() {
+        return Err(invalid("no host in url"));
+    }
+    Ok(Url {
+        user: user,
+        host: host.to_string(),
+        port: port,
+        path: path.trim_end_matches('/').to_string(),
+    })
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")).map(PathBuf::from)
+}
+
+
+pub struct SftpExecutor {
+    // the output directory url as given; destinations start with it
+    prefix: String,
+    root: String,
+    // keeps the connection alive for `sftp`
+    _session: Session,
+    sftp: Sftp,
+}
+
+impl SftpExecutor {
+    pub fn connect(url: &str) -> io::Result<SftpExecutor> {
+        let parsed = try!(parse_url(url));
+        let tcp = try!(TcpStream::connect((&parsed.host[..], parsed.port)));
+        let mut session = try!(Session::new());
+        session.set_tcp_stream(tcp);
+        try!(session.handshake());
+
+        try!(check_host_key(&session, &parsed.host, parsed.port));
+        try!(authenticate(&session, &parsed.user));
+
+        let sftp = try!(session.sftp());
+        Ok(SftpExecutor {
+            prefix: url.trim_end_matches('/').to_string(),
+            root: parsed.path,
+            _session: session,
+            sftp: sftp,
+        })
+    }
+
+    fn remote_path(&self, dst: &Path) -> io::Result<String> {
+        let dst = dst.to_string_lossy();
+        let rel = try!(dst.strip_prefix(&self.prefix[..])
+                          .ok_or_else(|| invalid("destination outside the sftp output directory")));
+        let rel = rel.replace('\\', "/");
+        Ok(format!("{}/{}", self.root, rel.trim_start_matches('/')))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.sftp.stat(Path::new(path)).is_ok()
+    }
+
+    // remote mkdir -p
+    fn create_dir_all(&self, dir: &str) -> io::Result<()> {
+        let mut cur = String::new();
+        for part in dir.split('/').filter(|p| !p.is_empty()) {
+            cur.push('/');
+            cur.push_str(part);
+            if !self.exists(&cur) {
+                try!(self.sftp.mkdir(Path::new(&cur), 0o755));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_host_key(session: &Session, host: &str, port: u16) -> io::Result<()> {
+    let mut known_hosts = try!(session.known_hosts());
+    if let Some(home) = home_dir() {
+        let file = home.join(".ssh").join("known_hosts");
+        if file.exists() {
+            try!(known_hosts.read_file(&file, KnownHostFileKind::OpenSSH));
+        }
+    }
+    let (key, _) = try!(session.host_key().ok_or_else(|| invalid("server sent no host key")));
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => Err(invalid("host key does not match known_hosts")),
+        _ => Err(invalid("host key not found in known_hosts")),
+    }
+}
+
+fn authenticate(session: &Session, user: &str) -> io::Result<()> {
+    if session.userauth_agent(user).is_ok() && session.authenticated() {
+        return Ok(());
+    }
+    if let Some(home) = home_dir() {
+        for name in &["id_ed25519", "id_ecdsa", "id_rsa"] {
+            let key = home.join(".ssh").join(name);
+            if key.exists() && session.userauth_pubkey_file(user, None, &key, None).is_ok() {
+                return Ok(());
+            }
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::PermissionDenied, "ssh authentication failed"))
+}
+
+impl Executor for SftpExecutor {
+    fn execute(&mut self, op: &MoveOp, force: bool) -> io::Result<()> {
+        let remote = try!(self.remote_path(&op.dst));
+        if let Some(i) = remote.rfind('/') {
+            try!(self.create_dir_all(&remote[..i]));
+        }
+        if !force && self.exists(&remote) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                      "destination file already exists"));
+        }
+        let md = try!(fs::metadata(&op.src));
+        {
+            let mut input = try!(File::open(&op.src));
+            let mut output = try!(self.sftp.create(Path::new(&remote)));
+            if op.compress {
+                try!(zstd::stream::copy_encode(input, output, 0));
+            } else {
+                try!(io::copy(&mut input, &mut output));
+            }
+        }
+        // SFTP can't store dates before 1970
+        let mtime = cmp::max(unix_seconds(&FileTime::from_last_modification_time(&md)), 0) as u64;
+        try!(self.sftp.setstat(Path::new(&remote),
+                               ssh2::FileStat {
+                                   size: None,
+                                   uid: None,
+                                   gid: None,
+                                   perm: None,
+                                   atime: Some(mtime),
+                                   mtime: Some(mtime),
+                               }));
+        fs::remove_file(&op.src)
+    }
+}
@@ -0,0 +1,148 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// --stats-only: what the source looks like date-wise, to help pick options
+// before organizing anything.
+
+use std::collections::BTreeMap;
+use std::fs;
+
+use chrono::*;
+
+use {file_mtime, Batch};
+
+
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&'static str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+// "512", "100K", "1.5G", ...: bytes, or KiB, MiB, GiB or TiB as with
+// human_size
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => (&s[..i], &s[i..]),
+        None => (s, ""),
+    };
+    let shift = match &unit.to_ascii_uppercase()[..] {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 10,
+        "M" | "MB" | "MIB" => 20,
+        "G" | "GB" | "GIB" => 30,
+        "T" | "TB" | "TIB" => 40,
+        _ => return None,
+    };
+    number.trim()
+          .parse::<f64>()
+          .ok()
+          .filter(|n| *n >= 0.0)
+          .map(|n| (n * (1u64 << shift) as f64) as u64)
+}
+
+// bytes per second, 0 for runs too short to tell
+pub fn rate(bytes: u64, seconds: f64) -> u64 {
+    if seconds < 0.001 {
+        0
+    } else {
+        (bytes as f64 / seconds) as u64
+    }
+}
+
+// the time left at the rate so far, as M:SS, once there is a rate to go by
+pub fn eta(done: u64, total: u64, seconds: f64) -> Option<String> {
+    let rate = rate(done, seconds);
+    if rate == 0 || done == 0 {
+        return None;
+    }
+    let left = total.saturating_sub(done) / rate;
+    Some(format!("{}:{:02}", left / 60, left % 60))
+}
+
+#[derive(Default)]
+struct Bucket {
+    files: usize,
+    bytes: u64,
+}
+
+pub fn report(batches: &[Batch]) {
+    // by each file's own mtime
+    let mut months: BTreeMap<(i32, u32), Bucket> = BTreeMap::new();
+    // by the date of the batch the file would be filed under
+    let mut years: BTreeMap<i32, (usize, Bucket)> = BTreeMap::new();
+    let mut total = Bucket::default();
+
+    for batch in batches {
+        let year = years.entry(batch.date.year()).or_insert((0, Bucket::default()));
+        year.0 += 1;
+        for &(ref src, _) in &batch.files {
+            let md = match fs::metadata(src) {
+                Ok(md) => md,
+                Err(_) => continue,
+            };
+            let mtime = file_mtime(&md);
+            let month = months.entry((mtime.year(), mtime.month())).or_insert(Bucket::default());
+            month.files += 1;
+            month.bytes += md.len();
+            year.1.files += 1;
+            year.1.bytes += md.len();
+            total.files += 1;
+            total.bytes += md.len();
+        }
+    }
+
+    println!("{} files, {} in {} batches", total.files, human_size(total.bytes), batches.len());
+    println!();
+    println!("files by modification month:");
+    let mut last_year = None;
+    for (&(year, month), bucket) in &months {
+        if last_year != Some(year) {
+            println!("  {}", year);
+            last_year = Some(year);
+        }
+        println!("    {:04}-{:02} {:>8} files {:>12}",
+                 year,
+                 month,
+                 bucket.files,
+                 human_size(bucket.bytes));
+    }
+    println!();
+    println!("batches by destination year:");
+    for (year, &(count, ref bucket)) in &years {
+        println!("  {} {:>6} batches {:>8} files {:>12}",
+                 year,
+                 count,
+                 bucket.files,
+                 human_size(bucket.bytes));
+    }
+}
@@ -0,0 +1,119 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// The run summary, reported as JSON to notification targets.
+
+use std::collections::BTreeMap;
+
+use rustc_serialize::json;
+
+use now;
+use stats;
+
+
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct Summary {
+    // the id recorded in the manifest and saved plan
+    pub run_id: String,
+    // "ok", or "failed" if there were any errors
+    pub status: String,
+    pub started: String,
+    pub finished: String,
+    pub directories: Vec<String>,
+    pub output_dir: String,
+    pub dry_run: bool,
+    // files to move or extract, also with --dry-run, and their size
+    pub files_planned: usize,
+    pub bytes_planned: u64,
+    pub files_organized: usize,
+    pub bytes_organized: u64,
+    // how long the run took, and the bytes moved per second in it
+    pub seconds: f64,
+    pub bytes_per_second: u64,
+    pub errors: isize,
+    // files left in place by --skip-duplicates
+    pub duplicates: Vec<String>,
+    // files skipped because another process had them open
+    pub in_use: Vec<String>,
+    // files whose destination was taken
+    pub conflicts: Vec<Conflict>,
+    // the number of files left in place for each reason, see Context::skipped
+    pub skipped: BTreeMap<String, usize>,
+}
+
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct Conflict {
+    pub src: String,
+    pub dst: String,
+    // the --on-conflict policy applied
+    pub action: String,
+    // where the file went instead, if it was moved
+    pub moved_to: Option<String>,
+}
+
+impl Summary {
+    pub fn new(run_id: &str, directories: &[String], output_dir: &str, dry_run: bool) -> Summary {
+        Summary {
+            run_id: run_id.to_string(),
+            status: "ok".to_string(),
+            started: now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            finished: String::new(),
+            directories: directories.to_vec(),
+            output_dir: output_dir.to_string(),
+            dry_run: dry_run,
+            files_planned: 0,
+            bytes_planned: 0,
+            files_organized: 0,
+            bytes_organized: 0,
+            seconds: 0.0,
+            bytes_per_second: 0,
+            errors: 0,
+            duplicates: vec![],
+            in_use: vec![],
+            conflicts: vec![],
+            skipped: BTreeMap::new(),
+        }
+    }
+
+    pub fn finish(&mut self,
+                  files_planned: usize,
+                  bytes_planned: u64,
+                  files_organized: usize,
+                  bytes_organized: u64,
+                  seconds: f64,
+                  errors: isize) {
+        self.finished = now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
+        self.files_planned = files_planned;
+        self.bytes_planned = bytes_planned;
+        self.files_organized = files_organized;
+        self.bytes_organized = bytes_organized;
+        self.seconds = seconds;
+        self.bytes_per_second = stats::rate(bytes_organized, seconds);
+        self.errors = errors;
+        self.status = if errors > 0 { "failed" } else { "ok" }.to_string();
+    }
+
+    pub fn to_json(&self) -> String {
+        json::encode(self).unwrap()
+    }
+}
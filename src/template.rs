@@ -0,0 +1,407 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// --layout: the folder a batch goes to below the output directory, from a
+// template like "{year}/{month}/{country}". Text outside of {tokens} may use
+// strftime escapes (%Y, %m, %H, ...) for the batch date. A template with
+// {name} in it gives the whole path of the file, e.g. "%Y/{parent}/{name}".
+// {hash} is the start of the file's checksum, {hash:I-J} hex digits I to J
+// of it; "cas" stands for a content-addressed layout built from those. {size}
+// is the size bucket of the file, see --size-buckets, and {owner} the name of
+// the user who owns it. {weekday} is the English name of the day of the
+// batch date, {weekend_or_weekday} "weekend" or "weekday". {week_of_month}
+// counts from 1 for days 1-7 of the month, {doy} is the day of the year.
+// --day-folder-style=flat-date names {day} by the whole date, 2023-07-15.
+// {stem} is the file name without the extension, and {counter} is empty, or
+// -2, -3 and so on where that is needed for a free destination.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use chrono::*;
+use chrono::format::{Item, StrftimeItems};
+
+use exif;
+use geo::Places;
+use hash;
+use stats;
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Weekday,
+    WeekendOrWeekday,
+    WeekOfMonth,
+    DayOfYear,
+    Country,
+    City,
+    Camera,
+    Name,
+    Stem,
+    Ext,
+    Parent,
+    Root,
+    // the hex digits of the checksum from the first to before the second
+    Hash(usize, usize),
+    Size,
+    Owner,
+    Counter,
+}
+
+#[derive(Debug)]
+enum Part {
+    Text(String),
+    Token(Token),
+}
+
+pub struct Template {
+    parts: Vec<Part>,
+    // loaded only if the template needs it
+    places: Option<Places>,
+}
+
+const UNKNOWN: &'static str = "Unknown";
+
+// --layout=cas: below the year, two levels of folders by checksum, and the
+// whole checksum before the name, so files of the same name but with other
+// content never collide
+const CAS: &'static str = "{year}/{hash:0-2}/{hash:2-4}/{checksum}_{name}";
+
+// the template a preset name such as "cas" stands for, or `text` itself
+pub fn preset(text: &str) -> &str {
+    if text == "cas" { CAS } else { text }
+}
+
+// the digits of {hash}; {checksum} has them all
+const HASH_DIGITS: usize = 8;
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Template, String> {
+        let text = preset(text);
+        let mut parts = vec![];
+        let mut rest = text;
+        while let Some(open) = rest.find('{') {
+            let close = try!(rest[open..]
+                                 .find('}')
+                                 .map(|i| open + i)
+                                 .ok_or(format!("unclosed {{ in {:?}", text)));
+            if open > 0 {
+                parts.push(Part::Text(rest[..open].to_string()));
+            }
+            let token = match &rest[open + 1..close] {
+                "year" => Token::Year,
+                "month" => Token::Month,
+                "day" => Token::Day,
+                "hour" => Token::Hour,
+                "weekday" => Token::Weekday,
+                "weekend_or_weekday" => Token::WeekendOrWeekday,
+                "week_of_month" => Token::WeekOfMonth,
+                "doy" => Token::DayOfYear,
+                "country" => Token::Country,
+                "city" => Token::City,
+                "camera" => Token::Camera,
+                "name" => Token::Name,
+                "stem" => Token::Stem,
+                "ext" => Token::Ext,
+                "parent" => Token::Parent,
+                "root" => Token::Root,
+                "hash" => Token::Hash(0, HASH_DIGITS),
+                "checksum" => Token::Hash(0, usize::MAX),
+                "size" => Token::Size,
+                "owner" => Token::Owner,
+                "counter" => Token::Counter,
+                other if other.starts_with("hash:") => try!(hash_range(&other[5..])),
+                other => return Err(format!("unknown token {{{}}}", other)),
+            };
+            parts.push(Part::Token(token));
+            rest = &rest[close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        for part in &parts {
+            if let Part::Text(ref t) = *part {
+                if StrftimeItems::new(t).any(|item| item == Item::Error) {
+                    return Err(format!("invalid % escape in {:?}", t));
+                }
+            }
+        }
+        // the text between the tokens must keep the path below the output
+        let outline: String = parts.iter()
+                                   .map(|p| match *p {
+                                       Part::Text(ref t) => &t[..],
+                                       Part::Token(_) => "x",
+                                   })
+                                   .collect();
+        if Path::new(&outline).components().any(|c| match c {
+            Component::Normal(_) | Component::CurDir => false,
+            _ => true,
+        }) {
+            return Err(format!("{:?} must stay below the output directory, without a root or ..",
+                               text));
+        }
+        let geo = parts.iter().any(|p| match *p {
+            Part::Token(Token::Country) | Part::Token(Token::City) => true,
+            _ => false,
+        });
+        Ok(Template {
+            parts: parts,
+            places: if geo { Some(Places::load()) } else { None },
+        })
+    }
+
+    // whether the template names the file itself, rather than its folder
+    pub fn names_file(&self) -> bool {
+        self.parts.iter().any(|p| match *p {
+            Part::Token(Token::Name) | Part::Token(Token::Stem) => true,
+            _ => false,
+        })
+    }
+
+    pub fn has_counter(&self) -> bool {
+        self.parts.iter().any(|p| match *p {
+            Part::Token(Token::Counter) => true,
+            _ => false,
+        })
+    }
+
+    // the folder (or path, see names_file) for `src`, found below the source
+    // directory `root`, in a batch dated `date`; `counter` is the number in
+    // {counter}, none for the first try
+    pub fn expand(&self,
+                  src: &Path,
+                  root: &Path,
+                  date: &NaiveDateTime,
+                  counter: Option<u32>,
+                  options: &Options)
+                  -> PathBuf {
+        let name_of = |path: Option<&Path>| {
+            path.and_then(|p| p.file_name())
+                .map_or(UNKNOWN.to_string(), |n| n.to_string_lossy().into_owned())
+        };
+        let place = match self.places {
+            Some(ref places) => exif::gps(src).and_then(|(lat, lon)| places.nearest(lat, lon)),
+            None => None,
+        };
+        let checksum = if self.parts.iter().any(|p| match *p {
+            Part::Token(Token::Hash(..)) => true,
+            _ => false,
+        }) {
+            hash::file(src, options.hash).map(|c| c.hex()).ok()
+        } else {
+            None
+        };
+        let mut out = String::new();
+        for part in &self.parts {
+            let value = match *part {
+                Part::Text(ref t) => date.format(t).to_string(),
+                Part::Token(Token::Year) => date.year().to_string(),
+                Part::Token(Token::Month) => format!("{:02}", date.month()),
+                Part::Token(Token::Day) if options.flat_date_days => {
+                    date.format("%Y-%m-%d").to_string()
+                }
+                Part::Token(Token::Day) => format!("{:02}", date.day()),
+                Part::Token(Token::Hour) => format!("{:02}", date.hour()),
+                Part::Token(Token::Weekday) => date.format("%A").to_string(),
+                Part::Token(Token::WeekendOrWeekday) => {
+                    match date.weekday() {
+                        Weekday::Sat | Weekday::Sun => "weekend".to_string(),
+                        _ => "weekday".to_string(),
+                    }
+                }
+                Part::Token(Token::WeekOfMonth) => ((date.day() - 1) / 7 + 1).to_string(),
+                Part::Token(Token::DayOfYear) => format!("{:03}", date.ordinal()),
+                Part::Token(Token::Country) => {
+                    place.map_or(UNKNOWN, |p| &p.country[..]).to_string()
+                }
+                Part::Token(Token::City) => place.map_or(UNKNOWN, |p| &p.city[..]).to_string(),
+                Part::Token(Token::Camera) => exif::camera(src).unwrap_or(UNKNOWN.to_string()),
+                Part::Token(Token::Name) => name_of(Some(src)),
+                Part::Token(Token::Stem) => {
+                    src.file_stem()
+                       .map_or(UNKNOWN.to_string(), |s| s.to_string_lossy().into_owned())
+                }
+                Part::Token(Token::Ext) => {
+                    src.extension().map_or(String::new(), |e| e.to_string_lossy().into_owned())
+                }
+                Part::Token(Token::Parent) => name_of(src.parent()),
+                // the name of `.` and the like is that of the folder it stands for
+                Part::Token(Token::Root) => {
+                    match root.file_name() {
+                        Some(_) => name_of(Some(root)),
+                        None => name_of(fs::canonicalize(root).ok().as_ref().map(|p| p.as_path())),
+                    }
+                }
+                Part::Token(Token::Size) => {
+                    fs::metadata(src)
+                        .map_or(UNKNOWN.to_string(), |md| options.size_bucket(md.len()))
+                }
+                Part::Token(Token::Owner) => owner(src).unwrap_or(UNKNOWN.to_string()),
+                Part::Token(Token::Counter) => counter.map_or(String::new(), |n| format!("-{}", n)),
+                Part::Token(Token::Hash(start, end)) => {
+                    match checksum {
+                        Some(ref hex) => hex[start.min(hex.len())..end.min(hex.len())].to_string(),
+                        None => UNKNOWN.to_string(),
+                    }
+                }
+            };
+            // a value can't add folders of its own
+            out.push_str(&match *part {
+                Part::Text(_) => value,
+                Part::Token(_) => value.replace('/', "-").replace('\\', "-"),
+            });
+        }
+        PathBuf::from(out)
+    }
+}
+
+// I-J in {hash:I-J}
+fn hash_range(range: &str) -> Result<Token, String> {
+    let mut bounds = range.splitn(2, '-').map(|n| n.parse::<usize>().ok());
+    match (bounds.next(), bounds.next()) {
+        (Some(Some(start)), Some(Some(end))) if start < end => Ok(Token::Hash(start, end)),
+        _ => Err(format!("invalid range {{hash:{}}}, expected e.g. {{hash:0-2}}", range)),
+    }
+}
+
+
+// the options of a run that tokens depend on
+pub struct Options {
+    // --day-folder-style: whether {day} is the bare day of the month (nested)
+    // or the full date (flat-date)
+    flat_date_days: bool,
+    // --size-buckets: names, each for the files smaller than its bound, in
+    // increasing order; the last one has no bound and takes the rest
+    size_buckets: Vec<(String, Option<u64>)>,
+    // --hash, for {hash}
+    hash: hash::Algorithm,
+}
+
+impl Options {
+    // `size_buckets` is a list like "tiny:100K,small:10M,large:1G,huge"
+    pub fn new(day_folder_style: &str,
+               size_buckets: &str,
+               hash: hash::Algorithm)
+               -> Result<Options, String> {
+        let flat = match day_folder_style {
+            "nested" => false,
+            "flat-date" => true,
+            style => {
+                return Err(format!("--day-folder-style: unknown style {:?}, not nested or \
+                                    flat-date",
+                                   style))
+            }
+        };
+        let buckets = try!(parse_size_buckets(size_buckets)
+                               .map_err(|e| format!("--size-buckets: {}", e)));
+        Ok(Options {
+            flat_date_days: flat,
+            size_buckets: buckets,
+            hash: hash,
+        })
+    }
+
+    fn size_bucket(&self, size: u64) -> String {
+        self.size_buckets
+            .iter()
+            .find(|&&(_, bound)| bound.map_or(true, |b| size < b))
+            .map_or(UNKNOWN.to_string(), |&(ref name, _)| name.clone())
+    }
+}
+
+fn parse_size_buckets(spec: &str) -> Result<Vec<(String, Option<u64>)>, String> {
+    let mut buckets: Vec<(String, Option<u64>)> = vec![];
+    let names: Vec<&str> = spec.split(',').map(|b| b.trim()).collect();
+    for (i, bucket) in names.iter().enumerate() {
+        let mut fields = bucket.splitn(2, ':');
+        let name = fields.next().unwrap_or("");
+        let bound = fields.next();
+        if !plain_name(name) {
+            return Err(format!("invalid bucket name {:?}", name));
+        }
+        let bound = match (bound, i + 1 == names.len()) {
+            (None, true) => None,
+            (None, false) => return Err(format!("bucket {:?} needs an upper bound", name)),
+            (Some(size), _) => {
+                match stats::parse_size(size) {
+                    Some(n) if buckets.last().map_or(true, |&(_, b)| b < Some(n)) => Some(n),
+                    _ => return Err(format!("invalid or decreasing bound {:?}", size)),
+                }
+            }
+        };
+        buckets.push((name.to_string(), bound));
+    }
+    Ok(buckets)
+}
+
+// whether `name` is a single folder name, not `.`, `..`, a drive or a root
+fn plain_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => !name.contains(|c| c == '/' || c == '\\'),
+        _ => false,
+    }
+}
+
+
+// user names by uid, so a tree of one user's files asks the system only once
+static OWNERS: OnceLock<Mutex<HashMap<u32, String>>> = OnceLock::new();
+
+// the name of the user owning `path`, or the uid if it has none
+#[cfg(unix)]
+fn owner(path: &Path) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    let uid = match fs::symlink_metadata(path) {
+        Ok(md) => md.uid(),
+        Err(_) => return None,
+    };
+    let mut owners = OWNERS.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap();
+    Some(owners.entry(uid).or_insert_with(|| user_name(uid).unwrap_or(uid.to_string())).clone())
+}
+
+#[cfg(not(unix))]
+fn owner(_: &Path) -> Option<String> {
+    None
+}
+
+#[cfg(unix)]
+fn user_name(uid: u32) -> Option<String> {
+    use std::ffi::CStr;
+    use std::{mem, ptr};
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut pwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut found = ptr::null_mut();
+    let rc = unsafe {
+        libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut found)
+    };
+    if rc != 0 || found.is_null() {
+        return None;
+    }
+    Some(unsafe { CStr::from_ptr(pwd.pw_name) }.to_string_lossy().into_owned())
+}
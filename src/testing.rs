@@ -0,0 +1,73 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Helpers for the tests: scratch directories and runs of the program through
+// the library API, where exiting returns an Error instead of ending the tests.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use docopt::Docopt;
+
+use api::{call, Cancel, Error};
+use {make_context, run, setup, AgePolicy, Args, Context, USAGE};
+
+
+// an empty directory of its own for the test `name`
+pub fn scratch(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("organize-by-mtime-test-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// a run of `organize-by-mtime ARGV...`; returns its error count
+pub fn organize(argv: &[&str]) -> Result<isize, Error> {
+    let mut args = try!(parse(argv));
+    call(&Cancel::new(), || {
+        let agepolicy = setup(&mut args);
+        run(&args, agepolicy, None).errors
+    })
+}
+
+// `f` given the arguments `ARGV...` and the context of a run with them, for
+// the subcommands
+pub fn with_context<T, F>(argv: &[&str], f: F) -> Result<T, Error>
+    where F: FnOnce(&Args, &mut Context, AgePolicy) -> T
+{
+    let mut args = try!(parse(argv));
+    call(&Cancel::new(), || {
+        let agepolicy = setup(&mut args);
+        let mut ctx = make_context(&args);
+        f(&args, &mut ctx, agepolicy)
+    })
+}
+
+fn parse(argv: &[&str]) -> Result<Args, Error> {
+    let argv = Some("organize-by-mtime").into_iter().chain(argv.iter().cloned());
+    Docopt::new(USAGE)
+        .and_then(|d| d.argv(argv).decode())
+        .map_err(|e| Error::Usage(e.to_string()))
+}
@@ -0,0 +1,114 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+
+// --timings: where the time of a run goes, by phase, so that a slow run over
+// a network share can be told apart from one held up by date extraction or
+// checksums.
+
+use std::time::{Duration, Instant};
+
+
+#[derive(Clone, Copy)]
+pub enum Phase {
+    // listing the source directories
+    Walk,
+    // reading metadata and the dates of files
+    Dates,
+    // checksums for the manifest and --skip-duplicates
+    Hash,
+    // moving, copying and compressing, with the hooks run for each file
+    Move,
+}
+
+const PHASES: [(Phase, &'static str); 4] = [(Phase::Walk, "walk"),
+                                            (Phase::Dates, "metadata and dates"),
+                                            (Phase::Hash, "hashing"),
+                                            (Phase::Move, "moves")];
+
+pub struct Timings {
+    spent: [Duration; 4],
+    started: Instant,
+}
+
+impl Timings {
+    pub fn new() -> Timings {
+        Timings {
+            spent: [Duration::new(0, 0); 4],
+            started: Instant::now(),
+        }
+    }
+
+    // adds the time since `since` to `phase`
+    pub fn add(&mut self, phase: Phase, since: Instant) {
+        self.spent[phase as usize] += since.elapsed();
+    }
+
+    // logs the time of each phase and its share of the run, and a hint for
+    // the phase that took longest
+    pub fn report(&self) {
+        let total = seconds(self.started.elapsed());
+        let share = |s: f64| if total > 0.0 { s * 100.0 / total } else { 0.0 };
+        let mut accounted = 0.0;
+        let mut slowest = (Phase::Walk, 0.0);
+        for &(phase, name) in PHASES.iter() {
+            let s = seconds(self.spent[phase as usize]);
+            log_info!("{:>20} {:8.2}s {:5.1}%", name, s, share(s));
+            accounted += s;
+            if s > slowest.1 {
+                slowest = (phase, s);
+            }
+        }
+        let other = (total - accounted).max(0.0);
+        log_info!("{:>20} {:8.2}s {:5.1}%", "other", other, share(other));
+        log_info!("{:>20} {:8.2}s", "total", total);
+        if slowest.1 > 0.0 && share(slowest.1) >= 50.0 {
+            log_info!("{}", hint(slowest.0));
+        }
+    }
+}
+
+fn hint(phase: Phase) -> &'static str {
+    match phase {
+        Phase::Walk => {
+            "most of the time goes into listing directories; on a network share, \
+             running on the machine that has the disks is much faster"
+        }
+        Phase::Dates => {
+            "most of the time goes into dates; --date-source reads file contents, \
+             and the mtime alone needs none"
+        }
+        Phase::Hash => {
+            "most of the time goes into checksums, which --manifest and \
+             --skip-duplicates read every file for; --hash=xxh3 is the fastest"
+        }
+        Phase::Move => {
+            "most of the time goes into moves; moves within one filesystem are \
+             renames, across filesystems and with --compress-older-than they copy"
+        }
+    }
+}
+
+fn seconds(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9
+}
@@ -0,0 +1,350 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// --tui: show the whole plan as a tree of batches before anything is moved.
+// Batches and files can be excluded, a batch can be filed under another
+// year, and applying shows live progress.
+//
+// Keys: up/down move, right/enter expand, left collapse, space exclude,
+// y retarget (type a year, enter), a apply, q quit without moving anything.
+
+use std::fs;
+use std::io;
+use std::time::Instant;
+
+use chrono::*;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Gauge, List, ListItem, ListState, Paragraph};
+use ratatui::{self, DefaultTerminal};
+
+use log;
+use stats::{self, human_size};
+use {move_batch, Args, Batch, Context};
+
+
+#[derive(Clone, Copy, PartialEq)]
+enum Row {
+    Batch(usize),
+    File(usize, usize),
+}
+
+struct Plan {
+    batches: Vec<Batch>,
+    expanded: Vec<bool>,
+    excluded: Vec<bool>,
+    excluded_files: Vec<Vec<bool>>,
+}
+
+impl Plan {
+    fn new(batches: Vec<Batch>) -> Plan {
+        let n = batches.len();
+        let files = batches.iter().map(|b| vec![false; b.files.len()]).collect();
+        Plan {
+            batches: batches,
+            expanded: vec![false; n],
+            excluded: vec![false; n],
+            excluded_files: files,
+        }
+    }
+
+    fn rows(&self) -> Vec<Row> {
+        let mut rows = vec![];
+        for (i, batch) in self.batches.iter().enumerate() {
+            rows.push(Row::Batch(i));
+            if self.expanded[i] {
+                for j in 0..batch.files.len() {
+                    rows.push(Row::File(i, j));
+                }
+            }
+        }
+        rows
+    }
+
+    fn label(&self, row: Row) -> String {
+        match row {
+            Row::Batch(i) => {
+                let batch = &self.batches[i];
+                format!("[{}] {} {}  {}  ({} files)",
+                        if self.excluded[i] { " " } else { "x" },
+                        if self.expanded[i] { "v" } else { ">" },
+                        batch.date.year(),
+                        batch.dir.display(),
+                        batch.files.len())
+            }
+            Row::File(i, j) => {
+                let (ref src, ref dst) = self.batches[i].files[j];
+                format!("      [{}] {} -> {}/{}",
+                        if self.excluded[i] || self.excluded_files[i][j] { " " } else { "x" },
+                        src.display(),
+                        self.batches[i].date.year(),
+                        dst.display())
+            }
+        }
+    }
+
+    fn toggle(&mut self, row: Row) {
+        match row {
+            Row::Batch(i) => self.excluded[i] = !self.excluded[i],
+            Row::File(i, j) => self.excluded_files[i][j] = !self.excluded_files[i][j],
+        }
+    }
+
+    // the batches to apply, without anything excluded
+    fn selected(self) -> Vec<Batch> {
+        let mut out = vec![];
+        for (i, mut batch) in self.batches.into_iter().enumerate() {
+            if self.excluded[i] {
+                continue;
+            }
+            let excluded = &self.excluded_files[i];
+            let mut j = 0;
+            batch.files.retain(|_| {
+                j += 1;
+                !excluded[j - 1]
+            });
+            if !batch.files.is_empty() {
+                out.push(batch);
+            }
+        }
+        out
+    }
+}
+
+
+fn draw_plan(terminal: &mut DefaultTerminal,
+             plan: &Plan,
+             state: &mut ListState,
+             input: &Option<String>)
+             -> io::Result<()> {
+    let rows = plan.rows();
+    try!(terminal.draw(|frame| {
+        let chunks = Layout::default()
+                         .direction(Direction::Vertical)
+                         .constraints([Constraint::Min(1), Constraint::Length(1)])
+                         .split(frame.area());
+        let items: Vec<ListItem> = rows.iter().map(|&r| ListItem::new(plan.label(r))).collect();
+        let list = List::new(items)
+                       .block(Block::default().borders(Borders::ALL).title(" plan "))
+                       .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, chunks[0], state);
+        let help = match *input {
+            Some(ref year) => format!("file batch under year: {}_", year),
+            None => "space exclude  enter expand  y retarget  a apply  q quit".to_string(),
+        };
+        frame.render_widget(Paragraph::new(help), chunks[1]);
+    }));
+    Ok(())
+}
+
+// Progress by bytes rather than files, so that a few large copies to
+// another filesystem don't leave the gauge stuck near the end
+struct Progress {
+    files: usize,
+    total_files: usize,
+    bytes: u64,
+    total_bytes: u64,
+    started: Instant,
+}
+
+impl Progress {
+    fn label(&self) -> String {
+        let elapsed = self.started.elapsed();
+        let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+        let mut label = format!("{}/{} files, {} of {}",
+                                self.files,
+                                self.total_files,
+                                human_size(self.bytes),
+                                human_size(self.total_bytes));
+        let rate = stats::rate(self.bytes, seconds);
+        if rate > 0 {
+            label.push_str(&format!(", {}/s", human_size(rate)));
+        }
+        if let Some(eta) = stats::eta(self.bytes, self.total_bytes, seconds) {
+            label.push_str(&format!(", {} left", eta));
+        }
+        label
+    }
+
+    fn ratio(&self) -> f64 {
+        if self.total_bytes > 0 {
+            (self.bytes as f64 / self.total_bytes as f64).min(1.0)
+        } else if self.total_files > 0 {
+            self.files as f64 / self.total_files as f64
+        } else {
+            1.0
+        }
+    }
+}
+
+fn draw_progress(terminal: &mut DefaultTerminal,
+                 progress: &Progress,
+                 messages: &[String],
+                 finished: bool)
+                 -> io::Result<()> {
+    try!(terminal.draw(|frame| {
+        let chunks = Layout::default()
+                         .direction(Direction::Vertical)
+                         .constraints([Constraint::Length(3), Constraint::Min(1)])
+                         .split(frame.area());
+        let title = if finished { " done, press any key " } else { " organizing " };
+        let gauge = Gauge::default()
+                        .block(Block::default().borders(Borders::ALL).title(title))
+                        .ratio(progress.ratio())
+                        .label(progress.label());
+        frame.render_widget(gauge, chunks[0]);
+        let height = chunks[1].height.saturating_sub(2) as usize;
+        let start = messages.len().saturating_sub(height);
+        let lines: Vec<Line> = messages[start..].iter().map(|m| Line::from(m.clone())).collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::default().borders(Borders::ALL)),
+                            chunks[1]);
+    }));
+    Ok(())
+}
+
+// lets the user edit the plan; returns None if they quit
+fn edit(terminal: &mut DefaultTerminal, mut plan: Plan) -> io::Result<Option<Vec<Batch>>> {
+    let mut state = ListState::default();
+    state.select(Some(0));
+    let mut input: Option<String> = None;
+    loop {
+        try!(draw_plan(terminal, &plan, &mut state, &input));
+        let key = match try!(event::read()) {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        let rows = plan.rows();
+        let cur = state.selected().unwrap_or(0).min(rows.len().saturating_sub(1));
+        let row = match rows.get(cur) {
+            Some(&r) => r,
+            None => return Ok(None),
+        };
+        let batch_index = match row {
+            Row::Batch(i) | Row::File(i, _) => i,
+        };
+
+        if let Some(mut year) = input.take() {
+            match key.code {
+                KeyCode::Char(c) if c.is_digit(10) && year.len() < 4 => {
+                    year.push(c);
+                    input = Some(year);
+                }
+                KeyCode::Backspace => {
+                    year.pop();
+                    input = Some(year);
+                }
+                KeyCode::Enter => {
+                    let batch = &mut plan.batches[batch_index];
+                    if let Some(date) = year.parse().ok().and_then(|y| batch.date.with_year(y)) {
+                        batch.date = date;
+                    }
+                }
+                KeyCode::Esc => {}
+                _ => input = Some(year),
+            }
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(None),
+            KeyCode::Char('a') => return Ok(Some(plan.selected())),
+            KeyCode::Char(' ') => plan.toggle(row),
+            KeyCode::Char('y') => input = Some(String::new()),
+            KeyCode::Up => state.select(Some(cur.saturating_sub(1))),
+            KeyCode::Down => state.select(Some((cur + 1).min(rows.len() - 1))),
+            KeyCode::Right | KeyCode::Enter => plan.expanded[batch_index] = true,
+            KeyCode::Left => {
+                plan.expanded[batch_index] = false;
+                let pos = plan.rows().iter().position(|&r| r == Row::Batch(batch_index));
+                state.select(pos);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn apply(terminal: &mut DefaultTerminal,
+         batches: Vec<Batch>,
+         args: &Args,
+         ctx: &mut Context)
+         -> io::Result<isize> {
+    let mut progress = Progress {
+        files: 0,
+        total_files: batches.iter().map(|b| b.files.len()).sum(),
+        bytes: 0,
+        total_bytes: batches.iter()
+                            .flat_map(|b| b.files.iter())
+                            .filter_map(|f| fs::metadata(&f.0).ok())
+                            .map(|md| md.len())
+                            .sum(),
+        started: Instant::now(),
+    };
+    // the run's count, which may not start at 0
+    let base = ctx.bytes;
+    let mut messages = vec![];
+    let mut errors = 0;
+    let mut draw_error = Ok(());
+    for batch in &batches {
+        errors += move_batch(batch, args, ctx, &mut |bytes| {
+            progress.bytes = bytes - base;
+            messages.extend(log::take_captured());
+            if draw_error.is_ok() {
+                draw_error = draw_progress(terminal, &progress, &messages, false);
+            }
+            progress.files += 1;
+        });
+    }
+    try!(draw_error);
+    messages.extend(log::take_captured());
+    progress.files = progress.total_files;
+    progress.bytes = ctx.bytes - base;
+    try!(draw_progress(terminal, &progress, &messages, true));
+    loop {
+        if let Event::Key(_) = try!(event::read()) {
+            break;
+        }
+    }
+    Ok(errors)
+}
+
+// runs the TUI over the planned batches; returns the error count
+pub fn run(batches: Vec<Batch>, args: &Args, ctx: &mut Context) -> io::Result<isize> {
+    if batches.is_empty() {
+        return Ok(0);
+    }
+    let mut terminal = ratatui::init();
+    let target = log::set_target(log::Target::Capture);
+    let result = match edit(&mut terminal, Plan::new(batches)) {
+        Ok(Some(selected)) => apply(&mut terminal, selected, args, ctx),
+        Ok(None) => Ok(0),
+        Err(e) => Err(e),
+    };
+    ratatui::restore();
+    log::set_target(target);
+    for message in log::take_captured() {
+        println!("{}", message);
+    }
+    result
+}
@@ -0,0 +1,166 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// `undo MANIFEST` moves the files of one run, as recorded in its manifest,
+// back where they came from: by default the last run, or the one given with
+// --run. Each file is checked against the size and checksum recorded when it
+// was moved, so a copy changed since then isn't silently moved back in its
+// place. Archive members extracted by --expand-archives are removed instead,
+// as long as their archive is still there. The moves back are recorded in
+// the manifest under "undo:RUN", which also keeps a run from being undone
+// twice.
+
+use std::fs;
+use std::path::Path;
+
+use chrono::*;
+
+use executor::{Executor, LocalExecutor, MoveOp};
+use hash;
+use log::{self, Paint};
+use manifest::{self, Entry, Manifest};
+
+const UNDO_PREFIX: &'static str = "undo:";
+
+
+// the move back for `e`, or why it can't be made
+fn check(e: &Entry, force: bool) -> Result<MoveOp, String> {
+    if e.compressed {
+        return Err("stored compressed".to_string());
+    }
+    let md = try!(e.dst.metadata().map_err(|_| format!("{:?} is gone", e.dst)));
+    // the only copy once its archive was removed
+    if e.extracted && !e.src.ancestors().skip(1).any(|dir| dir.is_file()) {
+        return Err("its archive is gone".to_string());
+    }
+    if !force {
+        if !e.extracted && e.src.exists() {
+            return Err(format!("{:?} exists", e.src));
+        }
+        let modified = match (e.size, &e.checksum) {
+            (Some(size), _) if size != md.len() => true,
+            (_, &Some(ref checksum)) => {
+                hash::file(&e.dst, checksum.algorithm()).ok().as_ref() != Some(checksum)
+            }
+            _ => false,
+        };
+        if modified {
+            return Err(format!("{:?} was modified since it was moved", e.dst));
+        }
+    }
+    let date = NaiveDateTime::parse_from_str(&e.date, "%Y-%m-%dT%H:%M:%S")
+                   .unwrap_or(NaiveDateTime::from_timestamp(0, 0));
+    Ok(MoveOp {
+        src: e.dst.clone(),
+        dst: e.src.clone(),
+        date: date,
+        compress: false,
+        conflict: None,
+    })
+}
+
+// returns error count, including files that couldn't be moved back
+pub fn run(path: &Path, run: Option<&str>, dry_run: bool, force: bool) -> isize {
+    let entries = match manifest::read(path) {
+        Ok(entries) => manifest::without_rollbacks(entries),
+        Err(e) => {
+            log_error!("Error: manifest {:?}: {}", path, e);
+            return 1;
+        }
+    };
+    let run = match run {
+        Some(run) => run.to_string(),
+        None => {
+            let last = entries.iter()
+                              .rev()
+                              .filter_map(|e| e.run.as_ref())
+                              .find(|run| !run.starts_with(UNDO_PREFIX));
+            match last {
+                Some(run) => run.clone(),
+                None => {
+                    log_error!("Error: manifest {:?} has no runs to undo", path);
+                    return 1;
+                }
+            }
+        }
+    };
+    let undo_run = format!("{}{}", UNDO_PREFIX, run);
+    if !force && entries.iter().any(|e| e.run.as_ref() == Some(&undo_run)) {
+        log_error!("Error: run {} was undone already; --force undoes it again", run);
+        return 1;
+    }
+    let mut manifest = if dry_run {
+        None
+    } else {
+        match Manifest::open(path, &undo_run) {
+            Ok(manifest) => Some(manifest),
+            Err(e) => {
+                log_error!("Error: manifest {:?}: {}", path, e);
+                return 1;
+            }
+        }
+    };
+    let mut executor = LocalExecutor;
+    let mut errors: isize = 0;
+
+    // last moved first, in case a file was moved more than once
+    for e in entries.iter().rev().filter(|e| e.run.as_ref() == Some(&run)) {
+        let op = match check(e, force) {
+            Ok(op) => op,
+            Err(reason) => {
+                log_warn!("can't undo {:?}: {}", e.src, reason);
+                errors += 1;
+                continue;
+            }
+        };
+        if e.extracted {
+            log_info!("remove {}, still in its archive",
+                      log::paint(&format!("{:?}", op.src), Paint::Source));
+        } else {
+            log_info!("move {} {}",
+                      log::paint(&format!("{:?}", op.src), Paint::Source),
+                      log::paint(&executor.describe(&op), Paint::Destination));
+        }
+        if dry_run {
+            continue;
+        }
+        let size = op.src.metadata().map(|md| md.len()).unwrap_or(0);
+        let result = if e.extracted {
+            fs::remove_file(&op.src)
+        } else {
+            executor.execute(&op, force)
+        };
+        if let Err(err) = result {
+            log_error!("Error: dest: {:?}: {}", op.dst, err);
+            errors += 1;
+            continue;
+        }
+        if let Some(ref mut manifest) = manifest {
+            if let Err(err) = manifest.record(&op, size, e.checksum.as_ref()) {
+                log_error!("Error: manifest: {}", err);
+                errors += 1;
+            }
+        }
+    }
+    errors
+}
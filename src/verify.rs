@@ -0,0 +1,190 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// `verify OUTPUT_DIR` checks an organized tree: files whose dates don't fit
+// the year folder they are in and, given the manifest, files it recorded that
+// are gone and files it doesn't know about.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::*;
+use walkdir::WalkDir;
+
+use executor;
+use lock;
+use manifest;
+use {file_mtime, source_date, AgePolicy, Context};
+
+
+pub struct Misfiled {
+    pub path: PathBuf,
+    pub date: NaiveDateTime,
+    pub folder: i32,
+    // the year folder it is in, YEAR or YEAR_N
+    pub top: PathBuf,
+}
+
+// the year of the top-level folder `path` is in, if it is a year folder:
+// YEAR, or YEAR_N as made by --merge=suffix
+fn year_folder(output: &Path, path: &Path) -> Option<i32> {
+    let rel = match path.strip_prefix(output) {
+        Ok(rel) => rel,
+        Err(_) => return None,
+    };
+    let mut components = rel.components();
+    let first = components.next();
+    // files right in the year folder's parent aren't ours
+    if components.next().is_none() {
+        return None;
+    }
+    let name = match first.and_then(|c| c.as_os_str().to_str()) {
+        Some(name) => name,
+        None => return None,
+    };
+    let (year, suffix) = match (name.get(..4), name.get(4..)) {
+        (Some(year), Some(suffix)) => (year, suffix),
+        _ => return None,
+    };
+    let suffixed = suffix.is_empty() ||
+                   suffix.len() >= 2 && suffix.starts_with('_') &&
+                   suffix[1..].chars().all(|c| c.is_digit(10));
+    if year.chars().all(|c| c.is_digit(10)) && suffixed {
+        year.parse().ok()
+    } else {
+        None
+    }
+}
+
+// all files in the year folders of `output`
+pub fn organized_files(output: &Path) -> Vec<(PathBuf, i32)> {
+    let walker = WalkDir::new(output).min_depth(2).sort_by(|a, b| a.file_name().cmp(b.file_name()));
+    walker.into_iter()
+          .filter_map(Result::ok)
+          .filter(|e| e.file_type().is_file())
+          .filter(|e| !executor::is_temp(e.path()) && !lock::is_lock(e.path()))
+          .filter_map(|e| year_folder(output, e.path()).map(|year| (e.path().to_path_buf(), year)))
+          .collect()
+}
+
+// A batch is filed under its oldest date (its newest with --newest), so the
+// other files in it may well be from later years; a file is only misfiled
+// when its date is on the wrong side of its folder. Files are dated as by the
+// run: by --date-override and the date sources, else by their mtime.
+pub fn misfiled(output: &Path, policy: AgePolicy, ctx: &Context) -> Vec<Misfiled> {
+    let mut found = vec![];
+    for (path, folder) in organized_files(output) {
+        let top = match path.strip_prefix(output).ok().and_then(|rel| rel.components().next()) {
+            Some(first) => output.join(first.as_os_str()),
+            None => continue,
+        };
+        let date = match fs::metadata(&path) {
+            Ok(md) => source_date(&path, output, &md, ctx).unwrap_or_else(|| file_mtime(&md)),
+            Err(_) => continue,
+        };
+        let wrong = match policy {
+            AgePolicy::Newest => date.year() > folder,
+            _ => date.year() < folder,
+        };
+        if wrong {
+            found.push(Misfiled {
+                path: path,
+                date: date,
+                folder: folder,
+                top: top,
+            });
+        }
+    }
+    found
+}
+
+// reports what is wrong with `output`; returns the number of problems found
+pub fn run(output: &Path,
+           policy: AgePolicy,
+           ctx: &Context,
+           manifest_path: Option<&Path>)
+           -> isize {
+    let mut problems: isize = 0;
+
+    for m in misfiled(output, policy, ctx) {
+        log_warn!("misfiled {:?}: dated {} but in {}",
+                  m.path,
+                  m.date.format("%Y-%m-%d"),
+                  m.folder);
+        problems += 1;
+    }
+
+    if let Some(path) = manifest_path {
+        let entries = match manifest::read(path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                log_error!("Error: manifest {:?}: {}", path, e);
+                return problems + 1;
+            }
+        };
+        // later entries may have moved a file again (see `reorganize`), in
+        // which case only its last destination is expected to exist
+        let mut expected = BTreeSet::new();
+        for entry in entries {
+            expected.remove(&entry.src);
+            if entry.dst.starts_with(output) {
+                expected.insert(entry.dst);
+            }
+        }
+        for dst in &expected {
+            if !dst.exists() {
+                log_warn!("missing {:?}", dst);
+                problems += 1;
+            }
+        }
+        for (file, _) in organized_files(output) {
+            if !expected.contains(&file) {
+                log_warn!("extra {:?}", file);
+                problems += 1;
+            }
+        }
+    }
+
+    log_info!("{} problems found in {:?}", problems, output);
+    problems
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::year_folder;
+
+    #[test]
+    fn year_folders() {
+        let output = Path::new("out");
+        assert_eq!(year_folder(output, Path::new("out/2019/a.jpg")), Some(2019));
+        assert_eq!(year_folder(output, Path::new("out/2019_2/trip/a.jpg")), Some(2019));
+        assert_eq!(year_folder(output, Path::new("out/2019_/a.jpg")), None);
+        assert_eq!(year_folder(output, Path::new("out/2019x/a.jpg")), None);
+        assert_eq!(year_folder(output, Path::new("out/a.jpg")), None);
+        assert_eq!(year_folder(output, Path::new("elsewhere/2019/a.jpg")), None);
+    }
+}
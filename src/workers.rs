@@ -0,0 +1,153 @@
+// The MIT License
+//
+// Copyright 2016 Marek Baczynski
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+
+// --io-workers: moves carried out by a pool of threads, each with its own
+// executor, fed through a bounded queue. Scanning goes on while slow writes
+// to the output are under way, and stops to wait only when the queue is full.
+// What follows a move (the manifest, hooks, counts) stays with the scanning
+// thread, which picks up the finished moves as it goes.
+
+use std::io;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use chrono::NaiveDateTime;
+
+use executor::{Executor, MoveOp};
+use hash::{self, Checksum};
+use {interrupted, spawn_for_run};
+
+
+// queued moves per worker, beyond those under way
+const QUEUE_PER_WORKER: usize = 4;
+
+pub struct Job {
+    pub op: MoveOp,
+    pub force: bool,
+    // checksum the source with this before moving it, for the manifest
+    pub checksum: Option<hash::Algorithm>,
+    pub size: u64,
+    // the file's own date, for --set-mtime
+    pub file_date: NaiveDateTime,
+}
+
+pub struct Done {
+    pub job: Job,
+    pub checksum: Option<Checksum>,
+    // None if the move was dropped because the run was interrupted
+    pub result: Option<io::Result<()>>,
+}
+
+pub struct Pool {
+    jobs: Option<SyncSender<Job>>,
+    done: Receiver<Done>,
+    threads: Vec<JoinHandle<Box<dyn Executor + Send>>>,
+}
+
+impl Pool {
+    pub fn new(executors: Vec<Box<dyn Executor + Send>>) -> Pool {
+        let (jobs, queue) = mpsc::sync_channel(executors.len() * QUEUE_PER_WORKER);
+        let queue = Arc::new(Mutex::new(queue));
+        let (done_tx, done) = mpsc::channel();
+        let threads = executors.into_iter()
+                               .map(|executor| {
+                                   let queue = queue.clone();
+                                   let done = done_tx.clone();
+                                   spawn_for_run(move || work(executor, queue, done))
+                               })
+                               .collect();
+        Pool {
+            jobs: Some(jobs),
+            done: done,
+            threads: threads,
+        }
+    }
+
+    // queues `job`, waiting for room if the queue is full
+    pub fn send(&mut self, job: Job) {
+        if let Some(ref jobs) = self.jobs {
+            // fails only if every worker is gone, which they aren't before finish
+            let _ = jobs.send(job);
+        }
+    }
+
+    // the moves finished since the last call, without waiting for more
+    pub fn finished(&mut self) -> Vec<Done> {
+        self.done.try_iter().collect()
+    }
+
+    // waits for the queued moves and the workers, and finishes their
+    // executors; returns the moves not picked up yet, and the first error
+    // finishing an executor
+    pub fn finish(&mut self) -> (Vec<Done>, io::Result<()>) {
+        self.jobs = None;
+        let mut result = Ok(());
+        for thread in self.threads.drain(..) {
+            match thread.join() {
+                Ok(mut executor) => {
+                    if let Err(e) = executor.finish() {
+                        if result.is_ok() {
+                            result = Err(e);
+                        }
+                    }
+                }
+                Err(_) => {
+                    if result.is_ok() {
+                        result = Err(io::Error::new(io::ErrorKind::Other, "io worker panicked"));
+                    }
+                }
+            }
+        }
+        (self.done.try_iter().collect(), result)
+    }
+}
+
+fn work(mut executor: Box<dyn Executor + Send>,
+        queue: Arc<Mutex<Receiver<Job>>>,
+        done: mpsc::Sender<Done>)
+        -> Box<dyn Executor + Send> {
+    loop {
+        let job = match queue.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return executor,
+        };
+        // queued moves are dropped, the ones under way complete
+        if interrupted() {
+            let _ = done.send(Done {
+                job: job,
+                checksum: None,
+                result: None,
+            });
+            continue;
+        }
+        let checksum = job.checksum.and_then(|algorithm| hash::file(&job.op.src, algorithm).ok());
+        let result = executor.execute(&job.op, job.force);
+        let _ = done.send(Done {
+            job: job,
+            checksum: checksum,
+            result: Some(result),
+        });
+    }
+}